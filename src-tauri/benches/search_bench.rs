@@ -26,10 +26,12 @@ use app_lib::search::{
     index_store::{IndexSnapshot, IndexState, NodeTables},
     kifu_reader::read_to_jkf,
     position_key::{key_from_partial_position, PositionKey},
+    rules::{check_move, IllegalMove},
     segment::{Segment, SegmentArc},
     sfen_position::partial_position_from_sfen,
     types::{FileEntry, Occurrence},
 };
+use shogi_core::{Color, Move, PartialPosition, Piece, PieceKind, Square};
 
 const ROOT: &str = env!("HOME");
 
@@ -102,6 +104,7 @@ fn do_full_build(records: &[app_lib::search::fs_scan::FileRecord]) -> BuildResul
             path: rec.path.to_string_lossy().to_string(),
             deleted: false,
             gen,
+            error: None,
         });
         nts.upsert(file_id, built.node_table);
 
@@ -316,6 +319,7 @@ fn bench_04_search() {
         file_table: Arc::new(result.file_table),
         node_tables: Arc::new(result.node_tables),
         buckets: result.buckets,
+        ..IndexSnapshot::empty()
     };
 
     // 検索対象: 平手初期局面
@@ -388,8 +392,8 @@ fn bench_05_memory_estimate() {
     // Segment entries: (PositionKey=16B, Occurrence=12B) = 28 bytes/entry
     let segment_bytes = result.total_entries * 28;
 
-    // NodeTable: NodeCursor=10B, ForkPtr=8B (rough)
-    let node_bytes_est = result.total_nodes * 10; // rough
+    // NodeTable: NodeCursor=12B, ForkPtr=8B (rough)
+    let node_bytes_est = result.total_nodes * 12; // rough
 
     // FileTable: ~100B per entry (path string + entry)
     let ft_bytes_est = result.file_count * 100;
@@ -458,9 +462,10 @@ fn bench_06_compaction() {
         file_table: Arc::new(result.file_table),
         node_tables: Arc::new(result.node_tables),
         buckets: result.buckets,
+        ..IndexSnapshot::empty()
     };
 
-    // compaction (same logic as index_cache::compact_all_buckets, inlined here)
+    // compaction (same logic as index_cache::compact_bucket, inlined here)
     let t = Instant::now();
     let mut compacted_entries = 0usize;
     let mut compacted_buckets: [Vec<SegmentArc>; 256] = std::array::from_fn(|_| Vec::new());
@@ -497,6 +502,7 @@ fn bench_06_compaction() {
         file_table: snap.file_table.clone(),
         node_tables: snap.node_tables.clone(),
         buckets: compacted_buckets,
+        ..IndexSnapshot::empty()
     };
 
     let startpos_sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
@@ -530,7 +536,8 @@ fn bench_07_diff_snapshot() {
 
     // 同一スナップショット (変化なし)
     let t = Instant::now();
-    let diff = diff_snapshot(&snap, &snap);
+    let mut same = snap.clone();
+    let diff = diff_snapshot(&snap, &mut same);
     let d = t.elapsed();
     println!(
         "same snapshot diff: added={} modified={} removed={} elapsed={:.3} ms",
@@ -547,7 +554,7 @@ fn bench_07_diff_snapshot() {
         reduced.by_path.remove(k);
     }
     let t2 = Instant::now();
-    let diff2 = diff_snapshot(&snap, &reduced);
+    let diff2 = diff_snapshot(&snap, &mut reduced);
     let d2 = t2.elapsed();
     println!(
         "10 files removed:  added={} modified={} removed={} elapsed={:.3} ms",
@@ -557,7 +564,7 @@ fn bench_07_diff_snapshot() {
         d2.as_secs_f64() * 1000.0,
     );
 
-    // mtime 変更（10ファイル modify）
+    // mtime 変更（10ファイル modify）: 前回 hash が無いので内容を読んで modified になる
     let mut modified_snap = snap.clone();
     let mod_keys: Vec<String> = modified_snap.by_path.keys().take(10).cloned().collect();
     for k in &mod_keys {
@@ -566,7 +573,7 @@ fn bench_07_diff_snapshot() {
         }
     }
     let t3 = Instant::now();
-    let diff3 = diff_snapshot(&snap, &modified_snap);
+    let diff3 = diff_snapshot(&snap, &mut modified_snap);
     let d3 = t3.elapsed();
     println!(
         "10 files modified: added={} modified={} removed={} elapsed={:.3} ms",
@@ -752,6 +759,7 @@ fn bench_10_summary() {
         file_table: Arc::new(result.file_table),
         node_tables: Arc::new(result.node_tables),
         buckets: result.buckets,
+        ..IndexSnapshot::empty()
     };
 
     let startpos_sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
@@ -841,3 +849,694 @@ fn bench_10_summary() {
         d_search.as_secs_f64() * 1_000_000.0 / iterations as f64
     );
 }
+
+// ============================================================
+// Rules (利き・王手)
+// ============================================================
+
+fn sq(file: u8, rank: u8) -> Square {
+    Square::new(file, rank).unwrap()
+}
+
+fn lone_piece(kind: PieceKind, color: Color, at: Square) -> PartialPosition {
+    let mut pos = PartialPosition::empty();
+    pos.piece_set(at, Some(Piece::new(kind, color)));
+    pos
+}
+
+/// 先手の駒を (筋, 段) に置いたとき 5e に利くか。後手は段を反転した位置で同じ結果になる
+const ATTACKS_ON_5E: &[(PieceKind, u8, u8, bool)] = &[
+    (PieceKind::Pawn, 5, 6, true),
+    (PieceKind::Pawn, 5, 4, false),
+    (PieceKind::Lance, 5, 8, true),
+    (PieceKind::Lance, 5, 2, false),
+    (PieceKind::Knight, 4, 7, true),
+    (PieceKind::Knight, 4, 3, false),
+    (PieceKind::Silver, 4, 6, true),
+    (PieceKind::Silver, 4, 4, true),
+    (PieceKind::Silver, 5, 4, false),
+    (PieceKind::Silver, 4, 5, false),
+    (PieceKind::Gold, 4, 6, true),
+    (PieceKind::Gold, 5, 4, true),
+    (PieceKind::Gold, 4, 4, false),
+    (PieceKind::ProPawn, 6, 6, true),
+    (PieceKind::ProPawn, 6, 4, false),
+    (PieceKind::Bishop, 1, 1, true),
+    (PieceKind::Bishop, 5, 1, false),
+    (PieceKind::Rook, 5, 1, true),
+    (PieceKind::Rook, 4, 4, false),
+    (PieceKind::ProBishop, 5, 4, true),
+    (PieceKind::ProBishop, 5, 3, false),
+    (PieceKind::ProRook, 4, 4, true),
+    (PieceKind::ProRook, 3, 3, false),
+    (PieceKind::King, 4, 4, true),
+    (PieceKind::King, 5, 3, false),
+];
+
+#[test]
+fn rules_is_attacked_black_pieces() {
+    use app_lib::search::rules::is_attacked;
+
+    for &(kind, file, rank, expected) in ATTACKS_ON_5E {
+        let pos = lone_piece(kind, Color::Black, sq(file, rank));
+        assert_eq!(
+            is_attacked(&pos, sq(5, 5), Color::Black),
+            expected,
+            "black {kind:?} at ({file}, {rank})"
+        );
+    }
+}
+
+#[test]
+fn rules_is_attacked_white_pieces() {
+    use app_lib::search::rules::is_attacked;
+
+    for &(kind, file, rank, expected) in ATTACKS_ON_5E {
+        let pos = lone_piece(kind, Color::White, sq(file, 10 - rank));
+        assert_eq!(
+            is_attacked(&pos, sq(5, 5), Color::White),
+            expected,
+            "white {kind:?} at ({file}, {})",
+            10 - rank
+        );
+    }
+}
+
+#[test]
+fn rules_is_attacked_slide_is_blocked() {
+    use app_lib::search::rules::is_attacked;
+
+    let mut pos = lone_piece(PieceKind::Lance, Color::Black, sq(5, 8));
+    pos.piece_set(sq(5, 7), Some(Piece::new(PieceKind::Pawn, Color::White)));
+    assert!(!is_attacked(&pos, sq(5, 5), Color::Black));
+
+    let mut pos = lone_piece(PieceKind::Rook, Color::White, sq(5, 1));
+    pos.piece_set(sq(5, 3), Some(Piece::new(PieceKind::Pawn, Color::Black)));
+    assert!(!is_attacked(&pos, sq(5, 5), Color::White));
+}
+
+#[test]
+fn rules_is_in_check_by_white() {
+    use app_lib::search::rules::is_in_check;
+
+    // 後手の歩 5h が先手玉 5i に王手
+    let pos = partial_position_from_sfen("4k4/9/9/9/9/9/9/4p4/4K4 b - 1").unwrap();
+    assert!(is_in_check(&pos, Color::Black));
+
+    // 後手の金 4h (後手から見て斜め前が 5i)
+    let pos = partial_position_from_sfen("4k4/9/9/9/9/9/9/5g3/4K4 b - 1").unwrap();
+    assert!(is_in_check(&pos, Color::Black));
+
+    // 玉より下にいる後手の香は利かない
+    let pos = partial_position_from_sfen("4k4/9/9/9/4K4/9/9/4l4/9 b - 1").unwrap();
+    assert!(!is_in_check(&pos, Color::Black));
+}
+
+#[test]
+fn rules_is_in_check_by_black() {
+    use app_lib::search::rules::is_in_check;
+
+    let pos = partial_position_from_sfen("4k4/4P4/9/9/9/9/9/9/4K4 w - 1").unwrap();
+    assert!(is_in_check(&pos, Color::White));
+
+    let pos = partial_position_from_sfen("4k4/5G3/9/9/9/9/9/9/4K4 w - 1").unwrap();
+    assert!(is_in_check(&pos, Color::White));
+
+    let pos = partial_position_from_sfen("9/4L4/9/9/4k4/9/9/9/4K4 w - 1").unwrap();
+    assert!(!is_in_check(&pos, Color::White));
+}
+
+// ============================================================
+// Rules (反則手の判定)
+// ============================================================
+
+fn drop_move(kind: PieceKind, color: Color, to: Square) -> Move {
+    Move::Drop {
+        piece: Piece::new(kind, color),
+        to,
+    }
+}
+
+fn normal_move(from: Square, to: Square) -> Move {
+    Move::Normal {
+        from,
+        to,
+        promote: false,
+    }
+}
+
+fn check_sfen(sfen: &str, mv: Move) -> Result<(), IllegalMove> {
+    check_move(&partial_position_from_sfen(sfen).unwrap(), mv)
+}
+
+#[test]
+fn rules_double_pawn() {
+    assert_eq!(
+        check_sfen(
+            "4k4/9/9/9/9/9/4P4/9/4K4 b P 1",
+            drop_move(PieceKind::Pawn, Color::Black, sq(5, 5))
+        ),
+        Err(IllegalMove::DoublePawn)
+    );
+    assert_eq!(
+        check_sfen(
+            "4k4/9/9/9/9/9/4P4/9/4K4 b P 1",
+            drop_move(PieceKind::Pawn, Color::Black, sq(4, 5))
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check_sfen(
+            "4k4/9/4p4/9/9/9/9/9/4K4 w p 1",
+            drop_move(PieceKind::Pawn, Color::White, sq(5, 5))
+        ),
+        Err(IllegalMove::DoublePawn)
+    );
+}
+
+#[test]
+fn rules_pawn_drop_mate() {
+    // 1b への歩打ちは金が支えていて、玉の逃げ道 (2a は自駒、2b は金の利き) がない
+    assert_eq!(
+        check_sfen(
+            "7lk/9/8G/9/9/9/9/9/K8 b P 1",
+            drop_move(PieceKind::Pawn, Color::Black, sq(1, 2))
+        ),
+        Err(IllegalMove::PawnDropMate)
+    );
+    // 支えがなければ玉で取れるので打ち歩詰めではない
+    assert_eq!(
+        check_sfen(
+            "7lk/9/9/9/9/9/9/9/K8 b P 1",
+            drop_move(PieceKind::Pawn, Color::Black, sq(1, 2))
+        ),
+        Ok(())
+    );
+    // 後手の打ち歩詰め (上の局面を 180 度回したもの)
+    assert_eq!(
+        check_sfen(
+            "8k/9/9/9/9/9/g8/9/KL7 w p 1",
+            drop_move(PieceKind::Pawn, Color::White, sq(9, 8))
+        ),
+        Err(IllegalMove::PawnDropMate)
+    );
+}
+
+#[test]
+fn rules_dead_piece() {
+    let sfen = "4k4/9/9/9/9/9/9/9/4K4 b PN 1";
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Pawn, Color::Black, sq(3, 1))),
+        Err(IllegalMove::DeadPiece)
+    );
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Knight, Color::Black, sq(3, 2))),
+        Err(IllegalMove::DeadPiece)
+    );
+    assert_eq!(
+        check_sfen(
+            "4k4/6P2/9/9/9/9/9/9/4K4 b - 1",
+            normal_move(sq(3, 2), sq(3, 1))
+        ),
+        Err(IllegalMove::DeadPiece)
+    );
+
+    let sfen = "4k4/9/9/9/9/9/9/9/4K4 w pn 1";
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Pawn, Color::White, sq(3, 9))),
+        Err(IllegalMove::DeadPiece)
+    );
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Knight, Color::White, sq(3, 8))),
+        Err(IllegalMove::DeadPiece)
+    );
+    assert_eq!(
+        check_sfen(
+            "4k4/9/9/9/9/9/9/6p2/4K4 w - 1",
+            normal_move(sq(3, 8), sq(3, 9))
+        ),
+        Err(IllegalMove::DeadPiece)
+    );
+}
+
+#[test]
+fn rules_self_check() {
+    // 飛車に釘付けの金を動かす
+    assert_eq!(
+        check_sfen(
+            "4r4/9/9/9/9/9/9/4G4/4K4 b - 1",
+            normal_move(sq(5, 8), sq(4, 8))
+        ),
+        Err(IllegalMove::SelfCheck)
+    );
+    assert_eq!(
+        check_sfen(
+            "4k4/4g4/9/9/9/9/9/9/4R4 w - 1",
+            normal_move(sq(5, 2), sq(6, 2))
+        ),
+        Err(IllegalMove::SelfCheck)
+    );
+
+    // 歩の利きに玉を入れる / 歩の後ろには入れる
+    assert_eq!(
+        check_sfen(
+            "9/9/9/9/9/4p4/9/5K3/9 b - 1",
+            normal_move(sq(4, 8), sq(5, 7))
+        ),
+        Err(IllegalMove::SelfCheck)
+    );
+    assert_eq!(
+        check_sfen(
+            "9/9/9/5K3/9/4p4/9/9/9 b - 1",
+            normal_move(sq(4, 4), sq(5, 5))
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check_sfen(
+            "9/5k3/9/4P4/9/9/9/9/9 w - 1",
+            normal_move(sq(4, 2), sq(5, 3))
+        ),
+        Err(IllegalMove::SelfCheck)
+    );
+    assert_eq!(
+        check_sfen(
+            "9/9/9/4P4/9/5k3/9/9/9 w - 1",
+            normal_move(sq(4, 6), sq(5, 5))
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn rules_piece_movement() {
+    let sfen = "k8/9/9/9/4p4/9/4P4/4G4/K8 b - 1";
+    // 空きマス / 相手の駒を動かす
+    assert_eq!(
+        check_sfen(sfen, normal_move(sq(3, 7), sq(3, 6))),
+        Err(IllegalMove::NoPiece)
+    );
+    assert_eq!(
+        check_sfen(sfen, normal_move(sq(5, 5), sq(5, 6))),
+        Err(IllegalMove::NoPiece)
+    );
+    // 歩は 2 マス進めない / 飛び駒は間の駒を越えられない
+    assert_eq!(
+        check_sfen(sfen, normal_move(sq(5, 7), sq(5, 5))),
+        Err(IllegalMove::Unreachable)
+    );
+    assert_eq!(
+        check_sfen(
+            "k8/9/9/9/9/9/9/9/R3K4 b - 1",
+            normal_move(sq(9, 9), sq(1, 9))
+        ),
+        Err(IllegalMove::Unreachable)
+    );
+    // 自分の駒は取れない
+    assert_eq!(
+        check_sfen(sfen, normal_move(sq(5, 8), sq(5, 7))),
+        Err(IllegalMove::CaptureOwnPiece)
+    );
+    assert_eq!(check_sfen(sfen, normal_move(sq(5, 7), sq(5, 6))), Ok(()));
+}
+
+#[test]
+fn rules_drops() {
+    let sfen = "k8/9/9/9/4p4/9/9/9/K8 b P 1";
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Pawn, Color::Black, sq(5, 5))),
+        Err(IllegalMove::DropOnOccupied)
+    );
+    // 持っていない駒 / 相手の駒は打てない
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Gold, Color::Black, sq(4, 5))),
+        Err(IllegalMove::NotInHand)
+    );
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Pawn, Color::White, sq(4, 5))),
+        Err(IllegalMove::NotInHand)
+    );
+    assert_eq!(
+        check_sfen(sfen, drop_move(PieceKind::Pawn, Color::Black, sq(4, 5))),
+        Ok(())
+    );
+}
+
+#[test]
+fn rules_promotion() {
+    let promote = |from, to| Move::Normal {
+        from,
+        to,
+        promote: true,
+    };
+    let sfen = "k8/9/4G4/5S3/9/9/4S4/9/K8 b - 1";
+    // 敵陣に入らない・出ない成り
+    assert_eq!(
+        check_sfen(sfen, promote(sq(5, 7), sq(5, 6))),
+        Err(IllegalMove::InvalidPromotion)
+    );
+    // 金は成れない
+    assert_eq!(
+        check_sfen(sfen, promote(sq(5, 3), sq(5, 2))),
+        Err(IllegalMove::InvalidPromotion)
+    );
+    // 敵陣に入る成り / 敵陣から出る成り
+    assert_eq!(check_sfen(sfen, promote(sq(4, 4), sq(4, 3))), Ok(()));
+    assert_eq!(
+        check_sfen("k8/9/5S3/9/9/9/9/9/K8 b - 1", promote(sq(4, 3), sq(5, 4))),
+        Ok(())
+    );
+}
+
+/// 1 手目 (先手の金) で自玉を飛車の利きに晒し、その後も手が続く棋譜
+const SELF_CHECK_CSA: &str = "V2.2
+P1 *  *  *  * -OU *  *  *  * 
+P2 *  *  *  *  *  *  *  *  * 
+P3 *  *  *  * -HI *  *  *  * 
+P4 *  *  *  *  *  *  *  *  * 
+P5 *  *  *  *  *  *  *  *  * 
+P6 *  *  *  *  *  *  *  *  * 
+P7 *  *  *  *  *  *  *  *  * 
+P8 *  *  *  * +KI *  *  *  * 
+P9 *  *  *  * +OU *  *  *  * 
++
++5848KI
+-5141OU
++5958OU
+-4131OU
+%TORYO
+";
+
+#[test]
+fn legal_policy_cuts_line_at_illegal_move() {
+    use app_lib::search::{
+        fs_scan::KifuKind, kifu_reader::read_path_to_jkf, types::BuildWarnKind,
+        types::ValidateKifuInput, validate::validate_kifu,
+    };
+
+    let dir = std::env::temp_dir().join(format!("obs_legal_policy_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("self_check.csa");
+    std::fs::write(&path, SELF_CHECK_CSA).unwrap();
+
+    let jkf = read_path_to_jkf(&path, KifuKind::Csa).unwrap();
+    let loose = build_index_for_jkf(1, 1, &jkf, BuildPolicy::Loose).unwrap();
+    let legal = build_index_for_jkf(1, 1, &jkf, BuildPolicy::Legal).unwrap();
+
+    // Loose は最後まで進み、Legal は 1 手目の反則手の手前で止まる
+    assert!(loose
+        .warns
+        .iter()
+        .all(|w| w.kind != BuildWarnKind::IllegalMove));
+    let illegal: Vec<_> = legal
+        .warns
+        .iter()
+        .filter(|w| w.kind == BuildWarnKind::IllegalMove)
+        .collect();
+    assert_eq!(illegal.len(), 1);
+    assert_eq!(illegal[0].cursor.tesuu, 1);
+    assert!(legal.node_table.nodes.len() < loose.node_table.nodes.len());
+    assert!(legal.entries.len() < loose.entries.len());
+
+    let out = validate_kifu(
+        &ValidateKifuInput {
+            path: path.to_string_lossy().to_string(),
+        },
+        &ScanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(out.checked, 1);
+    assert_eq!(out.issues.len(), 1);
+    let issue = &out.issues[0];
+    assert_eq!(issue.kind, Some(BuildWarnKind::IllegalMove));
+    assert_eq!(issue.cursor.as_ref().map(|c| c.tesuu), Some(1));
+    assert_eq!(issue.message, IllegalMove::SelfCheck.to_string());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ============================================================
+// Game meta (終局 special と勝敗)
+// ============================================================
+
+#[test]
+fn game_result_by_special() {
+    use app_lib::search::{game_meta::special_result, types::GameResult};
+    use shogi_kifu_converter_obsshogi::jkf::Color as JkfColor;
+
+    // (special, 先手が最後に指した時, 後手が最後に指した時)
+    let cases = [
+        ("TORYO", GameResult::BlackWin, GameResult::WhiteWin),
+        ("TSUMI", GameResult::BlackWin, GameResult::WhiteWin),
+        ("TIME_UP", GameResult::BlackWin, GameResult::WhiteWin),
+        ("ILLEGAL_MOVE", GameResult::BlackWin, GameResult::WhiteWin),
+        ("KACHI", GameResult::WhiteWin, GameResult::BlackWin),
+        (
+            "+ILLEGAL_ACTION",
+            GameResult::WhiteWin,
+            GameResult::WhiteWin,
+        ),
+        (
+            "-ILLEGAL_ACTION",
+            GameResult::BlackWin,
+            GameResult::BlackWin,
+        ),
+        ("SENNICHITE", GameResult::Draw, GameResult::Draw),
+        ("JISHOGI", GameResult::Draw, GameResult::Draw),
+        ("HIKIWAKE", GameResult::Draw, GameResult::Draw),
+        ("CHUDAN", GameResult::Unknown, GameResult::Unknown),
+        ("MATTA", GameResult::Unknown, GameResult::Unknown),
+    ];
+    for (name, after_black, after_white) in cases {
+        assert_eq!(
+            special_result(name, Some(JkfColor::Black)),
+            after_black,
+            "{name} after black"
+        );
+        assert_eq!(
+            special_result(name, Some(JkfColor::White)),
+            after_white,
+            "{name} after white"
+        );
+    }
+
+    // 1 手も指していなければ手番で決まるものは Unknown、符号付きの反則は決まる
+    assert_eq!(special_result("TORYO", None), GameResult::Unknown);
+    assert_eq!(special_result("KACHI", None), GameResult::Unknown);
+    assert_eq!(
+        special_result("+ILLEGAL_ACTION", None),
+        GameResult::WhiteWin
+    );
+    assert_eq!(special_result("SENNICHITE", None), GameResult::Draw);
+}
+
+// ============================================================
+// Engine (解析結果の書き戻し)
+// ============================================================
+
+fn eval_cp(value: i32) -> app_lib::engine::types::Evaluation {
+    app_lib::engine::types::Evaluation {
+        value,
+        kind: app_lib::engine::types::EvaluationKind::Centipawn,
+    }
+}
+
+fn ply_analysis(
+    ply: u32,
+    black_to_move: bool,
+    last_move: Option<&str>,
+    best_move: &str,
+    evaluation: app_lib::engine::types::Evaluation,
+) -> app_lib::engine::types::PlyAnalysis {
+    app_lib::engine::types::PlyAnalysis {
+        ply,
+        black_to_move,
+        last_move: last_move.map(str::to_string),
+        best_move: Some(best_move.to_string()),
+        pv: vec![best_move.to_string()],
+        evaluation: Some(evaluation),
+        score_black: None,
+        depth: Some(10),
+        nodes: None,
+    }
+}
+
+const HIRATE_THREE_MOVES_CSA: &str = "V2.2
+PI
++
++7776FU
+-3334FU
++2726FU
+%TORYO
+";
+
+#[test]
+fn annotate_merge_is_idempotent() {
+    use app_lib::engine::annotate::{merge_analysis_into_jkf, ANALYSIS_COMMENT_PREFIX};
+    use app_lib::search::{fs_scan::KifuKind, kifu_reader::read_path_to_jkf};
+
+    let dir = std::env::temp_dir().join(format!("obs_annotate_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("three_moves.csa");
+    std::fs::write(&path, HIRATE_THREE_MOVES_CSA).unwrap();
+    let mut jkf = read_path_to_jkf(&path, KifuKind::Csa).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // 解析とは別に付いていたコメント
+    jkf.moves[1].comments = Some(vec!["好手".to_string()]);
+
+    // 2 手目 (3c3d) だけが最善手 (8c8d) と違う
+    let plies = vec![
+        ply_analysis(0, true, None, "7g7f", eval_cp(50)),
+        ply_analysis(1, false, Some("7g7f"), "8c8d", eval_cp(-40)),
+        ply_analysis(2, true, Some("3c3d"), "2g2f", eval_cp(60)),
+        ply_analysis(3, false, Some("2g2f"), "8c8d", eval_cp(-50)),
+    ];
+
+    let first = merge_analysis_into_jkf(&mut jkf, &[], &plies).unwrap();
+    assert_eq!(first.commented, 4);
+    assert_eq!(first.forks_added, 1);
+    let after_first = serde_json::to_string(&jkf.moves).unwrap();
+
+    let second = merge_analysis_into_jkf(&mut jkf, &[], &plies).unwrap();
+    assert_eq!(second.commented, 4);
+    assert_eq!(second.forks_added, 0);
+    assert_eq!(serde_json::to_string(&jkf.moves).unwrap(), after_first);
+
+    // 元のコメントは残り、解析コメントは 1 つだけ
+    let comments = jkf.moves[1].comments.as_ref().unwrap();
+    assert_eq!(comments[0], "好手");
+    assert_eq!(
+        comments
+            .iter()
+            .filter(|c| c.starts_with(ANALYSIS_COMMENT_PREFIX))
+            .count(),
+        1
+    );
+    for (i, node) in jkf.moves.iter().enumerate() {
+        let forks = node.forks.as_ref().map_or(0, |f| f.len());
+        assert_eq!(forks, usize::from(i == 2), "forks at node {i}");
+    }
+}
+
+// ============================================================
+// Engine (指し手の分類)
+// ============================================================
+
+fn eval_mate(n: i32) -> app_lib::engine::types::Evaluation {
+    app_lib::engine::types::Evaluation {
+        value: n,
+        kind: app_lib::engine::types::EvaluationKind::MateInMoves(n),
+    }
+}
+
+fn eval_mate_unknown(plus: bool) -> app_lib::engine::types::Evaluation {
+    app_lib::engine::types::Evaluation {
+        value: 0,
+        kind: app_lib::engine::types::EvaluationKind::MateUnknown(plus),
+    }
+}
+
+#[test]
+fn classify_mate_lost_to_cp_is_blunder() {
+    use app_lib::engine::{
+        classify::{classify_game, evaluation_loss},
+        types::{ClassifyThresholds, MoveLabel},
+    };
+
+    let th = ClassifyThresholds::default();
+    // 5 手詰めがあったのに、指した後は相手番で +200 (指した側から見て -200)
+    assert_eq!(
+        evaluation_loss(&eval_mate(5), &eval_cp(200), th.mate_cap_cp),
+        th.mate_cap_cp + 200
+    );
+
+    let plies = vec![
+        ply_analysis(0, true, None, "2b2a+", eval_mate(5)),
+        ply_analysis(1, false, Some("7g7f"), "8c8d", eval_cp(200)),
+    ];
+    let out = classify_game(&plies, &th);
+    assert_eq!(out.moves.len(), 1);
+    assert_eq!(out.moves[0].label, MoveLabel::Blunder);
+}
+
+#[test]
+fn classify_longer_mate_is_not_blunder() {
+    use app_lib::engine::{
+        classify::{classify_game, evaluation_loss},
+        types::{ClassifyThresholds, MoveLabel},
+    };
+
+    let th = ClassifyThresholds::default();
+    // 5 手詰め → 指した後は相手が 6 手で詰む (= 7 手詰めに延びた)。どちらも頭打ちで損失 0
+    assert_eq!(
+        evaluation_loss(&eval_mate(5), &eval_mate(-6), th.mate_cap_cp),
+        0
+    );
+
+    let plies = vec![
+        ply_analysis(0, true, None, "2b2a+", eval_mate(5)),
+        ply_analysis(1, false, Some("3b3a+"), "4a3a", eval_mate(-6)),
+    ];
+    let out = classify_game(&plies, &th);
+    assert_eq!(out.moves[0].loss_cp, 0);
+    assert_eq!(out.moves[0].label, MoveLabel::Good);
+}
+
+#[test]
+fn classify_mate_unknown() {
+    use app_lib::engine::{classify::evaluation_loss, types::ClassifyThresholds};
+
+    let cap = ClassifyThresholds::default().mate_cap_cp;
+    // 距離不明の詰みも頭打ちの値として扱う
+    assert_eq!(
+        evaluation_loss(&eval_mate_unknown(true), &eval_mate_unknown(false), cap),
+        0
+    );
+    assert_eq!(
+        evaluation_loss(&eval_mate_unknown(true), &eval_cp(0), cap),
+        cap
+    );
+    // 詰まされる局面からは何を指しても損はない
+    assert_eq!(
+        evaluation_loss(&eval_mate_unknown(false), &eval_mate_unknown(true), cap),
+        0
+    );
+}
+
+#[test]
+fn classify_attributes_moves_to_each_player() {
+    use app_lib::engine::{
+        classify::classify_game,
+        types::{ClassifyThresholds, MoveLabel},
+    };
+
+    // 先手は 2 手とも最善でなく (1100 / 400 の損)、後手は 2 手とも最善
+    let plies = vec![
+        ply_analysis(0, true, None, "7g7f", eval_cp(100)),
+        ply_analysis(1, false, Some("2g2f"), "8c8d", eval_cp(1000)),
+        ply_analysis(2, true, Some("8c8d"), "2g2f", eval_cp(-1000)),
+        ply_analysis(3, false, Some("7g7f"), "3c3d", eval_cp(1400)),
+        ply_analysis(4, true, Some("3c3d"), "2f2e", eval_cp(-1400)),
+    ];
+    let out = classify_game(&plies, &ClassifyThresholds::default());
+
+    let labels: Vec<(bool, MoveLabel)> = out.moves.iter().map(|m| (m.black, m.label)).collect();
+    assert_eq!(
+        labels,
+        vec![
+            (true, MoveLabel::Blunder),
+            (false, MoveLabel::Best),
+            (true, MoveLabel::Inaccuracy),
+            (false, MoveLabel::Best),
+        ]
+    );
+
+    assert_eq!(out.black.moves, 2);
+    assert_eq!(out.black.blunder, 1);
+    assert_eq!(out.black.inaccuracy, 1);
+    assert_eq!(out.black.average_loss_cp, 750.0);
+    assert_eq!(out.white.moves, 2);
+    assert_eq!(out.white.best, 2);
+    assert_eq!(out.white.average_loss_cp, 0.0);
+}
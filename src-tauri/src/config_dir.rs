@@ -1,16 +1,29 @@
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
-use tauri::{AppHandle, Manager};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Manager, State};
 
 use crate::file_system::utils::atomic_write;
+use crate::search::{
+    api::{reopen_roots, SearchState},
+    fs_scan::{ScanOptions, ScanRules},
+};
 
 const CONFIG_FILE: &str = "app.json";
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct AppConfig {
     pub root_dir: Option<String>,
+    /// root_dir と一緒に開く追加の棋譜フォルダ
+    #[serde(default)]
+    pub root_dirs: Vec<String>,
     pub ai_root: Option<String>,
     pub last_preset_id: Option<String>,
+    /// 棋譜フォルダの走査ルール。変えると index cache は作り直しになる
+    #[serde(default)]
+    pub scan_rules: ScanRules,
 }
 
 fn config_path(app: &AppHandle) -> Result<PathBuf, String> {
@@ -32,9 +45,66 @@ pub fn load_config(app: AppHandle) -> Result<AppConfig, String> {
     }
 }
 
+/// 保存されている走査ルールをコンパイルして返す (未保存なら既定のルール)
+pub fn load_scan_options(app: &AppHandle) -> Result<ScanOptions, String> {
+    let rules = load_config(app.clone())?.scan_rules;
+    ScanOptions::from_rules(&rules).map_err(|e| e.to_string())
+}
+
+/// 走査ルールが変わったら、開いている root をそのルールで開き直す (index と watcher)
 #[tauri::command]
-pub fn save_config(app: AppHandle, config: AppConfig) -> Result<(), String> {
-    let path = config_path(&app)?;
-    let data = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+pub async fn save_config(
+    app: AppHandle,
+    state: State<'_, SearchState>,
+    config: AppConfig,
+) -> Result<(), String> {
+    // 壊れたパターンは保存前に弾く
+    ScanOptions::from_rules(&config.scan_rules).map_err(|e| e.to_string())?;
+    let rules_changed =
+        load_config(app.clone()).map_or(true, |old| old.scan_rules != config.scan_rules);
+    write_config(&app, &config)?;
+    if rules_changed {
+        reopen_roots(&app, &state.roots).await;
+    }
+    Ok(())
+}
+
+fn write_config(app: &AppHandle, config: &AppConfig) -> Result<(), String> {
+    let path = config_path(app)?;
+    let data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
     atomic_write(&path, data.as_bytes()).map_err(|e| e.to_string())
 }
+
+/// 追加で開いた root を root_dirs に記録する。root_dir か記録済みのものと同じなら何もしない
+pub fn add_root_dir(app: &AppHandle, dir: &Path) -> Result<(), String> {
+    let mut config = load_config(app.clone())?;
+    let known = config.root_dir.iter().chain(&config.root_dirs);
+    if known.into_iter().any(|d| same_dir(Path::new(d), dir)) {
+        return Ok(());
+    }
+    config.root_dirs.push(dir.to_string_lossy().to_string());
+    write_config(app, &config)
+}
+
+/// 閉じた root を root_dir / root_dirs から外す
+pub fn remove_root_dir(app: &AppHandle, dir: &Path) -> Result<(), String> {
+    let mut config = load_config(app.clone())?;
+    let before = config.root_dirs.len() + config.root_dir.is_some() as usize;
+    config.root_dirs.retain(|d| !same_dir(Path::new(d), dir));
+    if config
+        .root_dir
+        .as_deref()
+        .is_some_and(|d| same_dir(Path::new(d), dir))
+    {
+        config.root_dir = None;
+    }
+    if config.root_dirs.len() + config.root_dir.is_some() as usize == before {
+        return Ok(());
+    }
+    write_config(app, &config)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    let canon = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.components().collect());
+    canon(a) == canon(b)
+}
@@ -0,0 +1,142 @@
+//! 局面ごとの解析結果のディスクキャッシュ
+//!
+//! `PositionKey` とエンジンの指紋 (名前・設定オプション) ごとに、最も深く読んだ
+//! `AnalysisResult` を 1 ファイルずつ保存する。浅い結果で上書きはしない。
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use shogi_core::PartialPosition;
+
+use super::types::{AnalysisResult, EngineSettings};
+use crate::file_system::utils::atomic_write;
+use crate::search::{
+    position_apply::usi_to_core_move,
+    position_key::{key_from_partial_position, PositionKey},
+    sfen_position::partial_position_from_sfen,
+};
+
+const LOGT: &str = "obs_shogi::engine::analysis_cache";
+
+/// 保存される 1 局面ぶんの解析
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAnalysis {
+    pub depth: u32,
+    pub nodes: u64,
+    pub result: AnalysisResult,
+    pub saved_at_ms: u64,
+}
+
+impl CachedAnalysis {
+    /// 最善候補の深さ・ノード数を取り出す。深さが無い結果は保存しない
+    pub fn from_result(result: &AnalysisResult) -> Option<Self> {
+        let best = result.candidates.iter().find(|c| c.rank == 1)?;
+        Some(Self {
+            depth: best.depth?,
+            nodes: best.nodes.unwrap_or(0),
+            result: result.clone(),
+            saved_at_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        })
+    }
+
+    /// self が other より深く読んでいるか (同じ深さならノード数で比べる)
+    fn is_deeper_than(&self, other: &CachedAnalysis) -> bool {
+        (self.depth, self.nodes) > (other.depth, other.nodes)
+    }
+}
+
+/// エンジン名と設定オプション (評価関数の場所などを含む) の指紋
+pub fn engine_fingerprint(engine_name: &str, settings: &EngineSettings) -> String {
+    let mut opts: Vec<(&String, &String)> = settings.options.iter().collect();
+    opts.sort();
+
+    let mut h = blake3::Hasher::new();
+    h.update(engine_name.as_bytes());
+    for (name, value) in opts {
+        h.update(b"\0");
+        h.update(name.as_bytes());
+        h.update(b"\0");
+        h.update(value.as_bytes());
+    }
+    h.finalize().to_hex()[..32].to_string()
+}
+
+/// `set_position` に渡す文字列 (`startpos moves ...` / `sfen ... moves ...`) の局面キー
+pub fn position_key_from_usi(position: &str) -> Result<PositionKey, String> {
+    let (base, moves) = match position.split_once(" moves ") {
+        Some((base, moves)) => (base, moves),
+        None => (position.trim_end_matches(" moves"), ""),
+    };
+    let mut pos: PartialPosition = partial_position_from_sfen(base).map_err(|e| e.to_string())?;
+    for usi in moves.split_whitespace() {
+        let mv = usi_to_core_move(&pos, usi).map_err(|e| e.to_string())?;
+        pos.make_move(mv)
+            .ok_or_else(|| format!("cannot apply move: {usi}"))?;
+    }
+    Ok(key_from_partial_position(&pos))
+}
+
+/// `<dir>/<engine 指紋>/<局面キー>.json` に保存する
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn entry_path(&self, engine: &str, key: PositionKey) -> PathBuf {
+        self.dir
+            .join(engine)
+            .join(format!("{:016x}{:016x}.json", key.z0, key.z1))
+    }
+
+    pub fn get(&self, engine: &str, key: PositionKey) -> Option<CachedAnalysis> {
+        read_entry(&self.entry_path(engine, key))
+    }
+
+    /// 保存済みのものより深ければ保存する。保存したら true
+    pub fn put_if_deeper(
+        &self,
+        engine: &str,
+        key: PositionKey,
+        result: &AnalysisResult,
+    ) -> Result<bool, String> {
+        let Some(entry) = CachedAnalysis::from_result(result) else {
+            return Ok(false);
+        };
+        let path = self.entry_path(engine, key);
+        if read_entry(&path).is_some_and(|old| !entry.is_deeper_than(&old)) {
+            return Ok(false);
+        }
+
+        let data = serde_json::to_vec(&entry).map_err(|e| e.to_string())?;
+        atomic_write(&path, &data).map_err(|e| format!("{}: {e}", path.display()))?;
+        log::debug!(
+            target: LOGT,
+            "saved depth={} nodes={} path={}",
+            entry.depth,
+            entry.nodes,
+            path.display()
+        );
+        Ok(true)
+    }
+}
+
+fn read_entry(path: &Path) -> Option<CachedAnalysis> {
+    let data = fs::read(path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            log::warn!(target: LOGT, "broken entry {}: {e}", path.display());
+            None
+        }
+    }
+}
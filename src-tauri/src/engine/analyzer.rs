@@ -8,6 +8,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
 use usi::{EngineCommand, GuiCommand, InfoParams, ThinkParams};
 
 const LOGT: &str = "obs_shogi::engine::analyzer";
@@ -326,6 +327,103 @@ impl EngineAnalyzer {
         Ok(analysis_result)
     }
 
+    /// 予算 (時間・深さ・ノード数) を指定した解析。`cancel` で途中停止できる
+    ///
+    /// 時間は byoyomi として渡し、深さ・ノード数は info を見て到達したら stop を送る。
+    /// キャンセル時も bestmove を待ってから `EngineError::Cancelled` を返す。
+    pub async fn analyze_with_budget(
+        &self,
+        budget: &AnalysisBudget,
+        cancel: &CancellationToken,
+    ) -> Result<AnalysisResult, EngineError> {
+        let manager_guard = self.manager.lock().await;
+        if !manager_guard.is_initialized().await {
+            return Err(EngineError::NotInitialized(
+                "Engine not initialized".to_string(),
+            ));
+        }
+        let protocol = manager_guard.protocol()?;
+        drop(manager_guard);
+
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+
+        let listener_id = format!("budget_analysis_{}", now_nanos());
+
+        protocol
+            .register_listener(listener_id.clone(), raw_tx)
+            .await?;
+
+        // 時間指定が無いときは深さ・ノード数で止める前提で最大60秒
+        let time_limit = budget
+            .time_ms
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(60));
+        let go_command = GuiCommand::Go(ThinkParams::new().byoyomi(time_limit));
+        if let Err(e) = protocol.send_command(&go_command).await {
+            protocol.remove_listener(&listener_id).await;
+            return Err(e);
+        }
+
+        // 結果収集
+        let mut result = AnalysisResult::default();
+        let deadline = Instant::now() + time_limit + Duration::from_secs(5);
+        let mut stop_sent = false;
+
+        let outcome = loop {
+            if Instant::now() >= deadline {
+                break Err(EngineError::Timeout("Analysis timeout".to_string()));
+            }
+
+            if !stop_sent && (cancel.is_cancelled() || Self::budget_reached(budget, &result)) {
+                if let Err(e) = protocol.send_command(&GuiCommand::Stop).await {
+                    break Err(e);
+                }
+                stop_sent = true;
+            }
+
+            let cmd = tokio::select! {
+                _ = cancel.cancelled(), if !stop_sent => continue,
+                r = tokio::time::timeout(Duration::from_millis(100), raw_rx.recv()) => r,
+            };
+
+            match cmd {
+                Ok(Some(EngineCommand::Info(info_params))) => {
+                    Self::process_info_params(&info_params, &mut result);
+                }
+                Ok(Some(EngineCommand::Checkmate(checkmate_params))) => {
+                    Self::process_checkmate(&checkmate_params, &mut result);
+                }
+                Ok(Some(EngineCommand::BestMove(_))) => {
+                    if cancel.is_cancelled() {
+                        break Err(EngineError::Cancelled("Analysis cancelled".to_string()));
+                    }
+                    break Ok(result);
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    break Err(EngineError::CommunicationFailed(
+                        "Channel closed".to_string(),
+                    ));
+                }
+                Err(_) => continue, // タイムアウト継続
+            }
+        };
+
+        // クリーンアップ
+        protocol.remove_listener(&listener_id).await;
+
+        let analysis_result = outcome?;
+
+        // 状態更新
+        {
+            let mut state = self.state.write().await;
+            state.last_result = Some(analysis_result.clone());
+            state.analysis_count += 1;
+        }
+
+        Ok(analysis_result)
+    }
+
     /// 解析停止
     pub async fn stop_analysis(&self) -> Result<(), EngineError> {
         let manager_guard = self.manager.lock().await;
@@ -509,6 +607,20 @@ impl EngineAnalyzer {
         Err(EngineError::Timeout("Analysis timeout".to_string()))
     }
 
+    /// 最善手の深さ・ノード数が予算に達したか
+    fn budget_reached(budget: &AnalysisBudget, result: &AnalysisResult) -> bool {
+        let Some(best) = result.candidates.iter().find(|c| c.rank == 1) else {
+            return false;
+        };
+        let depth_done = budget
+            .depth
+            .is_some_and(|d| best.depth.is_some_and(|got| got >= d));
+        let nodes_done = budget
+            .nodes
+            .is_some_and(|n| best.nodes.is_some_and(|got| got >= n));
+        depth_done || nodes_done
+    }
+
     /// InfoParams処理
     fn process_info_params(info_params: &[InfoParams], result: &mut AnalysisResult) {
         let rank = extract_rank(info_params);
@@ -0,0 +1,177 @@
+//! 解析結果を棋譜に書き戻す
+//!
+//! 各手に評価値・深さをコメントで付け、指し手がエンジンの最善手と違う手には
+//! 読み筋を分岐として足す。何度実行しても同じ結果になるよう、自分で付けた
+//! コメントは `ANALYSIS_COMMENT_PREFIX` で見分けて置き換え、同じ手で始まる分岐が
+//! 既にあれば足さない。
+
+use std::collections::HashMap;
+
+use shogi_core::PartialPosition;
+use shogi_kifu_converter_obsshogi::jkf::{MoveFormat, MoveMoveFormat};
+
+use super::types::*;
+use crate::search::{
+    initial_position::{initial_partial_position, Jkf},
+    position_apply::{apply_node_action, core_move_to_jkf, jkf_move_to_usi, usi_to_core_move},
+    traverse::{line_moves_in_jkf, locate_in_jkf_mut, NodeAction},
+    types::{CursorLite, ForkPointer},
+};
+
+/// 解析で付けたコメントの目印。KIF では `**解析 ...` になる
+pub const ANALYSIS_COMMENT_PREFIX: &str = "*解析";
+
+/// 書き戻した件数
+#[derive(Debug, Default, Clone)]
+pub struct MergeSummary {
+    pub commented: usize,
+    pub forks_added: usize,
+}
+
+/// `fork_pointers` の系列に一括解析の結果 (ply 0 から) を書き込む
+pub fn merge_analysis_into_jkf(
+    jkf: &mut Jkf,
+    fork_pointers: &[ForkPointer],
+    plies: &[PlyAnalysis],
+) -> Result<MergeSummary, String> {
+    let line = line_moves_in_jkf(&jkf.moves, fork_pointers);
+
+    // positions[p] = p 手指した後の局面
+    let mut pos = initial_partial_position(jkf).map_err(|e| e.to_string())?;
+    let mut positions = vec![pos.clone()];
+    let mut played = Vec::with_capacity(line.len());
+    for (i, mv) in line.iter().enumerate() {
+        let usi = jkf_move_to_usi(*mv).map_err(|e| format!("ply {}: {e}", i + 1))?;
+        apply_node_action(&mut pos, NodeAction::Move(*mv))
+            .map_err(|e| format!("ply {}: {e}", i + 1))?;
+        played.push(usi);
+        positions.push(pos.clone());
+    }
+
+    let by_ply: HashMap<u32, &PlyAnalysis> = plies.iter().map(|p| (p.ply, p)).collect();
+    let cursor = CursorLite {
+        tesuu: 0,
+        fork_pointers: fork_pointers.to_vec(),
+    };
+    let mut summary = MergeSummary::default();
+
+    for ply in 0..=line.len() as u32 {
+        let Some(analysis) = by_ply.get(&ply) else {
+            continue;
+        };
+        let played_usi = ply.checked_sub(1).map(|i| played[i as usize].as_str());
+        if analysis.last_move.is_some() && analysis.last_move.as_deref() != played_usi {
+            return Err(format!("analysis does not match the kifu at ply {ply}"));
+        }
+
+        // 分岐は「この手の代わりに最善手を指していたら」なので 1 手前の解析を使う
+        let variation = match (ply.checked_sub(1), played_usi) {
+            (Some(prev), Some(played_usi)) => by_ply
+                .get(&prev)
+                .filter(|a| a.best_move.as_deref().is_some_and(|b| b != played_usi))
+                .and_then(|a| variation_line(&positions[prev as usize], a)),
+            _ => None,
+        };
+
+        let Some(node) = locate_in_jkf_mut(&mut jkf.moves, &cursor.filtered_for_tesuu(ply)) else {
+            return Err(format!("kifu node not found at ply {ply}"));
+        };
+
+        if let Some(comment) = analysis_comment(analysis) {
+            let comments = node.comments.get_or_insert_with(Vec::new);
+            comments.retain(|c| !c.starts_with(ANALYSIS_COMMENT_PREFIX));
+            comments.push(comment);
+            summary.commented += 1;
+        }
+
+        if let Some(variation) = variation {
+            let first = variation.first().and_then(|m| m.move_);
+            let exists = node.forks.as_ref().is_some_and(|forks| {
+                forks.iter().any(|f| {
+                    let head = f.first().and_then(|m| m.move_);
+                    head.is_some() && same_usi(head, first)
+                })
+            });
+            if !exists {
+                node.forks.get_or_insert_with(Vec::new).push(variation);
+                summary.forks_added += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// 評価値と深さのコメント。評価が無ければ付けない
+fn analysis_comment(a: &PlyAnalysis) -> Option<String> {
+    let eval = a.evaluation.as_ref()?;
+    let mut out = format!(
+        "{ANALYSIS_COMMENT_PREFIX} 評価値 {}",
+        format_evaluation(a, eval)
+    );
+    if let Some(depth) = a.depth {
+        out.push_str(&format!(" 深さ {depth}"));
+    }
+    if let Some(best) = &a.best_move {
+        out.push_str(&format!(" 最善手 {best}"));
+    }
+    Some(out)
+}
+
+/// 先手から見た表記にする (詰みは勝つ側と手数)
+fn format_evaluation(a: &PlyAnalysis, eval: &Evaluation) -> String {
+    let winner = |side_to_move_wins: bool| {
+        if side_to_move_wins == a.black_to_move {
+            "先手"
+        } else {
+            "後手"
+        }
+    };
+    match eval.kind {
+        EvaluationKind::MateInMoves(n) => format!("{}勝ち {}手詰", winner(n > 0), n.abs()),
+        EvaluationKind::MateUnknown(plus) => format!("{}勝ち 詰", winner(plus)),
+        EvaluationKind::Centipawn => format!("{:+}", a.score_black.unwrap_or(eval.value)),
+    }
+}
+
+/// 局面 `pos` からの読み筋を JKF の系列にする。指せない手が出たらそこで切る
+fn variation_line(pos: &PartialPosition, a: &PlyAnalysis) -> Option<Vec<MoveFormat>> {
+    let best = a.best_move.as_deref()?;
+    let pv: Vec<&str> = if a.pv.first().map(String::as_str) == Some(best) {
+        a.pv.iter().map(String::as_str).collect()
+    } else {
+        vec![best]
+    };
+
+    let mut pos = pos.clone();
+    let mut out = Vec::new();
+    for usi in pv {
+        let Ok(mv) = usi_to_core_move(&pos, usi) else {
+            break;
+        };
+        let Ok(jm) = core_move_to_jkf(&pos, mv) else {
+            break;
+        };
+        if pos.make_move(mv).is_none() {
+            break;
+        }
+        out.push(MoveFormat {
+            comments: None,
+            move_: Some(jm),
+            time: None,
+            special: None,
+            forks: None,
+        });
+    }
+
+    let first = out.first_mut()?;
+    first.comments = Some(vec![format!("{ANALYSIS_COMMENT_PREFIX} 読み筋")]);
+    Some(out)
+}
+
+fn same_usi(a: Option<MoveMoveFormat>, b: Option<MoveMoveFormat>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => jkf_move_to_usi(a).ok() == jkf_move_to_usi(b).ok(),
+        _ => false,
+    }
+}
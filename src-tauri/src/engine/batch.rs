@@ -0,0 +1,151 @@
+//! 棋譜一本ぶんの一括解析
+//!
+//! JKF の本譜 (または指定した分岐) を辿って各局面の `position` 文字列を作り、
+//! `EngineAnalyzer::analyze_with_budget` で順に解析する。
+
+use shogi_core::Color;
+use tokio_util::sync::CancellationToken;
+
+use super::analyzer::EngineAnalyzer;
+use super::types::*;
+use crate::search::{
+    initial_position::{initial_partial_position, Jkf},
+    position_apply::{apply_node_action, jkf_move_to_usi},
+    sfen_position::partial_position_to_sfen,
+    traverse::{line_moves_in_jkf, NodeAction},
+    types::ForkPointer,
+};
+
+const LOGT: &str = "obs_shogi::engine::batch";
+
+/// 評価値グラフで詰みを表す値。n 手詰みは `MATE_SCORE - n` にする
+pub const MATE_SCORE: i32 = 100_000;
+
+/// 解析対象の 1 局面
+#[derive(Debug, Clone)]
+pub struct PlannedPly {
+    /// 0 = 開始局面
+    pub ply: u32,
+    /// この局面に至った手 (USI)
+    pub last_move: Option<String>,
+    /// `GuiCommand::Position` に渡す文字列 (`sfen ... moves ...`)
+    pub position: String,
+    pub side_to_move: Color,
+}
+
+/// 系列を辿り、開始局面から最終局面までの各局面を並べる
+pub fn plan_batch(jkf: &Jkf, fork_pointers: &[ForkPointer]) -> Result<Vec<PlannedPly>, String> {
+    let mut pos = initial_partial_position(jkf).map_err(|e| e.to_string())?;
+    let initial_sfen = partial_position_to_sfen(&pos);
+
+    let mut plies = vec![PlannedPly {
+        ply: 0,
+        last_move: None,
+        position: format!("sfen {initial_sfen}"),
+        side_to_move: pos.side_to_move(),
+    }];
+
+    let mut usi_moves: Vec<String> = Vec::new();
+    for (i, mv) in line_moves_in_jkf(&jkf.moves, fork_pointers)
+        .into_iter()
+        .enumerate()
+    {
+        let ply = i as u32 + 1;
+        let usi = jkf_move_to_usi(mv).map_err(|e| format!("ply {ply}: {e}"))?;
+        apply_node_action(&mut pos, NodeAction::Move(mv)).map_err(|e| format!("ply {ply}: {e}"))?;
+        usi_moves.push(usi.clone());
+
+        plies.push(PlannedPly {
+            ply,
+            last_move: Some(usi),
+            position: format!("sfen {initial_sfen} moves {}", usi_moves.join(" ")),
+            side_to_move: pos.side_to_move(),
+        });
+    }
+
+    Ok(plies)
+}
+
+/// 評価を手番側から見た 1 つの数値にする。詰みは ±`MATE_SCORE` 付近に丸める
+pub fn score_for_side_to_move(eval: &Evaluation) -> i32 {
+    match eval.kind {
+        EvaluationKind::Centipawn => eval.value,
+        // mate 0 / mate -n は手番側が詰まされている
+        EvaluationKind::MateInMoves(n) if n > 0 => MATE_SCORE - n,
+        EvaluationKind::MateInMoves(n) => -(MATE_SCORE + n),
+        EvaluationKind::MateUnknown(true) => MATE_SCORE,
+        EvaluationKind::MateUnknown(false) => -MATE_SCORE,
+    }
+}
+
+/// 手番側から見た評価を先手から見た値にする
+pub fn score_for_black(eval: &Evaluation, side_to_move: Color) -> i32 {
+    let score = score_for_side_to_move(eval);
+    match side_to_move {
+        Color::Black => score,
+        Color::White => -score,
+    }
+}
+
+/// 解析結果の最善候補を 1 局面ぶんの結果にまとめる
+pub fn ply_analysis(planned: &PlannedPly, result: &AnalysisResult) -> PlyAnalysis {
+    let best = result.candidates.iter().find(|c| c.rank == 1);
+    let evaluation = best.and_then(|c| c.evaluation.clone());
+    PlyAnalysis {
+        ply: planned.ply,
+        black_to_move: planned.side_to_move == Color::Black,
+        last_move: planned.last_move.clone(),
+        best_move: best.and_then(|c| c.first_move.clone()),
+        pv: best.map(|c| c.pv_line.clone()).unwrap_or_default(),
+        score_black: evaluation
+            .as_ref()
+            .map(|e| score_for_black(e, planned.side_to_move)),
+        evaluation,
+        depth: best.and_then(|c| c.depth),
+        nodes: best.and_then(|c| c.nodes),
+    }
+}
+
+/// 各局面を順に解析する。1 局面終わるごとに `on_ply(done, total, &result)` を呼ぶ。
+///
+/// キャンセル・エラー時はそこまでの結果とエラーを返す。
+pub async fn run_batch(
+    analyzer: &EngineAnalyzer,
+    plies: &[PlannedPly],
+    budget: &AnalysisBudget,
+    cancel: &CancellationToken,
+    mut on_ply: impl FnMut(usize, usize, &PlyAnalysis),
+) -> (Vec<PlyAnalysis>, Result<(), EngineError>) {
+    let total = plies.len();
+    let mut out = Vec::with_capacity(total);
+
+    for planned in plies {
+        if cancel.is_cancelled() {
+            return (
+                out,
+                Err(EngineError::Cancelled("Batch cancelled".to_string())),
+            );
+        }
+
+        if let Err(e) = analyzer.set_position(&planned.position).await {
+            return (out, Err(e));
+        }
+        let result = match analyzer.analyze_with_budget(budget, cancel).await {
+            Ok(r) => r,
+            Err(e) => return (out, Err(e)),
+        };
+
+        let ply = ply_analysis(planned, &result);
+        log::debug!(
+            target: LOGT,
+            "batch: ply={} best={:?} score_black={:?}",
+            ply.ply,
+            ply.best_move,
+            ply.score_black
+        );
+        out.push(ply);
+        on_ply(out.len(), total, &out[out.len() - 1]);
+    }
+
+    (out, Ok(()))
+}
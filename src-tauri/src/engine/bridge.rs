@@ -1,14 +1,21 @@
 use crate::engine::utils::LogThrottle;
 
+use super::analysis_cache::{
+    engine_fingerprint, position_key_from_usi, AnalysisCache, CachedAnalysis,
+};
 use super::analyzer::EngineAnalyzer;
+use super::batch::{plan_batch, run_batch};
+use super::classify::classify_game;
 use super::types::*;
+use crate::search::position_key::PositionKey;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
+use tokio_util::sync::CancellationToken;
 
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
 
 const LOGT: &str = "obs_shogi::engine::bridge";
 
@@ -26,12 +33,24 @@ impl AppState {
     }
 }
 
-/// Tauriコマンドとエンジン機能の橋渡し
+/// エンジンを指定しないコマンドが使う engine_id
+pub const DEFAULT_ENGINE_ID: &str = "default";
+
+/// Tauriコマンドとエンジン機能の橋渡し。engine_id ごとに別プロセスのエンジンを持つ
 pub struct EngineBridge {
+    engines: RwLock<HashMap<String, Arc<EngineInstance>>>,
+    app_handle: Arc<RwLock<Option<tauri::AppHandle>>>,
+}
+
+/// 名前付きのエンジン 1 つ (= EngineAnalyzer → EngineManager → 1 プロセス) と、その解析状態
+struct EngineInstance {
+    engine_id: String,
     analyzer: EngineAnalyzer,
     active_sessions: Arc<RwLock<HashMap<String, AnalysisSession>>>,
     settings: Arc<RwLock<EngineSettings>>,
     app_handle: Arc<RwLock<Option<tauri::AppHandle>>>,
+    /// 実行中の一括解析 (job_id -> キャンセル用トークン)
+    batch_jobs: Arc<RwLock<HashMap<String, CancellationToken>>>,
 }
 
 #[derive(Debug)]
@@ -42,10 +61,49 @@ struct AnalysisSession {
 
 #[derive(Debug, Clone, Serialize)]
 struct AnalysisUpdate {
+    engine_id: String,
     session_id: String,
     result: AnalysisResult,
 }
 
+/// 解析キャッシュの保存先と、局面・エンジンに対応するキー
+struct CacheTarget {
+    cache: AnalysisCache,
+    engine: String,
+    key: PositionKey,
+}
+
+impl CacheTarget {
+    fn lookup(&self) -> Option<CachedAnalysis> {
+        self.cache.get(&self.engine, self.key)
+    }
+
+    /// 失敗してもログだけ残す (キャッシュなので解析結果は返せる)
+    fn store(&self, result: &AnalysisResult) {
+        if let Err(e) = self.cache.put_if_deeper(&self.engine, self.key, result) {
+            log::warn!(target: LOGT, "analysis cache: save failed: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchProgress {
+    engine_id: String,
+    job_id: String,
+    done: usize,
+    total: usize,
+    ply: PlyAnalysis,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchEnd {
+    engine_id: String,
+    job_id: String,
+    plies: Vec<PlyAnalysis>,
+    cancelled: bool,
+    error: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 enum SessionType {
     Infinite,
@@ -58,9 +116,7 @@ enum SessionType {
 impl EngineBridge {
     pub fn new() -> Self {
         Self {
-            analyzer: EngineAnalyzer::new(),
-            active_sessions: Arc::new(RwLock::new(HashMap::new())),
-            settings: Arc::new(RwLock::new(EngineSettings::default())),
+            engines: RwLock::new(HashMap::new()),
             app_handle: Arc::new(RwLock::new(None)),
         }
     }
@@ -70,12 +126,93 @@ impl EngineBridge {
         *self.app_handle.write().await = Some(handle);
     }
 
+    /// 登録済みのエンジン。engine_id 省略時は DEFAULT_ENGINE_ID
+    async fn engine(&self, engine_id: Option<String>) -> Result<Arc<EngineInstance>, String> {
+        let id = engine_id.unwrap_or_else(|| DEFAULT_ENGINE_ID.to_string());
+        self.engines
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("Engine not found: {id}"))
+    }
+
+    /// engine_id のエンジンを返す。無ければ (未初期化のまま) 登録する
+    async fn engine_or_insert(&self, engine_id: Option<String>) -> Arc<EngineInstance> {
+        let id = engine_id.unwrap_or_else(|| DEFAULT_ENGINE_ID.to_string());
+        let mut engines = self.engines.write().await;
+        let instance = engines
+            .entry(id.clone())
+            .or_insert_with(|| Arc::new(EngineInstance::new(id, Arc::clone(&self.app_handle))));
+        Arc::clone(instance)
+    }
+
+    pub async fn initialize_engine_impl(
+        &self,
+        engine_id: Option<String>,
+        engine_path: String,
+        working_dir: Option<String>,
+    ) -> Result<(), String> {
+        self.engine_or_insert(engine_id)
+            .await
+            .initialize_engine_impl(engine_path, working_dir)
+            .await
+    }
+
+    /// エンジンを止めて登録から外す。未登録の id は何もせず Ok。
+    /// 止めるのに失敗しても登録は外す (残すと同じ id で初期化し直せなくなる)
+    pub async fn shutdown_engine_impl(&self, engine_id: Option<String>) -> Result<(), String> {
+        let id = engine_id.unwrap_or_else(|| DEFAULT_ENGINE_ID.to_string());
+        let Some(instance) = self.engines.write().await.remove(&id) else {
+            log::debug!(target: LOGT, "shutdown_engine: not registered engine_id={}", id);
+            return Ok(());
+        };
+        instance.shutdown_engine_impl().await
+    }
+
+    /// 一括解析の結果から各手を分類する。閾値省略時は既定値
+    pub fn classify_analysis_impl(
+        &self,
+        plies: Vec<PlyAnalysis>,
+        thresholds: Option<ClassifyThresholds>,
+    ) -> Result<GameClassification, String> {
+        let th = thresholds.unwrap_or_default();
+        if !(th.inaccuracy_cp <= th.mistake_cp && th.mistake_cp <= th.blunder_cp) {
+            return Err("thresholds must satisfy inaccuracy <= mistake <= blunder".to_string());
+        }
+        Ok(classify_game(&plies, &th))
+    }
+
+    /// 登録済みの engine_id 一覧 (名前順)
+    pub async fn list_engines_impl(&self) -> Result<Vec<String>, String> {
+        let mut ids: Vec<String> = self.engines.read().await.keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+impl EngineInstance {
+    fn new(engine_id: String, app_handle: Arc<RwLock<Option<tauri::AppHandle>>>) -> Self {
+        Self {
+            engine_id,
+            analyzer: EngineAnalyzer::new(),
+            active_sessions: Arc::new(RwLock::new(HashMap::new())),
+            settings: Arc::new(RwLock::new(EngineSettings::default())),
+            app_handle,
+            batch_jobs: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
     pub async fn initialize_engine_impl(
         &self,
         engine_path: String,
         working_dir: Option<String>,
     ) -> Result<(), String> {
-        log::info!(target: LOGT, "initialize_engine: start");
+        log::info!(
+            target: LOGT,
+            "initialize_engine: start engine_id={}",
+            self.engine_id
+        );
 
         // engine_path は絶対パスかつ既存ファイルであることを要求する。
         // 攻撃者が /bin/sh などの任意バイナリを起動させる経路を塞ぐ最低限のガード。
@@ -105,12 +242,20 @@ impl EngineBridge {
     async fn ensure_no_active_session(&self) -> Result<(), String> {
         let sessions = self.active_sessions.read().await;
         let has_active = sessions.values().any(|s| s.is_active);
-        if has_active {
+        if has_active || !self.batch_jobs.read().await.is_empty() {
             return Err("Analysis already running".to_string());
         }
         Ok(())
     }
 
+    /// 一括解析はエンジンの局面を 1 手ずつ差し替えていくので、その間の局面設定や単発解析は断る
+    async fn ensure_no_batch_job(&self) -> Result<(), String> {
+        if !self.batch_jobs.read().await.is_empty() {
+            return Err("Batch analysis running".to_string());
+        }
+        Ok(())
+    }
+
     pub async fn shutdown_engine_impl(&self) -> Result<(), String> {
         log::info!(target: LOGT, "shutdown_engine: start");
 
@@ -129,6 +274,10 @@ impl EngineBridge {
     }
 
     pub async fn set_position_impl(&self, position: String) -> Result<(), String> {
+        if let Err(e) = self.ensure_no_batch_job().await {
+            log::warn!(target: LOGT, "set_position: rejected: {}", e);
+            return Err(e);
+        }
         log::debug!(target: LOGT, "set_position: len={}", position.len());
 
         self.analyzer.set_position(&position).await.map_err(|e| {
@@ -148,6 +297,8 @@ impl EngineBridge {
 
         log::debug!(target: LOGT, "start_infinite_analysis: requested");
 
+        let cache_target = self.current_cache_target().await;
+
         let result_rx = self.analyzer.start_infinite_analysis().await.map_err(|e| {
             log::error!(
                 target: LOGT,
@@ -164,7 +315,34 @@ impl EngineBridge {
             session_id
         );
 
-        self.start_result_forwarding(&session_id, result_rx).await;
+        // 保存済みの解析があれば、エンジンの最初の info を待たずに先に見せる
+        if let Some(hit) = cache_target.as_ref().and_then(|t| t.lookup()) {
+            log::debug!(
+                target: LOGT,
+                "start_infinite_analysis: cache hit depth={}",
+                hit.depth
+            );
+            if let Some(session) = self.active_sessions.write().await.get_mut(&session_id) {
+                session.last_result = Some(hit.result.clone());
+            }
+            if let Some(handle) = self.app_handle.read().await.clone() {
+                let payload = AnalysisUpdate {
+                    engine_id: self.engine_id.clone(),
+                    session_id: session_id.clone(),
+                    result: hit.result,
+                };
+                if let Err(e) = handle.emit("analysis-update", payload) {
+                    log::warn!(
+                        target: LOGT,
+                        "start_infinite_analysis: emit cached failed: {}",
+                        e
+                    );
+                }
+            }
+        }
+
+        self.start_result_forwarding(&session_id, result_rx, cache_target)
+            .await;
         Ok(session_id)
     }
 
@@ -172,17 +350,21 @@ impl EngineBridge {
         &self,
         session_id: &str,
         receiver: mpsc::UnboundedReceiver<AnalysisResult>,
+        cache_target: Option<CacheTarget>,
     ) {
         let sessions_clone = Arc::clone(&self.active_sessions);
         let app_handle_clone = Arc::clone(&self.app_handle);
         let session_id_clone = session_id.to_string();
+        let engine_id = self.engine_id.clone();
 
         tokio::spawn(async move {
             Self::forward_results_to_ui(
                 app_handle_clone,
                 sessions_clone,
+                engine_id,
                 session_id_clone,
                 receiver,
+                cache_target,
             )
             .await;
         });
@@ -192,8 +374,10 @@ impl EngineBridge {
     async fn forward_results_to_ui(
         app_handle: Arc<RwLock<Option<tauri::AppHandle>>>,
         sessions: Arc<RwLock<HashMap<String, AnalysisSession>>>,
+        engine_id: String,
         session_id: String,
         mut receiver: mpsc::UnboundedReceiver<AnalysisResult>,
+        cache_target: Option<CacheTarget>,
     ) {
         // session が消えたら emit/保存をやめるためのフラグ
         let mut session_exists = true;
@@ -202,8 +386,14 @@ impl EngineBridge {
         let mut emit_warn = LogThrottle::new(Duration::from_secs(5));
         // session消失も1回だけdebug
         let mut session_missing_logged = false;
+        // 解析が終わったら最後の結果をキャッシュに残す
+        let mut last_result: Option<AnalysisResult> = None;
 
         while let Some(result) = receiver.recv().await {
+            if cache_target.is_some() {
+                last_result = Some(result.clone());
+            }
+
             // session がまだあるなら last_result を保存 & active なら emit
             let mut emit = false;
 
@@ -229,6 +419,7 @@ impl EngineBridge {
             if emit {
                 if let Some(handle) = app_handle.read().await.clone() {
                     let payload = AnalysisUpdate {
+                        engine_id: engine_id.clone(),
                         session_id: session_id.clone(),
                         result,
                     };
@@ -247,6 +438,10 @@ impl EngineBridge {
             // session が消えた後は、receiver を drop せずに drain 継続する
         }
 
+        if let (Some(target), Some(result)) = (&cache_target, &last_result) {
+            target.store(result);
+        }
+
         // receiver が閉じた（analyzer 側が終了）ので最後に状態だけ落とす
         {
             let mut sessions_guard = sessions.write().await;
@@ -265,19 +460,229 @@ impl EngineBridge {
         &self,
         time_seconds: u64,
     ) -> Result<AnalysisResult, String> {
+        if let Err(e) = self.ensure_no_batch_job().await {
+            log::warn!(target: LOGT, "analyze_with_time: rejected: {}", e);
+            return Err(e);
+        }
         let duration = Duration::from_secs(time_seconds);
+        let cache_target = self.current_cache_target().await;
 
-        self.analyzer
+        let result = self
+            .analyzer
             .analyze_with_time(duration)
             .await
-            .map_err(|e| format!("Timed analysis failed: {:?}", e))
+            .map_err(|e| format!("Timed analysis failed: {:?}", e))?;
+
+        if let Some(target) = &cache_target {
+            target.store(&result);
+        }
+        Ok(result)
     }
 
+    /// 保存済みの解析が depth 以上読んでいれば、エンジンを動かさずにそれを返す
     pub async fn analyze_with_depth_impl(&self, depth: u32) -> Result<AnalysisResult, String> {
-        self.analyzer
+        if let Err(e) = self.ensure_no_batch_job().await {
+            log::warn!(target: LOGT, "analyze_with_depth: rejected: {}", e);
+            return Err(e);
+        }
+        let cache_target = self.current_cache_target().await;
+        if let Some(hit) = cache_target
+            .as_ref()
+            .and_then(|t| t.lookup())
+            .filter(|hit| hit.depth >= depth)
+        {
+            log::debug!(
+                target: LOGT,
+                "analyze_with_depth: cache hit depth={} requested={}",
+                hit.depth,
+                depth
+            );
+            return Ok(hit.result);
+        }
+
+        let result = self
+            .analyzer
             .analyze_with_depth(depth)
             .await
-            .map_err(|e| format!("Depth analysis failed: {:?}", e))
+            .map_err(|e| format!("Depth analysis failed: {:?}", e))?;
+
+        if let Some(target) = &cache_target {
+            target.store(&result);
+        }
+        Ok(result)
+    }
+
+    /// position (`set_position` と同じ形式) の保存済み解析を返す
+    pub async fn get_cached_analysis_impl(
+        &self,
+        position: String,
+    ) -> Result<Option<CachedAnalysis>, String> {
+        let target = self.cache_target_for(&position).await?;
+        Ok(target.and_then(|t| t.lookup()))
+    }
+
+    /// 今の局面・エンジンのキャッシュ先。局面未設定・エンジン未初期化なら None
+    async fn current_cache_target(&self) -> Option<CacheTarget> {
+        let position = self.analyzer.get_current_position().await?;
+        match self.cache_target_for(&position).await {
+            Ok(target) => target,
+            Err(e) => {
+                log::debug!(target: LOGT, "analysis cache: skipped: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn cache_target_for(&self, position: &str) -> Result<Option<CacheTarget>, String> {
+        let Some(handle) = self.app_handle.read().await.clone() else {
+            return Ok(None);
+        };
+        let info = match self.analyzer.get_engine_info().await {
+            Ok(info) => info,
+            Err(EngineError::NotInitialized(_)) => return Ok(None),
+            Err(e) => return Err(format!("Failed to get engine info: {:?}", e)),
+        };
+
+        let key = position_key_from_usi(position)?;
+        let engine = engine_fingerprint(&info.name, &*self.settings.read().await);
+        let dir = handle
+            .path()
+            .app_cache_dir()
+            .map_err(|e| e.to_string())?
+            .join("obs-shogi")
+            .join("analysis");
+
+        Ok(Some(CacheTarget {
+            cache: AnalysisCache::new(dir),
+            engine,
+            key,
+        }))
+    }
+
+    /// 棋譜一本の一括解析を開始し job_id を返す。
+    ///
+    /// 進捗は局面ごとに "analysis-batch-progress"、終了時 (完了・キャンセル・エラー) に
+    /// 全局面ぶんの結果を "analysis-batch-end" で送る。
+    pub async fn start_batch_analysis_impl(
+        &self,
+        input: BatchAnalysisInput,
+    ) -> Result<String, String> {
+        let plies = plan_batch(&input.jkf, &input.fork_pointers)
+            .map_err(|e| format!("Failed to read kifu line: {e}"))?;
+        let budget = input.budget;
+
+        let job_id = format!(
+            "batch_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let cancel = CancellationToken::new();
+        {
+            // 確認と登録を同じ write lock の中で行い、同時に 2 本始まらないようにする。
+            // lock の順は ensure_no_active_session と同じ (sessions → batch_jobs)
+            let sessions = self.active_sessions.read().await;
+            let mut jobs = self.batch_jobs.write().await;
+            if sessions.values().any(|s| s.is_active) || !jobs.is_empty() {
+                log::warn!(
+                    target: LOGT,
+                    "start_batch_analysis: rejected: Analysis already running"
+                );
+                return Err("Analysis already running".to_string());
+            }
+            jobs.insert(job_id.clone(), cancel.clone());
+        }
+
+        log::info!(
+            target: LOGT,
+            "start_batch_analysis: ok job_id={} plies={}",
+            job_id,
+            plies.len()
+        );
+
+        let analyzer = self.analyzer.clone();
+        let app_handle = self.app_handle.read().await.clone();
+        let batch_jobs = Arc::clone(&self.batch_jobs);
+        let id = job_id.clone();
+        let engine_id = self.engine_id.clone();
+
+        tokio::spawn(async move {
+            let mut emit_warn = LogThrottle::new(Duration::from_secs(5));
+            let (results, outcome) =
+                run_batch(&analyzer, &plies, &budget, &cancel, |done, total, ply| {
+                    let Some(handle) = &app_handle else {
+                        return;
+                    };
+                    let payload = BatchProgress {
+                        engine_id: engine_id.clone(),
+                        job_id: id.clone(),
+                        done,
+                        total,
+                        ply: ply.clone(),
+                    };
+                    if let Err(e) = handle.emit("analysis-batch-progress", payload) {
+                        if emit_warn.allow() {
+                            log::warn!(
+                                target: LOGT,
+                                "batch: emit progress failed job_id={} err={}",
+                                id,
+                                e
+                            );
+                        }
+                    }
+                })
+                .await;
+
+            batch_jobs.write().await.remove(&id);
+
+            let (cancelled, error) = match outcome {
+                Ok(()) => (false, None),
+                Err(EngineError::Cancelled(_)) => (true, None),
+                Err(e) => {
+                    log::warn!(target: LOGT, "batch: failed job_id={} err={:?}", id, e);
+                    (false, Some(format!("{e}")))
+                }
+            };
+            log::info!(
+                target: LOGT,
+                "batch: end job_id={} analysed={} cancelled={}",
+                id,
+                results.len(),
+                cancelled
+            );
+
+            if let Some(handle) = &app_handle {
+                let payload = BatchEnd {
+                    engine_id: engine_id.clone(),
+                    job_id: id.clone(),
+                    plies: results,
+                    cancelled,
+                    error,
+                };
+                if let Err(e) = handle.emit("analysis-batch-end", payload) {
+                    log::warn!(
+                        target: LOGT,
+                        "batch: emit end failed job_id={} err={}",
+                        id,
+                        e
+                    );
+                }
+            }
+        });
+
+        Ok(job_id)
+    }
+
+    /// 一括解析を止める。解析中の局面は stop を送って打ち切る
+    pub async fn cancel_batch_analysis_impl(&self, job_id: String) -> Result<(), String> {
+        let jobs = self.batch_jobs.read().await;
+        let Some(cancel) = jobs.get(&job_id) else {
+            return Err("Batch job not found".to_string());
+        };
+        cancel.cancel();
+        log::info!(target: LOGT, "cancel_batch_analysis: job_id={}", job_id);
+        Ok(())
     }
 
     pub async fn stop_analysis_impl(&self, session_id: Option<String>) -> Result<(), String> {
@@ -304,6 +709,10 @@ impl EngineBridge {
     }
 
     pub async fn apply_engine_settings_impl(&self, settings: EngineSettings) -> Result<(), String> {
+        if let Err(e) = self.ensure_no_batch_job().await {
+            log::warn!(target: LOGT, "apply_engine_settings: rejected: {}", e);
+            return Err(e);
+        }
         log::info!(
             target: LOGT,
             "apply_engine_settings: start options={}",
@@ -422,6 +831,10 @@ impl EngineBridge {
             sessions.clear();
         }
 
+        for cancel in self.batch_jobs.read().await.values() {
+            cancel.cancel();
+        }
+
         self.analyzer.stop_analysis().await.map_err(|e| {
             log::error!(
                 target: LOGT,
@@ -443,101 +856,223 @@ impl Default for EngineBridge {
 }
 
 // === Tauriコマンド定義 ===
+// engine_id を省略したコマンドは DEFAULT_ENGINE_ID のエンジンを使う
 
 #[tauri::command]
 pub async fn initialize_engine(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     engine_path: String,
     working_dir: Option<String>,
 ) -> Result<(), String> {
     state
         .bridge
-        .initialize_engine_impl(engine_path, working_dir)
+        .initialize_engine_impl(engine_id, engine_path, working_dir)
         .await
 }
 
 #[tauri::command]
-pub async fn shutdown_engine(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    state.bridge.shutdown_engine_impl().await
+pub async fn shutdown_engine(
+    state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
+) -> Result<(), String> {
+    state.bridge.shutdown_engine_impl(engine_id).await
+}
+
+#[tauri::command]
+pub async fn list_engines(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    state.bridge.list_engines_impl().await
 }
 
 #[tauri::command]
 pub async fn set_position(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     position: String,
 ) -> Result<(), String> {
-    state.bridge.set_position_impl(position).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .set_position_impl(position)
+        .await
 }
 
 #[tauri::command]
-pub async fn start_infinite_analysis(state: tauri::State<'_, AppState>) -> Result<String, String> {
-    state.bridge.start_infinite_analysis_impl().await
+pub async fn start_infinite_analysis(
+    state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
+) -> Result<String, String> {
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .start_infinite_analysis_impl()
+        .await
 }
 
 #[tauri::command]
 pub async fn analyze_with_time(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     time_seconds: u64,
 ) -> Result<AnalysisResult, String> {
-    state.bridge.analyze_with_time_impl(time_seconds).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .analyze_with_time_impl(time_seconds)
+        .await
 }
 
 #[tauri::command]
 pub async fn analyze_with_depth(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     depth: u32,
 ) -> Result<AnalysisResult, String> {
-    state.bridge.analyze_with_depth_impl(depth).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .analyze_with_depth_impl(depth)
+        .await
+}
+
+#[tauri::command]
+pub async fn start_batch_analysis(
+    state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
+    input: BatchAnalysisInput,
+) -> Result<String, String> {
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .start_batch_analysis_impl(input)
+        .await
+}
+
+#[tauri::command]
+pub async fn cancel_batch_analysis(
+    state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
+    job_id: String,
+) -> Result<(), String> {
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .cancel_batch_analysis_impl(job_id)
+        .await
+}
+
+#[tauri::command]
+pub async fn classify_analysis(
+    state: tauri::State<'_, AppState>,
+    plies: Vec<PlyAnalysis>,
+    thresholds: Option<ClassifyThresholds>,
+) -> Result<GameClassification, String> {
+    state.bridge.classify_analysis_impl(plies, thresholds)
+}
+
+#[tauri::command]
+pub async fn get_cached_analysis(
+    state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
+    position: String,
+) -> Result<Option<CachedAnalysis>, String> {
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .get_cached_analysis_impl(position)
+        .await
 }
 
 #[tauri::command]
 pub async fn stop_analysis(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     session_id: Option<String>,
 ) -> Result<(), String> {
-    state.bridge.stop_analysis_impl(session_id).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .stop_analysis_impl(session_id)
+        .await
 }
 
 #[tauri::command]
 pub async fn get_analysis_result(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     session_id: String,
 ) -> Result<Option<AnalysisResult>, String> {
-    state.bridge.get_analysis_result_impl(session_id).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .get_analysis_result_impl(session_id)
+        .await
 }
 
 #[tauri::command]
 pub async fn get_last_result(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
 ) -> Result<Option<AnalysisResult>, String> {
-    state.bridge.get_last_result_impl().await
+    match state.bridge.engine(engine_id).await {
+        Ok(engine) => engine.get_last_result_impl().await,
+        Err(_) => Ok(None),
+    }
 }
 
 #[tauri::command]
 pub async fn apply_engine_settings(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
     settings: EngineSettings,
 ) -> Result<(), String> {
-    state.bridge.apply_engine_settings_impl(settings).await
+    state
+        .bridge
+        .engine(engine_id)
+        .await?
+        .apply_engine_settings_impl(settings)
+        .await
 }
 
 #[tauri::command]
 pub async fn get_engine_settings(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
 ) -> Result<EngineSettings, String> {
-    state.bridge.get_engine_settings_impl().await
+    match state.bridge.engine(engine_id).await {
+        Ok(engine) => engine.get_engine_settings_impl().await,
+        Err(_) => Ok(EngineSettings::default()),
+    }
 }
 
 #[tauri::command]
 pub async fn get_analysis_status(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
 ) -> Result<Vec<AnalysisStatus>, String> {
-    state.bridge.get_analysis_status_impl().await
+    match state.bridge.engine(engine_id).await {
+        Ok(engine) => engine.get_analysis_status_impl().await,
+        Err(_) => Ok(Vec::new()),
+    }
 }
 
+/// 未登録・未初期化のエンジンは None
 #[tauri::command]
 pub async fn get_engine_info(
     state: tauri::State<'_, AppState>,
+    engine_id: Option<String>,
 ) -> Result<Option<EngineInfo>, String> {
-    state.bridge.get_engine_info_impl().await
+    match state.bridge.engine(engine_id).await {
+        Ok(engine) => engine.get_engine_info_impl().await,
+        Err(_) => Ok(None),
+    }
 }
@@ -0,0 +1,110 @@
+//! 解析結果から指し手を分類する (最善 / 好手 / 疑問手 / 悪手 / 大悪手)
+//!
+//! 指す前の局面の評価 (最善を指した場合) と、指した後の局面の評価を
+//! 指した側から見た値にそろえ、その差を損失とする。
+
+use super::batch::score_for_side_to_move;
+use super::types::*;
+
+/// 指す前後の評価から損失 (cp, 0 以上) を求める。
+///
+/// `before` は指す側の手番、`after` は相手の手番から見た評価。
+/// 詰みを含む評価は ±`mate_cap_cp` に丸めてから差を取る。
+pub fn evaluation_loss(before: &Evaluation, after: &Evaluation, mate_cap_cp: i32) -> i32 {
+    let cap = mate_cap_cp.max(0);
+    let before = score_for_side_to_move(before).clamp(-cap, cap);
+    let after = (-score_for_side_to_move(after)).clamp(-cap, cap);
+    (before - after).max(0)
+}
+
+/// 損失と「最善手を指したか」からラベルを決める
+pub fn label_for_loss(loss_cp: i32, played_best: bool, th: &ClassifyThresholds) -> MoveLabel {
+    if played_best {
+        MoveLabel::Best
+    } else if loss_cp >= th.blunder_cp {
+        MoveLabel::Blunder
+    } else if loss_cp >= th.mistake_cp {
+        MoveLabel::Mistake
+    } else if loss_cp >= th.inaccuracy_cp {
+        MoveLabel::Inaccuracy
+    } else {
+        MoveLabel::Good
+    }
+}
+
+/// 1 手を分類する。`before` は指す前の局面、`after` は指した後の局面の解析結果。
+///
+/// どちらかに最善候補の評価が無ければ None。
+pub fn classify_move(
+    before: &AnalysisResult,
+    after: &AnalysisResult,
+    played_move: &str,
+    th: &ClassifyThresholds,
+) -> Option<(i32, MoveLabel)> {
+    let best_before = before.candidates.iter().find(|c| c.rank == 1)?;
+    let best_after = after.candidates.iter().find(|c| c.rank == 1)?;
+    let loss = evaluation_loss(
+        best_before.evaluation.as_ref()?,
+        best_after.evaluation.as_ref()?,
+        th.mate_cap_cp,
+    );
+    let played_best = best_before.first_move.as_deref() == Some(played_move);
+    Some((loss, label_for_loss(loss, played_best, th)))
+}
+
+/// 一括解析の結果 (ply 0 から順に並んだもの) の各手を分類し、対局者ごとに集計する。
+///
+/// 前後どちらかの局面に評価が無い手は飛ばす。
+pub fn classify_game(plies: &[PlyAnalysis], th: &ClassifyThresholds) -> GameClassification {
+    let mut moves = Vec::new();
+    let mut black = PlayerSummary::default();
+    let mut white = PlayerSummary::default();
+    let mut loss_sum = [0i64; 2];
+
+    for pair in plies.windows(2) {
+        let (prev, cur) = (&pair[0], &pair[1]);
+        let (Some(before), Some(after)) = (&prev.evaluation, &cur.evaluation) else {
+            continue;
+        };
+
+        let loss = evaluation_loss(before, after, th.mate_cap_cp);
+        let played_best = cur.last_move.is_some() && cur.last_move == prev.best_move;
+        let label = label_for_loss(loss, played_best, th);
+
+        let (summary, sum) = if prev.black_to_move {
+            (&mut black, &mut loss_sum[0])
+        } else {
+            (&mut white, &mut loss_sum[1])
+        };
+        summary.moves += 1;
+        *sum += loss as i64;
+        match label {
+            MoveLabel::Best => summary.best += 1,
+            MoveLabel::Good => summary.good += 1,
+            MoveLabel::Inaccuracy => summary.inaccuracy += 1,
+            MoveLabel::Mistake => summary.mistake += 1,
+            MoveLabel::Blunder => summary.blunder += 1,
+        }
+
+        moves.push(MoveClassification {
+            ply: cur.ply,
+            black: prev.black_to_move,
+            played_move: cur.last_move.clone(),
+            best_move: prev.best_move.clone(),
+            loss_cp: loss,
+            label,
+        });
+    }
+
+    for (summary, sum) in [(&mut black, loss_sum[0]), (&mut white, loss_sum[1])] {
+        if summary.moves > 0 {
+            summary.average_loss_cp = sum as f64 / summary.moves as f64;
+        }
+    }
+
+    GameClassification {
+        moves,
+        black,
+        white,
+    }
+}
@@ -1,5 +1,9 @@
+pub mod analysis_cache; // 局面ごとの解析キャッシュ
 pub mod analyzer; // 解析処理
+pub mod annotate; // 解析結果の棋譜への書き戻し
+pub mod batch; // 棋譜一括解析
 pub mod bridge;
+pub mod classify; // 指し手の分類
 pub mod manager; // エンジン管理
 pub mod protocol; // USIプロトコル // Tauriコマンドブリッジ
 pub mod types;
@@ -106,6 +106,8 @@ pub enum EngineError {
     AnalysisFailed(String),
     #[error("Already listening: {0}")]
     AlreadyListening(String),
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -201,3 +203,117 @@ pub struct AnalysisCandidate {
     /// time は info time を受けるたび更新される
     pub time_ms: Option<u64>,
 }
+
+/// 1 局面あたりの解析予算。指定したもののうち最初に達したところで打ち切る
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AnalysisBudget {
+    pub time_ms: Option<u64>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+}
+
+/// 棋譜一本ぶんの一括解析の入力
+#[derive(Deserialize)]
+pub struct BatchAnalysisInput {
+    pub jkf: shogi_kifu_converter_obsshogi::jkf::JsonKifuFormat,
+
+    /// 解析する系列。空なら本譜 (CursorLite の fork_pointers と同じ形)
+    #[serde(default)]
+    pub fork_pointers: Vec<crate::search::types::ForkPointer>,
+
+    pub budget: AnalysisBudget,
+}
+
+/// 一括解析の 1 局面ぶんの結果 (ply 手目を指した後の局面)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlyAnalysis {
+    /// 0 = 開始局面
+    pub ply: u32,
+
+    /// この局面の手番が先手か
+    pub black_to_move: bool,
+
+    /// この局面に至った手 (USI)。開始局面は None
+    pub last_move: Option<String>,
+
+    pub best_move: Option<String>,
+    pub pv: Vec<String>,
+
+    /// エンジンの評価 (手番側から見た値)
+    pub evaluation: Option<Evaluation>,
+
+    /// 評価値グラフ用の先手から見た値。詰みは ±MATE_SCORE に丸める
+    pub score_black: Option<i32>,
+
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+}
+
+/// 指し手の評価ラベル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveLabel {
+    /// エンジンの最善手と同じ
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+/// 評価値の損失 (cp) からラベルを決める閾値。損失がその値以上でそのラベルになる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassifyThresholds {
+    pub inaccuracy_cp: i32,
+    pub mistake_cp: i32,
+    pub blunder_cp: i32,
+
+    /// 詰みを含む評価はこの値で頭打ちにしてから差を取る
+    /// (詰みが 5 手 → 7 手に延びただけの手を悪手にしないため)
+    pub mate_cap_cp: i32,
+}
+
+impl Default for ClassifyThresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy_cp: 300,
+            mistake_cp: 600,
+            blunder_cp: 1000,
+            mate_cap_cp: 3000,
+        }
+    }
+}
+
+/// 1 手ぶんの分類結果
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveClassification {
+    /// 何手目の指し手か (1 始まり)
+    pub ply: u32,
+    /// 指したのが先手か
+    pub black: bool,
+    pub played_move: Option<String>,
+    pub best_move: Option<String>,
+    /// 指した側から見た評価値の損失 (0 以上)
+    pub loss_cp: i32,
+    pub label: MoveLabel,
+}
+
+/// 対局者ごとの集計
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PlayerSummary {
+    pub moves: u32,
+    pub best: u32,
+    pub good: u32,
+    pub inaccuracy: u32,
+    pub mistake: u32,
+    pub blunder: u32,
+    /// 平均損失 (cp)。分類した手が無ければ 0
+    pub average_loss_cp: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameClassification {
+    pub moves: Vec<MoveClassification>,
+    pub black: PlayerSummary,
+    pub white: PlayerSummary,
+}
@@ -1,13 +1,20 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use tauri::command;
+use tauri::{command, AppHandle};
 
+use crate::config_dir::load_scan_options;
 use crate::file_system::error::{FsError, FsErrorCode};
+use crate::search::fs_scan::ScanOptions;
 
 use super::types::FileTreeNode;
 use super::utils::{generate_id, get_file_extension, is_kifu_file};
 
-fn build_file_tree_recursive(path: &Path) -> Result<FileTreeNode, FsError> {
+/// `root` 配下のツリーを作る。走査ルール (`opts`) で index の対象外になるものは出さない
+fn build_file_tree_recursive(
+    root: &Path,
+    path: &Path,
+    opts: &ScanOptions,
+) -> Result<FileTreeNode, FsError> {
     let metadata = fs::metadata(path).map_err(FsError::from)?;
     let is_dir = metadata.is_dir();
 
@@ -49,9 +56,22 @@ fn build_file_tree_recursive(path: &Path) -> Result<FileTreeNode, FsError> {
             };
             let child_path = entry.path();
 
-            // ディレクトリまたは棋譜ファイルのみを含める
-            if child_path.is_dir() || is_kifu_file(&child_path) {
-                match build_file_tree_recursive(&child_path) {
+            // index の走査と同じく、follow_links でなければリンクは辿らない
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if (is_link && !opts.rules.follow_links) || opts.excludes(root, &child_path) {
+                continue;
+            }
+
+            // ディレクトリまたは対象の棋譜ファイルのみを含める
+            let included = if child_path.is_dir() {
+                true
+            } else {
+                is_kifu_file(&child_path)
+                    && opts.includes_file(root, &child_path)
+                    && fs::metadata(&child_path).is_ok_and(|m| opts.allows_size(m.len()))
+            };
+            if included {
+                match build_file_tree_recursive(root, &child_path, opts) {
                     Ok(child_node) => children.push(child_node),
                     Err(_) => continue, // エラーは無視して続行
                 }
@@ -74,7 +94,7 @@ fn build_file_tree_recursive(path: &Path) -> Result<FileTreeNode, FsError> {
 }
 
 #[command]
-pub fn get_file_tree(root_dir: String) -> Result<FileTreeNode, FsError> {
+pub fn get_file_tree(app: AppHandle, root_dir: String) -> Result<FileTreeNode, FsError> {
     let root_path = PathBuf::from(&root_dir);
 
     if !root_path.exists() {
@@ -95,5 +115,6 @@ pub fn get_file_tree(root_dir: String) -> Result<FileTreeNode, FsError> {
 
     // 絶対パスに正規化
     let canonical_path = root_path.canonicalize().map_err(FsError::from)?;
-    build_file_tree_recursive(&canonical_path)
+    let opts = load_scan_options(&app).map_err(|e| FsError::new(FsErrorCode::InvalidPath, e))?;
+    build_file_tree_recursive(&canonical_path, &canonical_path, &opts)
 }
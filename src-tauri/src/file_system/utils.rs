@@ -52,39 +52,52 @@ pub fn validate_basename(name: &str) -> Result<(), FsError> {
     Ok(())
 }
 
-/// AppConfig.root_dir を取得（未設定なら None）
-fn load_root_dir<R: Runtime>(app: &AppHandle<R>) -> Result<Option<PathBuf>, FsError> {
+/// AppConfig.root_dir と root_dirs を取得（どちらも未設定なら空）
+fn load_root_dirs<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<PathBuf>, FsError> {
     let cfg_path = app
         .path()
         .app_config_dir()
         .map_err(|e| FsError::new(FsErrorCode::InvalidPath, e.to_string()))?
         .join("app.json");
     if !cfg_path.exists() {
-        return Ok(None);
+        return Ok(Vec::new());
     }
     let data = fs::read_to_string(&cfg_path).map_err(FsError::from)?;
     #[derive(serde::Deserialize)]
     struct Cfg {
         root_dir: Option<String>,
+        #[serde(default)]
+        root_dirs: Vec<String>,
     }
     let cfg: Cfg = serde_json::from_str(&data)
         .map_err(|e| FsError::new(FsErrorCode::InvalidPath, e.to_string()))?;
-    Ok(cfg.root_dir.map(PathBuf::from))
+    Ok(cfg
+        .root_dir
+        .into_iter()
+        .chain(cfg.root_dirs)
+        .map(PathBuf::from)
+        .collect())
 }
 
-/// 与えられた target が AppConfig.root_dir 配下にあるか検証する。
+/// 与えられた target が AppConfig.root_dir / root_dirs のいずれかの配下にあるか検証する。
 /// target が存在しない場合は、親ディレクトリを canonicalize して合成する。
-/// root_dir が未設定なら検証をスキップする（後方互換）。
+/// root が 1 つも設定されていなければ検証をスキップする（後方互換）。
 pub fn validate_under_root<R: Runtime>(app: &AppHandle<R>, target: &Path) -> Result<(), FsError> {
-    let Some(root) = load_root_dir(app)? else {
+    let roots = load_root_dirs(app)?;
+    if roots.is_empty() {
         return Ok(());
-    };
-    let canonical_root = fs::canonicalize(&root).map_err(|e| {
-        FsError::new(
-            FsErrorCode::InvalidPath,
-            format!("root_dir canonicalize: {e}"),
-        )
-    })?;
+    }
+    // 消えた・外付けで今は無いフォルダは root として扱わない
+    let canonical_roots = roots
+        .iter()
+        .filter_map(|root| match fs::canonicalize(root) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                log::debug!("[validate_under_root] skip root {}: {e}", root.display());
+                None
+            }
+        })
+        .collect::<Vec<_>>();
 
     let canonical_target = if target.exists() {
         fs::canonicalize(target).map_err(FsError::from)?
@@ -99,7 +112,10 @@ pub fn validate_under_root<R: Runtime>(app: &AppHandle<R>, target: &Path) -> Res
         parent_canon.join(name)
     };
 
-    if !canonical_target.starts_with(&canonical_root) {
+    if !canonical_roots
+        .iter()
+        .any(|root| canonical_target.starts_with(root))
+    {
         return Err(
             FsError::new(FsErrorCode::InvalidPath, "path is outside project root")
                 .with_path(target.to_string_lossy().to_string()),
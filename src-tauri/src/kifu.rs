@@ -6,6 +6,7 @@ use shogi_kifu_converter_obsshogi::{
 use std::path::Path;
 use tauri::{command, AppHandle, Runtime};
 
+use crate::engine::{annotate::merge_analysis_into_jkf, types::PlyAnalysis};
 use crate::file_system::utils::{atomic_write, is_kifu_file, validate_under_root};
 use crate::file_system::{is_initial_gote, patch_gote_start};
 
@@ -24,6 +25,18 @@ pub struct WriteKifuResponse {
     pub error: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct WriteAnalysisRequest {
+    pub jkf: JsonKifuFormat,
+    pub file_path: String,
+    pub format: String,
+    /// 解析した系列。空なら本譜
+    #[serde(default)]
+    pub fork_pointers: Vec<crate::search::types::ForkPointer>,
+    /// 一括解析の結果 (ply 0 から)
+    pub plies: Vec<PlyAnalysis>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConvertKifuRequest {
     pub jkf: JsonKifuFormat,
@@ -119,6 +132,40 @@ pub async fn write_kifu_to_file<R: Runtime>(
     }
 }
 
+/// 解析結果をコメントと分岐として棋譜に書き込み、`write_kifu_to_file` で保存する
+#[command]
+pub async fn write_analysis_to_kifu<R: Runtime>(
+    app: AppHandle<R>,
+    request: WriteAnalysisRequest,
+) -> WriteKifuResponse {
+    let mut jkf = request.jkf;
+
+    let merged = merge_analysis_into_jkf(&mut jkf, &request.fork_pointers, &request.plies)
+        .map_err(|e| format!("解析結果の書き込みエラー: {e}"))
+        .and_then(|_| {
+            jkf.normalize()
+                .map_err(|e| format!("正規化エラー: {:?}", e))
+        });
+    if let Err(e) = merged {
+        return WriteKifuResponse {
+            success: false,
+            file_path: None,
+            normalized_jkf: None,
+            error: Some(e),
+        };
+    }
+
+    write_kifu_to_file(
+        app,
+        WriteKifuRequest {
+            jkf,
+            file_path: request.file_path,
+            format: request.format,
+        },
+    )
+    .await
+}
+
 #[command]
 pub async fn convert_jkf_to_format(request: ConvertKifuRequest) -> ConvertKifuResponse {
     let mut jkf = request.jkf;
@@ -11,9 +11,10 @@ pub use crate::engine::bridge::AppState;
 pub use ai_library::{ensure_engines_dir, scan_ai_root};
 pub use config_dir::{load_config, save_config};
 pub use engine::bridge::{
-    analyze_with_depth, analyze_with_time, apply_engine_settings, get_analysis_result,
-    get_analysis_status, get_engine_info, get_engine_settings, get_last_result, initialize_engine,
-    set_position, shutdown_engine, start_infinite_analysis, stop_analysis,
+    analyze_with_depth, analyze_with_time, apply_engine_settings, cancel_batch_analysis,
+    classify_analysis, get_analysis_result, get_analysis_status, get_cached_analysis,
+    get_engine_info, get_engine_settings, get_last_result, initialize_engine, list_engines,
+    set_position, shutdown_engine, start_batch_analysis, start_infinite_analysis, stop_analysis,
 };
 pub use engine_presets::{load_presets, save_presets};
 pub use file_system::{
@@ -21,8 +22,13 @@ pub use file_system::{
     import_kifu_file, mv_directory, mv_kifu_file, read_file, rename_directory, rename_kifu_file,
     save_kifu_file,
 };
-pub use kifu::{convert_jkf_to_format, normalize_jkf, write_kifu_to_file};
-pub use search::api::{cancel_search, open_project, search_position, SearchState};
+pub use kifu::{convert_jkf_to_format, normalize_jkf, write_analysis_to_kifu, write_kifu_to_file};
+pub use search::api::{
+    add_project_root, build_opening_tree, cancel_search, export_search_results,
+    find_transpositions, get_index_stats, get_move_stats, list_project_roots, open_project,
+    remove_project_root, search_pattern, search_position, search_sequence, validate_kifu,
+    SearchState,
+};
 pub use search::index_store::IndexStore;
 pub use study_positions::{load_study_positions, save_study_positions};
 
@@ -31,8 +37,7 @@ use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let store = Arc::new(IndexStore::new());
-    let search_state = SearchState::new(store);
+    let search_state = SearchState::new();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_updater::Builder::new().build())
@@ -63,6 +68,7 @@ pub fn run() {
             import_kifu_file,
             read_file,
             write_kifu_to_file,
+            write_analysis_to_kifu,
             mv_directory,
             ensure_engines_dir,
             scan_ai_root,
@@ -73,10 +79,15 @@ pub fn run() {
             normalize_jkf,
             initialize_engine,
             shutdown_engine,
+            list_engines,
             set_position,
             start_infinite_analysis,
             analyze_with_time,
             analyze_with_depth,
+            start_batch_analysis,
+            cancel_batch_analysis,
+            classify_analysis,
+            get_cached_analysis,
             stop_analysis,
             get_analysis_result,
             get_last_result,
@@ -85,8 +96,19 @@ pub fn run() {
             get_analysis_status,
             get_engine_info,
             open_project,
+            add_project_root,
+            remove_project_root,
+            list_project_roots,
+            get_index_stats,
             search_position,
+            search_pattern,
+            search_sequence,
             cancel_search,
+            get_move_stats,
+            export_search_results,
+            build_opening_tree,
+            find_transpositions,
+            validate_kifu,
             load_study_positions,
             save_study_positions,
         ])
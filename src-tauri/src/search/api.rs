@@ -9,44 +9,63 @@ use tauri::{AppHandle, Emitter, State};
 use tokio::{sync::Semaphore, task::JoinSet};
 
 use crate::search::{
-    fs_scan::snapshot_from_records,
+    fs_scan::{fill_hashes, snapshot_from_records},
     node_table::NodeTable,
     position_key::PositionKey,
     project_manager::ProjectManager,
-    query_service::QueryService,
-    types::{CancelSearchInput, Occurrence, SearchPositionInput, SearchPositionOutput},
+    query_service::{QueryService, SearchHits},
+    roots::{ProjectRoot, RootSet},
+    types::{
+        CancelSearchInput, ExportResultsInput, ExportResultsOutput, IndexStatsOutput,
+        MoveStatsInput, MoveStatsOutput, Occurrence, OpeningTreeInput, OpeningTreeOutput,
+        ProjectRootInfo, RemoveProjectRootInput, SearchPatternInput, SearchPositionInput,
+        SearchPositionOutput, SearchSequenceInput, TranspositionsInput, TranspositionsOutput,
+        ValidateKifuInput, ValidateKifuOutput,
+    },
 };
 
 use super::{
-    fs_scan::{scan_kifu_files, ScanOptions},
-    index_builder::{bucketize_entries, build_index_for_jkf, BuildPolicy},
-    index_store::{IndexState as StoreIndexState, IndexStore},
+    export::export_hits,
+    fs_scan::scan_kifu_files,
+    index_builder::{
+        bucketize_board_entries, bucketize_entries, build_index_for_jkf, BuildPolicy, BuildWarn,
+    },
+    index_store::{
+        BoardBucketEntries, FileBucketEntries, IndexState as StoreIndexState, IndexStore,
+    },
     kifu_reader::read_to_jkf,
+    move_stats::collect_move_stats,
+    opening_tree,
+    stats::collect_index_stats,
+    transposition,
     types::{
-        FileEntry, FileId, IndexProgressPayload, IndexState, IndexStatePayload, IndexWarnPayload,
-        OpenProjectInput, OpenProjectOutput, EVT_INDEX_PROGRESS, EVT_INDEX_STATE, EVT_INDEX_WARN,
+        FileEntry, FileId, GameMeta, IndexProgressPayload, IndexState, IndexStatePayload,
+        IndexWarnPayload, OpenProjectInput, OpenProjectOutput, EVT_INDEX_PROGRESS, EVT_INDEX_STATE,
+        EVT_INDEX_WARN,
     },
+    validate,
 };
 
 /// search モジュールの Tauri State
 ///
 /// - QueryService が emit するために AppHandle を保持する
-/// - IndexStore は QueryService が参照する
+/// - root ごとの IndexStore / ProjectManager は RootSet が持ち、QueryService が束ねて引く
 pub struct SearchState {
-    pub store: Arc<IndexStore>,
+    pub roots: Arc<RootSet>,
     pub query: Arc<QueryService>,
-    pub project: Arc<ProjectManager>,
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SearchState {
-    pub fn new(store: Arc<IndexStore>) -> Self {
-        let query = Arc::new(QueryService::new(store.clone()));
-        let project = Arc::new(ProjectManager::new());
-        Self {
-            store,
-            query,
-            project,
-        }
+    pub fn new() -> Self {
+        let roots = Arc::new(RootSet::new());
+        let query = Arc::new(QueryService::new(roots.clone()));
+        Self { roots, query }
     }
 
     /// setup で AppHandle を流し込む用
@@ -68,6 +87,62 @@ pub async fn search_position(
     state.query.clone().start_search(input).await
 }
 
+/// パターン (マス目→駒 + 持ち駒) 検索コマンド。
+///
+/// `search_position` と同じく `request_id` を即 return し、hit は `EVT_SEARCH_*`
+/// で push される。キャンセルも `cancel_search` を使う。
+///
+/// index には局面のハッシュしか無いので、パターン検索は index 済みの全棋譜を
+/// ディスクから読み直して parse する (局面検索よりずっと重い)。
+#[tauri::command]
+pub async fn search_pattern(
+    state: State<'_, SearchState>,
+    input: SearchPatternInput,
+) -> Result<SearchPositionOutput, String> {
+    log::debug!("[cmd] search_pattern invoked");
+    state.query.clone().start_pattern_search(input).await
+}
+
+/// 局面列検索コマンド。sfens の局面をこの順に同じ系列上で通る棋譜を探し、
+/// 最後の局面の出現箇所を `EVT_SEARCH_*` で push する。
+#[tauri::command]
+pub async fn search_sequence(
+    state: State<'_, SearchState>,
+    input: SearchSequenceInput,
+) -> Result<SearchPositionOutput, String> {
+    log::debug!("[cmd] search_sequence invoked n={}", input.sfens.len());
+    state.query.clone().start_sequence_search(input).await
+}
+
+/// 検索結果の棋譜をまとめて書き出す (フォルダへのコピー / シンボリックリンク /
+/// マニフェスト JSON)。書き出し先は root_dir 配下に限る。
+#[tauri::command]
+pub async fn export_search_results(
+    app: AppHandle,
+    state: State<'_, SearchState>,
+    input: ExportResultsInput,
+) -> Result<ExportResultsOutput, String> {
+    log::debug!("[cmd] export_search_results mode={:?}", input.mode);
+    let hits = match (input.hits, input.request_id) {
+        (Some(hits), _) => SearchHits::Hits(Arc::new(hits)),
+        (None, Some(rid)) => state
+            .query
+            .recent_hits(rid)
+            .ok_or_else(|| format!("no retained results for request {rid}"))?,
+        (None, None) => return Err("either hits or requestId is required".to_string()),
+    };
+    let snaps = state.roots.snapshots();
+    let dest = PathBuf::from(input.dest);
+    let mode = input.mode;
+
+    tokio::task::spawn_blocking(move || {
+        let hits = hits.into_hits(&snaps);
+        export_hits(&app, &snaps, &hits, &dest, mode)
+    })
+    .await
+    .map_err(|e| format!("export task join error: {e}"))?
+}
+
 /// 進行中の検索をキャンセル。フロントの cleanup で呼ぶ。
 #[tauri::command]
 pub async fn cancel_search(
@@ -79,19 +154,216 @@ pub async fn cancel_search(
     Ok(())
 }
 
+/// 局面の次の一手と勝敗の集計 (定跡エクスプローラ用)。
+///
+/// 出現した棋譜を読み直すので、件数に応じて時間がかかる。blocking pool で実行する。
+#[tauri::command]
+pub async fn get_move_stats(
+    state: State<'_, SearchState>,
+    input: MoveStatsInput,
+) -> Result<MoveStatsOutput, String> {
+    log::debug!("[cmd] get_move_stats invoked");
+    let snaps = state.roots.snapshots();
+    tokio::task::spawn_blocking(move || collect_move_stats(&snaps, &input))
+        .await
+        .map_err(|e| format!("move stats task join error: {e}"))?
+        .map_err(|e| e.to_string())
+}
+
+/// フォルダ配下の棋譜を 1 つの分岐つき棋譜 (定跡木) にまとめる。
+#[tauri::command]
+pub async fn build_opening_tree(
+    app: AppHandle,
+    input: OpeningTreeInput,
+) -> Result<OpeningTreeOutput, String> {
+    log::debug!(
+        "[cmd] build_opening_tree dir={} max_plies={}",
+        input.dir,
+        input.max_plies
+    );
+    let opts = crate::config_dir::load_scan_options(&app)?;
+    tokio::task::spawn_blocking(move || opening_tree::build_opening_tree(&input, &opts))
+        .await
+        .map_err(|e| format!("opening tree task join error: {e}"))?
+}
+
+/// 1 つの棋譜の分岐同士の手順違い (同一局面への合流) を列挙する。
+#[tauri::command]
+pub async fn find_transpositions(
+    input: TranspositionsInput,
+) -> Result<TranspositionsOutput, String> {
+    log::debug!("[cmd] find_transpositions invoked");
+    tokio::task::spawn_blocking(move || transposition::find_transpositions(&input.jkf))
+        .await
+        .map_err(|e| format!("transposition task join error: {e}"))?
+        .map(|groups| TranspositionsOutput { groups })
+        .map_err(|e| e.to_string())
+}
+
+/// 棋譜 (またはフォルダ配下の棋譜) の反則手・適用できない手を列挙する。
+#[tauri::command]
+pub async fn validate_kifu(
+    app: AppHandle,
+    input: ValidateKifuInput,
+) -> Result<ValidateKifuOutput, String> {
+    log::debug!("[cmd] validate_kifu invoked: path={}", input.path);
+    let opts = crate::config_dir::load_scan_options(&app)?;
+    tokio::task::spawn_blocking(move || validate::validate_kifu(&input, &opts))
+        .await
+        .map_err(|e| format!("validate task join error: {e}"))?
+}
+
+/// 棋譜フォルダを開く。他に開いている root は閉じて、このフォルダだけにする。
 #[tauri::command]
 pub async fn open_project(
     app: AppHandle,
-    state: State<'_, crate::search::api::SearchState>,
+    state: State<'_, SearchState>,
     input: OpenProjectInput,
 ) -> Result<OpenProjectOutput, String> {
-    let store = state.store.clone();
-    let project = state.project.clone();
+    let root_dir = PathBuf::from(input.root_dir);
+    for r in state.roots.list() {
+        if !r.is_dir(&root_dir) {
+            close_root(&state.roots, &r).await;
+            // 閉じた root の下をファイル操作の対象から外す (remove_project_root と同じ)
+            crate::config_dir::remove_root_dir(&app, &r.root_dir)?;
+        }
+    }
+    let (root, _) = state.roots.add(&root_dir);
+    let res = open_root(app, root.clone()).await;
+    if res.is_err() {
+        close_root(&state.roots, &root).await;
+    }
+    res
+}
 
+/// 開いている root はそのままに、棋譜フォルダを追加で開く。既に開いていれば何もしない。
+#[tauri::command]
+pub async fn add_project_root(
+    app: AppHandle,
+    state: State<'_, SearchState>,
+    input: OpenProjectInput,
+) -> Result<OpenProjectOutput, String> {
     let root_dir = PathBuf::from(input.root_dir);
+    let (root, added) = state.roots.add(&root_dir);
+    if !added {
+        log::debug!("[add_project_root] already open: {}", root_dir.display());
+        return Ok(OpenProjectOutput {
+            root_id: root.id,
+            total_files: root.store.snapshot().file_table.len() as u32,
+        });
+    }
+    // 再起動後もファイル操作の対象になるよう、開く前に記録しておく
+    if let Err(e) = crate::config_dir::add_root_dir(&app, &root.root_dir) {
+        state.roots.remove(root.id);
+        return Err(e);
+    }
+    let res = open_root(app.clone(), root.clone()).await;
+    if res.is_err() {
+        close_root(&state.roots, &root).await;
+        if let Err(e) = crate::config_dir::remove_root_dir(&app, &root.root_dir) {
+            log::warn!("[add_project_root] forget root FAILED: {e}");
+        }
+    }
+    res
+}
+
+/// root を閉じる。他の root の index や、この root の cache には触らない。
+#[tauri::command]
+pub async fn remove_project_root(
+    app: AppHandle,
+    state: State<'_, SearchState>,
+    input: RemoveProjectRootInput,
+) -> Result<(), String> {
+    let root = state
+        .roots
+        .get(input.root_id)
+        .ok_or_else(|| format!("unknown root: {}", input.root_id))?;
+    close_root(&state.roots, &root).await;
+    crate::config_dir::remove_root_dir(&app, &root.root_dir)
+}
+
+/// 開いている root の一覧 (追加順)
+#[tauri::command]
+pub async fn list_project_roots(
+    state: State<'_, SearchState>,
+) -> Result<Vec<ProjectRootInfo>, String> {
+    Ok(state
+        .roots
+        .list()
+        .iter()
+        .map(|r| {
+            let snap = r.store.snapshot();
+            ProjectRootInfo {
+                root_id: r.id,
+                root_dir: r.root_dir.to_string_lossy().to_string(),
+                state: snap.state.into(),
+                total_files: snap
+                    .file_table
+                    .iter_all()
+                    .filter(|(_, e)| !e.deleted)
+                    .count() as u32,
+            }
+        })
+        .collect())
+}
+
+/// 開いている root ごとの index の統計 (bucket ごとのセグメント・失敗したファイル・cache)
+#[tauri::command]
+pub async fn get_index_stats(
+    app: AppHandle,
+    state: State<'_, SearchState>,
+) -> Result<IndexStatsOutput, String> {
+    log::debug!("[cmd] get_index_stats invoked");
+    let roots = state.roots.list();
+    tokio::task::spawn_blocking(move || {
+        let roots = roots
+            .iter()
+            .map(|r| {
+                let cache = crate::search::index_cache::cache_stats(&app, &r.root_dir)
+                    .unwrap_or_else(|e| {
+                        log::warn!("[get_index_stats] cache stats FAILED: {e}");
+                        None
+                    });
+                collect_index_stats(r.id, &r.root_dir, &r.store.snapshot(), cache)
+            })
+            .collect();
+        IndexStatsOutput { roots }
+    })
+    .await
+    .map_err(|e| format!("index stats task join error: {e}"))
+}
+
+async fn close_root(roots: &RootSet, root: &ProjectRoot) {
+    log::info!("[close_root] root_dir={}", root.root_dir.display());
+    root.project.stop().await;
+    roots.remove(root.id);
+}
+
+/// 走査ルールが変わった後に、開いている root を同じ RootId のまま開き直す。
+///
+/// 保存済みの cache は走査ルールの指紋が合わなくなるので full build になり、
+/// watcher も新しいルールで張り直す。
+pub async fn reopen_roots(app: &AppHandle, roots: &RootSet) {
+    for root in roots.list() {
+        log::info!("[reopen_roots] root_dir={}", root.root_dir.display());
+        root.project.stop().await;
+        if let Err(e) = open_root(app.clone(), root.clone()).await {
+            log::warn!("[reopen_roots] open FAILED: {e}");
+        }
+    }
+}
+
+/// root 1 つ分を開く。cache から復元できなければ full build を裏で始める
+async fn open_root(app: AppHandle, root: Arc<ProjectRoot>) -> Result<OpenProjectOutput, String> {
+    let store = root.store.clone();
+    let project = root.project.clone();
+    let root_dir = root.root_dir.clone();
 
     log::info!("[open_project] BEGIN root_dir={}", root_dir.display());
 
+    let scan_opts = Arc::new(crate::config_dir::load_scan_options(&app)?);
+    project.set_scan_options(scan_opts.clone()).await;
+
     // 0) Restoring state (UIに「復元中」を見せる)
     store.start_restoring();
     let _ = app.emit(
@@ -105,7 +377,7 @@ pub async fn open_project(
     );
 
     // 1) try restore (cache)
-    match crate::search::index_cache::try_restore(&app, &root_dir) {
+    match crate::search::index_cache::try_restore(&app, &root_dir, &scan_opts.fingerprint()) {
         Ok(mut restored) => {
             // 念のため（decode側でroot_dirを入れてるなら不要だが安全）
             restored.scan.root_dir = root_dir.clone();
@@ -127,6 +399,7 @@ pub async fn open_project(
                 restored.file_table,
                 restored.node_tables,
                 restored.buckets,
+                restored.board_buckets,
             );
 
             project
@@ -183,7 +456,10 @@ pub async fn open_project(
             });
 
             log::info!("[open_project] END (restore path) total_files={total_files}");
-            return Ok(OpenProjectOutput { total_files });
+            return Ok(OpenProjectOutput {
+                root_id: root.id,
+                total_files,
+            });
         }
         Err(e) => {
             log::warn!("[open_project] RESTORE FAILED: {e} -> fallback full build");
@@ -193,7 +469,7 @@ pub async fn open_project(
     // 2) restore 失敗 → full build
     store.start_full_build();
 
-    let records = scan_kifu_files(&root_dir, &ScanOptions::default()).map_err(|e| e.to_string())?;
+    let records = scan_kifu_files(&root_dir, &scan_opts).map_err(|e| e.to_string())?;
     let total_files = records.len() as u32;
 
     log::info!(
@@ -216,12 +492,15 @@ pub async fn open_project(
         store,
         project,
         root_dir,
+        scan_opts.fingerprint(),
         records,
-        total_files,
     ));
 
     log::info!("[open_project] END (full build path) total_files={total_files}");
-    Ok(OpenProjectOutput { total_files })
+    Ok(OpenProjectOutput {
+        root_id: root.id,
+        total_files,
+    })
 }
 
 async fn build_full_index_task(
@@ -229,21 +508,45 @@ async fn build_full_index_task(
     store: Arc<IndexStore>,
     project: Arc<ProjectManager>,
     root_dir: PathBuf,
+    scan_rules: [u8; 32],
     mut records: Vec<super::fs_scan::FileRecord>,
-    total_files: u32,
 ) {
+    let total_files = records.len() as u32;
     type BucketEntries = [Vec<(PositionKey, Occurrence)>; 256];
     type BuildItem = (
         FileId,
         u32,
         String,
         BucketEntries,
+        BoardBucketEntries,
+        Arc<NodeTable>,
+        GameMeta,
+        Vec<IndexWarnPayload>,
+        // 失敗の理由 (成功なら None)
+        Option<String>,
+    );
+    type BuildOk = (
+        BucketEntries,
+        BoardBucketEntries,
         Arc<NodeTable>,
-        Vec<String>,
-        bool,
+        GameMeta,
+        Vec<BuildWarn>,
     );
 
     records.sort_by(|a, b| a.path.cmp(&b.path));
+    // 差分反映で内容比較・rename 検出に使う hash を先に埋めておく
+    let records = match tokio::task::spawn_blocking(move || {
+        fill_hashes(&mut records);
+        records
+    })
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("[open_project] hash join error: {e}");
+            return;
+        }
+    };
     let scan = snapshot_from_records(&root_dir, records.clone());
 
     let mut path_to_id: HashMap<String, FileId> = HashMap::with_capacity(records.len());
@@ -266,8 +569,7 @@ async fn build_full_index_task(
     const COMMIT_BATCH: usize = 64;
     const EMIT_INTERVAL: Duration = Duration::from_millis(100);
 
-    let mut batch: Vec<(FileEntry, Arc<NodeTable>, BucketEntries)> =
-        Vec::with_capacity(COMMIT_BATCH);
+    let mut batch: Vec<FileBucketEntries> = Vec::with_capacity(COMMIT_BATCH);
 
     let mut done_files: u32 = 0;
     let mut indexed_ok: u32 = 0;
@@ -290,42 +592,78 @@ async fn build_full_index_task(
         join.spawn(async move {
             let _permit = permit;
 
-            let res = tokio::task::spawn_blocking(
-                move || -> Result<(BucketEntries, Arc<NodeTable>, Vec<String>), String> {
-                    let jkf = read_to_jkf(&rec2).map_err(|e| e.to_string())?;
-                    let built = build_index_for_jkf(file_id, gen, &jkf, BuildPolicy::Loose)
-                        .map_err(|e| e.to_string())?;
-
-                    let by_bucket: BucketEntries = bucketize_entries(built.entries);
-
-                    let warns = built
-                        .warns
-                        .into_iter()
-                        .map(|w| format!("{:?}: {}", w.cursor, w.message))
-                        .collect::<Vec<_>>();
-
-                    Ok((by_bucket, built.node_table, warns))
-                },
-            )
+            let res = tokio::task::spawn_blocking(move || -> Result<BuildOk, String> {
+                let jkf = read_to_jkf(&rec2).map_err(|e| e.to_string())?;
+                let built = build_index_for_jkf(file_id, gen, &jkf, BuildPolicy::Loose)
+                    .map_err(|e| e.to_string())?;
+
+                let by_bucket: BucketEntries = bucketize_entries(built.entries);
+                let board_by_bucket = bucketize_board_entries(built.board_entries);
+
+                Ok((
+                    by_bucket,
+                    board_by_bucket,
+                    built.node_table,
+                    built.meta,
+                    built.warns,
+                ))
+            })
             .await;
 
             let empty: BucketEntries = std::array::from_fn(|_| Vec::new());
+            let empty_board: BoardBucketEntries = std::array::from_fn(|_| Vec::new());
             let empty_nt = Arc::new(NodeTable::empty());
 
             let out: BuildItem = match res {
-                Ok(Ok((by_bucket, node_table, warns))) => {
-                    (file_id, gen, path_str, by_bucket, node_table, warns, true)
-                }
-                Ok(Err(e)) => (file_id, gen, path_str, empty, empty_nt, vec![e], false),
-                Err(e) => (
+                Ok(Ok((by_bucket, board_by_bucket, node_table, meta, warns))) => (
                     file_id,
                     gen,
-                    path_str,
+                    path_str.clone(),
+                    by_bucket,
+                    board_by_bucket,
+                    node_table,
+                    meta,
+                    warns
+                        .into_iter()
+                        .map(|w| w.into_payload(path_str.clone()))
+                        .collect(),
+                    None,
+                ),
+                Ok(Err(e)) => (
+                    file_id,
+                    gen,
+                    path_str.clone(),
                     empty,
+                    empty_board,
                     empty_nt,
-                    vec![format!("spawn_blocking join error: {e}")],
-                    false,
+                    GameMeta::default(),
+                    vec![IndexWarnPayload {
+                        path: path_str,
+                        message: e.clone(),
+                        kind: None,
+                        cursor: None,
+                    }],
+                    Some(e),
                 ),
+                Err(e) => {
+                    let message = format!("spawn_blocking join error: {e}");
+                    (
+                        file_id,
+                        gen,
+                        path_str.clone(),
+                        empty,
+                        empty_board,
+                        empty_nt,
+                        GameMeta::default(),
+                        vec![IndexWarnPayload {
+                            path: path_str,
+                            message: message.clone(),
+                            kind: None,
+                            cursor: None,
+                        }],
+                        Some(message),
+                    )
+                }
             };
 
             out
@@ -333,27 +671,22 @@ async fn build_full_index_task(
     }
 
     while let Some(r) = join.join_next().await {
-        let (file_id, gen, path_str, by_bucket, node_table, warns, ok) = match r {
-            Ok(v) => v,
-            Err(_join_err) => {
-                done_files += 1;
-                continue;
-            }
-        };
+        let (file_id, gen, path_str, by_bucket, board_by_bucket, node_table, meta, warns, error) =
+            match r {
+                Ok(v) => v,
+                Err(_join_err) => {
+                    done_files += 1;
+                    continue;
+                }
+            };
 
         done_files += 1;
-        if ok {
+        if error.is_none() {
             indexed_ok += 1;
         }
 
         for w in warns {
-            let _ = app.emit(
-                EVT_INDEX_WARN,
-                IndexWarnPayload {
-                    path: path_str.clone(),
-                    message: w,
-                },
-            );
+            let _ = app.emit(EVT_INDEX_WARN, w);
         }
 
         let file_entry = FileEntry {
@@ -361,9 +694,10 @@ async fn build_full_index_task(
             path: path_str.clone(),
             deleted: false,
             gen,
+            error,
         };
 
-        batch.push((file_entry, node_table, by_bucket));
+        batch.push((file_entry, node_table, by_bucket, board_by_bucket, meta));
 
         if batch.len() >= COMMIT_BATCH {
             store.insert_many_file_segments(std::mem::take(&mut batch));
@@ -433,6 +767,7 @@ async fn build_full_index_task(
             let _ = crate::search::index_cache::save_checkpoint(
                 &app2,
                 &root2,
+                &scan_rules,
                 &snap,
                 &scan2,
                 &path_to_id2,
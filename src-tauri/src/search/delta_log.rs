@@ -0,0 +1,422 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use super::{
+    fs_scan::{path_key, FileRecord},
+    index_cache::{
+        read_file_entry, read_file_record, read_node_table, write_file_entry, write_file_record,
+        write_node_table, write_string, write_u32, write_u64, write_u8, Reader, RestoredCache,
+    },
+    index_store::FileBucketEntries,
+    node_table::NodeTable,
+    position_key::PositionKey,
+    segment::{BoardEntry, Segment},
+    types::{FileEntry, FileId, GameMeta, Occurrence},
+};
+
+// index 本体 (`index.v1.zst`) の後に起きた差分を追記していくログ。
+//
+// header : MAGIC, VERSION, root_hash[32], token (対応する本体の segments token)
+// record : len u32, checksum[8] (blake3 の先頭 8 byte), zstd(payload)[len]
+//
+// 追記は 1 レコード 1 回の write + fsync で行う。書きかけで落ちた末尾レコードは
+// checksum が合わないので replay で捨てる (失うのは最後の差分だけ)。
+// token が本体と合わないログ (本体の書き直し前に取り残されたもの) は丸ごと無視する。
+const MAGIC: [u8; 8] = *b"OBSDLv01";
+const VERSION: u32 = 4;
+
+/// MAGIC + VERSION + root_hash + token
+const HEADER_LEN: usize = 8 + 4 + 32 + 8;
+/// 1 レコードの枠: (payload 長 u32, checksum [u8; 8])
+const FRAME_LEN: usize = 4 + 8;
+
+/// 1 回の差分反映 (run_rescan_diff_apply) 分の変更
+#[derive(Debug, Default)]
+pub struct DeltaBatch {
+    /// scan / path_to_id から消えたパス (path_key)
+    pub removed_paths: Vec<String>,
+    /// 追加・更新されたファイルの走査情報と file_id
+    pub records: Vec<(FileRecord, FileId)>,
+    /// 削除されたファイル
+    pub tombstones: Vec<FileId>,
+    /// 内容を変えずに移動したファイルと新しい path
+    pub renames: Vec<(FileId, String)>,
+    pub next_file_id: FileId,
+}
+
+/// 1 回の差分反映分を payload (非圧縮) にする。`files` は insert_many_file_segments に
+/// 渡すのと同じもの。
+pub fn encode_delta(batch: &DeltaBatch, files: &[FileBucketEntries]) -> Vec<u8> {
+    let mut w = Vec::new();
+
+    write_u32(&mut w, batch.removed_paths.len() as u32);
+    for k in &batch.removed_paths {
+        write_string(&mut w, k);
+    }
+
+    write_u32(&mut w, batch.records.len() as u32);
+    for (rec, file_id) in &batch.records {
+        write_file_record(&mut w, rec);
+        write_u32(&mut w, *file_id);
+    }
+
+    write_u32(&mut w, batch.tombstones.len() as u32);
+    for file_id in &batch.tombstones {
+        write_u32(&mut w, *file_id);
+    }
+
+    write_u32(&mut w, batch.renames.len() as u32);
+    for (file_id, path) in &batch.renames {
+        write_u32(&mut w, *file_id);
+        write_string(&mut w, path);
+    }
+
+    write_u32(&mut w, batch.next_file_id);
+
+    write_u32(&mut w, files.len() as u32);
+    for (entry, nt, by_bucket, board_by_bucket, meta) in files {
+        write_file_entry(&mut w, entry, Some(meta));
+        write_node_table(&mut w, nt);
+
+        let used = by_bucket.iter().filter(|v| !v.is_empty()).count();
+        write_u32(&mut w, used as u32);
+        for (b, v) in by_bucket.iter().enumerate().filter(|(_, v)| !v.is_empty()) {
+            write_u8(&mut w, b as u8);
+            write_u32(&mut w, v.len() as u32);
+            for (k, occ) in v {
+                write_key_occ(&mut w, *k, *occ);
+            }
+        }
+
+        let used = board_by_bucket.iter().filter(|v| !v.is_empty()).count();
+        write_u32(&mut w, used as u32);
+        for (b, v) in board_by_bucket
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.is_empty())
+        {
+            write_u8(&mut w, b as u8);
+            write_u32(&mut w, v.len() as u32);
+            for (k, occ, hands) in v {
+                write_key_occ(&mut w, *k, *occ);
+                write_u64(&mut w, *hands);
+            }
+        }
+    }
+
+    w
+}
+
+fn write_key_occ(w: &mut Vec<u8>, k: PositionKey, occ: Occurrence) {
+    write_u64(w, k.z0);
+    write_u64(w, k.z1);
+    write_u32(w, occ.file_id);
+    write_u32(w, occ.r#gen);
+    write_u32(w, occ.node_id);
+}
+
+fn read_key_occ(r: &mut Reader<'_>) -> Result<(PositionKey, Occurrence), String> {
+    let key = PositionKey {
+        z0: r.read_u64()?,
+        z1: r.read_u64()?,
+    };
+    let occ = Occurrence {
+        file_id: r.read_u32()?,
+        gen: r.read_u32()?,
+        node_id: r.read_u32()?,
+    };
+    Ok((key, occ))
+}
+
+fn header(root_hash: &[u8; 32], token: u64) -> Vec<u8> {
+    let mut h = Vec::with_capacity(HEADER_LEN);
+    h.extend_from_slice(&MAGIC);
+    h.extend_from_slice(&VERSION.to_le_bytes());
+    h.extend_from_slice(root_hash);
+    h.extend_from_slice(&token.to_le_bytes());
+    h
+}
+
+fn checksum(payload: &[u8]) -> [u8; 8] {
+    let h = blake3::hash(payload);
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&h.as_bytes()[..8]);
+    out
+}
+
+/// 本体を書き直した直後に、新しい token で空のログを作る (古いログは置き換わる)。
+pub fn start_delta_log(path: &Path, root_hash: &[u8; 32], token: u64) -> io::Result<()> {
+    let tmp = path.with_extension("delta.tmp");
+    {
+        let mut f = File::create(&tmp)?;
+        f.write_all(&header(root_hash, token))?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+/// payload を 1 レコードとして追記し、追記後のログのバイト数を返す。
+///
+/// ログが無い (まだ本体を保存していない) 場合は何もせずエラーにする。
+pub fn append_delta(path: &Path, root_hash: &[u8; 32], payload: &[u8]) -> Result<u64, String> {
+    let mut f = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("open {}: {e}", path.display()))?;
+
+    let mut head = [0u8; HEADER_LEN];
+    f.read_exact(&mut head).map_err(|e| e.to_string())?;
+    if head[0..8] != MAGIC || head[12..44] != root_hash[..] {
+        return Err("delta log: bad header".to_string());
+    }
+
+    let compressed = zstd::stream::encode_all(payload, 1).map_err(|e| e.to_string())?;
+    let mut frame = Vec::with_capacity(FRAME_LEN + compressed.len());
+    frame.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&checksum(&compressed));
+    frame.extend_from_slice(&compressed);
+
+    f.write_all(&frame).map_err(|e| e.to_string())?;
+    f.sync_data().map_err(|e| e.to_string())?;
+
+    Ok(f.metadata().map_err(|e| e.to_string())?.len())
+}
+
+/// ログを読んで `restored` に順に適用し、適用したレコード数を返す。
+///
+/// 壊れた (書きかけの) 末尾レコード以降は捨て、次の追記がその後ろに続かないよう
+/// ファイルを最後の正常なレコードまで切り詰める。
+pub fn replay_delta(
+    path: &Path,
+    root_hash: &[u8; 32],
+    token: u64,
+    restored: &mut RestoredCache,
+) -> Result<usize, String> {
+    let bytes = fs::read(path).map_err(|e| format!("read {}: {e}", path.display()))?;
+    if bytes.len() < HEADER_LEN || bytes[0..8] != MAGIC {
+        return Err("delta log: bad header".to_string());
+    }
+    let ver = u32::from_le_bytes(bytes[8..12].try_into().map_err(|_| "bad header")?);
+    if ver != VERSION {
+        return Err(format!("delta log: bad version: {ver}"));
+    }
+    if bytes[12..44] != root_hash[..] {
+        return Err("delta log: root hash mismatch".to_string());
+    }
+    let saved_token = u64::from_le_bytes(bytes[44..52].try_into().map_err(|_| "bad header")?);
+    if saved_token != token {
+        return Err("delta log: token mismatch (stale log)".to_string());
+    }
+
+    let mut extra = DeltaSegments::default();
+    let mut applied = 0usize;
+    let mut off = HEADER_LEN;
+
+    while off < bytes.len() {
+        match apply_frame(&bytes[off..], restored, &mut extra) {
+            Ok(used) => {
+                off += used;
+                applied += 1;
+            }
+            Err(e) => {
+                log::warn!(
+                    "[delta_log] drop tail at {off} ({} bytes): {e}",
+                    bytes.len() - off
+                );
+                if let Ok(f) = OpenOptions::new().write(true).open(path) {
+                    let _ = f.set_len(off as u64).and_then(|_| f.sync_all());
+                }
+                break;
+            }
+        }
+    }
+
+    extra.install(restored);
+    Ok(applied)
+}
+
+/// replay 中に集めた差分エントリ。bucket ごとに 1 本のセグメントにまとめて積む
+struct DeltaSegments {
+    exact: [Vec<(PositionKey, Occurrence)>; 256],
+    board: [Vec<BoardEntry>; 256],
+}
+
+impl Default for DeltaSegments {
+    fn default() -> Self {
+        Self {
+            exact: std::array::from_fn(|_| Vec::new()),
+            board: std::array::from_fn(|_| Vec::new()),
+        }
+    }
+}
+
+impl DeltaSegments {
+    fn install(self, restored: &mut RestoredCache) {
+        for (b, mut v) in self.exact.into_iter().enumerate() {
+            if v.is_empty() {
+                continue;
+            }
+            v.sort_unstable_by_key(|(k, occ)| (k.z0, k.z1, occ.file_id, occ.node_id));
+            restored.buckets[b].push(Arc::new(Segment::new_sorted(v)));
+        }
+        for (b, mut v) in self.board.into_iter().enumerate() {
+            if v.is_empty() {
+                continue;
+            }
+            v.sort_unstable_by_key(|(k, occ, _)| (k.z0, k.z1, occ.file_id, occ.node_id));
+            restored.board_buckets[b].push(Arc::new(Segment::new_sorted_with_hands(v)));
+        }
+    }
+}
+
+/// 先頭の 1 レコードを検証して適用し、消費したバイト数を返す。
+///
+/// 検証 (長さ・checksum・展開・decode) が全部通ってから適用するので、壊れた
+/// レコードが中途半端に反映されることはない。
+fn apply_frame(
+    bytes: &[u8],
+    restored: &mut RestoredCache,
+    extra: &mut DeltaSegments,
+) -> Result<usize, String> {
+    if bytes.len() < FRAME_LEN {
+        return Err("truncated frame".to_string());
+    }
+    let len = u32::from_le_bytes(bytes[0..4].try_into().map_err(|_| "bad frame")?) as usize;
+    let end = FRAME_LEN + len;
+    if bytes.len() < end {
+        return Err("truncated payload".to_string());
+    }
+    let compressed = &bytes[FRAME_LEN..end];
+    if bytes[4..FRAME_LEN] != checksum(compressed) {
+        return Err("checksum mismatch".to_string());
+    }
+    let payload = zstd::stream::decode_all(compressed).map_err(|e| format!("zstd decode: {e}"))?;
+    let record = decode_delta(&payload)?;
+    record.apply(restored, extra);
+    Ok(end)
+}
+
+type DeltaFile = (FileEntry, NodeTable, Option<GameMeta>);
+
+struct DecodedDelta {
+    batch: DeltaBatch,
+    files: Vec<DeltaFile>,
+    exact: Vec<(u8, (PositionKey, Occurrence))>,
+    board: Vec<(u8, BoardEntry)>,
+}
+
+fn decode_delta(payload: &[u8]) -> Result<DecodedDelta, String> {
+    let mut r = Reader::new(payload);
+    let mut batch = DeltaBatch::default();
+
+    let n = r.read_u32()? as usize;
+    for _ in 0..n {
+        batch.removed_paths.push(r.read_string()?);
+    }
+
+    let n = r.read_u32()? as usize;
+    for _ in 0..n {
+        let rec = read_file_record(&mut r)?;
+        let file_id = r.read_u32()?;
+        batch.records.push((rec, file_id));
+    }
+
+    let n = r.read_u32()? as usize;
+    for _ in 0..n {
+        batch.tombstones.push(r.read_u32()?);
+    }
+
+    let n = r.read_u32()? as usize;
+    for _ in 0..n {
+        let file_id = r.read_u32()?;
+        batch.renames.push((file_id, r.read_string()?));
+    }
+
+    batch.next_file_id = r.read_u32()?;
+
+    let mut files = Vec::new();
+    let mut exact = Vec::new();
+    let mut board = Vec::new();
+    let n = r.read_u32()? as usize;
+    for _ in 0..n {
+        let (entry, meta) = read_file_entry(&mut r)?;
+        let nt = read_node_table(&mut r)?;
+        files.push((entry, nt, meta));
+
+        let used = r.read_u32()? as usize;
+        for _ in 0..used {
+            let b = r.read_u8()?;
+            let len = r.read_u32()? as usize;
+            for _ in 0..len {
+                exact.push((b, read_key_occ(&mut r)?));
+            }
+        }
+
+        let used = r.read_u32()? as usize;
+        for _ in 0..used {
+            let b = r.read_u8()?;
+            let len = r.read_u32()? as usize;
+            for _ in 0..len {
+                let (k, occ) = read_key_occ(&mut r)?;
+                board.push((b, (k, occ, r.read_u64()?)));
+            }
+        }
+    }
+
+    Ok(DecodedDelta {
+        batch,
+        files,
+        exact,
+        board,
+    })
+}
+
+impl DecodedDelta {
+    /// run_rescan_diff_apply と同じ順 (削除 → 追加・更新) で反映する
+    fn apply(self, restored: &mut RestoredCache, extra: &mut DeltaSegments) {
+        let DecodedDelta {
+            batch,
+            files,
+            exact,
+            board,
+        } = self;
+
+        for k in &batch.removed_paths {
+            restored.scan.by_path.remove(k);
+            restored.path_to_id.remove(k);
+        }
+        for file_id in batch.tombstones {
+            restored.file_table.tombstone(file_id);
+        }
+        for (file_id, path) in batch.renames {
+            restored.file_table.rename(file_id, path);
+        }
+        for (rec, file_id) in batch.records {
+            let k = path_key(&rec.path);
+            restored.path_to_id.insert(k.clone(), file_id);
+            restored.scan.by_path.insert(k, rec);
+        }
+        restored.next_file_id = batch.next_file_id;
+
+        for (entry, nt, meta) in files {
+            let file_id = entry.file_id;
+            restored.file_table.upsert(entry);
+            restored
+                .file_table
+                .set_meta(file_id, &meta.unwrap_or_default());
+            restored.node_tables.upsert(file_id, Arc::new(nt));
+        }
+
+        for (b, e) in exact {
+            extra.exact[b as usize].push(e);
+        }
+        for (b, e) in board {
+            extra.board[b as usize].push(e);
+        }
+    }
+}
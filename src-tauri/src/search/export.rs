@@ -0,0 +1,160 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tauri::{AppHandle, Runtime};
+
+use crate::file_system::utils::{atomic_write, validate_under_root};
+
+use super::{
+    file_table::FileTable,
+    roots::RootSnapshots,
+    types::{
+        CursorLite, ExportManifest, ExportManifestEntry, ExportMode, ExportResultsOutput, FileId,
+        PositionHit, RootId,
+    },
+};
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// hit の元棋譜を `dest` に書き出す。
+///
+/// - ファイル単位でまとめ、hit の出現順に並べる
+/// - index 後に削除・更新されたファイルは skipped に回す
+/// - Copy / Symlink で同名が衝突したら "name_2.kif" のように番号を付ける
+pub fn export_hits<R: Runtime>(
+    app: &AppHandle<R>,
+    snaps: &RootSnapshots,
+    hits: &[PositionHit],
+    dest: &Path,
+    mode: ExportMode,
+) -> Result<ExportResultsOutput, String> {
+    validate_under_root(app, dest).map_err(|e| e.message)?;
+
+    let table_of = |root_id: RootId| -> Option<&FileTable> {
+        snaps
+            .iter()
+            .find(|(id, _)| *id == root_id)
+            .map(|(_, snap)| snap.file_table.as_ref())
+    };
+
+    let mut out = ExportResultsOutput::default();
+    let mut order: Vec<(&FileTable, FileId, Vec<CursorLite>)> = Vec::new();
+    let mut index: HashMap<(RootId, FileId), usize> = HashMap::new();
+
+    for h in hits {
+        let fid = h.occ.file_id;
+        // 外した root の hit は元ファイルを引けないので落とす
+        let Some(ft) = table_of(h.root_id) else {
+            continue;
+        };
+        if !ft.is_occ_alive(fid, h.occ.r#gen) {
+            if let Some(p) = ft.get_path(fid) {
+                if !out.skipped.iter().any(|s| s == p) {
+                    out.skipped.push(p.to_string());
+                }
+            }
+            continue;
+        }
+        let i = *index.entry((h.root_id, fid)).or_insert_with(|| {
+            order.push((ft, fid, Vec::new()));
+            order.len() - 1
+        });
+        order[i].2.push(h.cursor.clone());
+    }
+
+    if mode == ExportMode::Manifest {
+        let manifest = ExportManifest {
+            version: MANIFEST_VERSION,
+            created_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            entries: order
+                .into_iter()
+                .filter_map(|(ft, fid, cursors)| {
+                    Some(ExportManifestEntry {
+                        abs_path: ft.get_path(fid)?.to_string(),
+                        cursors,
+                    })
+                })
+                .collect(),
+        };
+        let data = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+        atomic_write(dest, &data).map_err(|e| e.to_string())?;
+        out.written.push(dest.to_string_lossy().to_string());
+        return Ok(out);
+    }
+
+    fs::create_dir_all(dest).map_err(|e| e.to_string())?;
+
+    let mut used: HashSet<PathBuf> = HashSet::new();
+    for (ft, fid, _) in order {
+        let Some(src) = ft.get_path(fid).map(Path::new) else {
+            continue;
+        };
+        let Some(name) = src.file_name() else {
+            continue;
+        };
+        let target = unique_target(dest, name, &used);
+
+        let res = if mode == ExportMode::Symlink {
+            make_symlink(src, &target)
+        } else {
+            fs::read(src).and_then(|bytes| atomic_write(&target, &bytes))
+        };
+        match res {
+            Ok(()) => {
+                out.written.push(target.to_string_lossy().to_string());
+                used.insert(target);
+            }
+            Err(e) => {
+                log::warn!("[export] {} -> {}: {e}", src.display(), target.display());
+                out.skipped.push(src.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// dest/name が既にあれば stem に "_2", "_3", ... を付けて空いている名前を探す
+fn unique_target(dest: &Path, name: &OsStr, used: &HashSet<PathBuf>) -> PathBuf {
+    let first = dest.join(name);
+    if !first.exists() && !used.contains(&first) {
+        return first;
+    }
+
+    let p = Path::new(name);
+    let stem = p.file_stem().unwrap_or(name).to_os_string();
+    let ext = p.extension().map(|e| e.to_os_string());
+
+    let mut n = 2u32;
+    loop {
+        let mut candidate: OsString = stem.clone();
+        candidate.push(format!("_{n}"));
+        if let Some(ext) = &ext {
+            candidate.push(".");
+            candidate.push(ext);
+        }
+        let path = dest.join(candidate);
+        if !path.exists() && !used.contains(&path) {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, dst)
+}
+
+#[cfg(windows)]
+fn make_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(src, dst)
+}
@@ -1,4 +1,20 @@
-use super::types::{FileEntry, FileId, Gen};
+use std::collections::HashMap;
+
+use super::{
+    game_meta::{parse_date_ymd, MetaFilter},
+    types::{FileEntry, FileId, GameMeta, GameResult, Gen},
+};
+
+/// 対局情報 1 件。文字列は `FileTable::strings` の id (0 = なし)
+#[derive(Debug, Clone, Copy, Default)]
+struct MetaRow {
+    black: u32,
+    white: u32,
+    event: u32,
+    /// YYYYMMDD (0 = 不明)
+    date: u32,
+    result: GameResult,
+}
 
 /// SoA-backed file metadata. `is_occ_alive` は検索ホットパスなので O(1) 配列
 /// アクセスにする。`file_id` はフルビルドで 1 から密に振られるので、そのまま
@@ -8,6 +24,14 @@ pub struct FileTable {
     gens: Vec<Gen>,
     deleted: Vec<bool>,
     paths: Vec<Option<String>>,
+    metas: Vec<Option<MetaRow>>,
+    /// 開始日時の元の表記。ほぼ対局ごとに違うので intern しない
+    start_dates: Vec<Option<String>>,
+    /// 読み込み・build に失敗したファイルの理由 (ほとんど None)
+    errors: Vec<Option<String>>,
+    /// 対局者名・棋戦名は同じ文字列が大量に繰り返すので intern する。slot 0 は未使用
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
 }
 
 impl FileTable {
@@ -17,7 +41,33 @@ impl FileTable {
             self.gens.resize(i + 1, 0);
             self.deleted.resize(i + 1, false);
             self.paths.resize(i + 1, None);
+            self.metas.resize(i + 1, None);
+            self.start_dates.resize(i + 1, None);
+            self.errors.resize(i + 1, None);
+        }
+    }
+
+    fn intern(&mut self, s: Option<&str>) -> u32 {
+        let Some(s) = s else {
+            return 0;
+        };
+        if self.strings.is_empty() {
+            self.strings.push(String::new());
+        }
+        if let Some(&id) = self.string_ids.get(s) {
+            return id;
         }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.string_ids.insert(s.to_string(), id);
+        id
+    }
+
+    fn string(&self, id: u32) -> Option<&str> {
+        if id == 0 {
+            return None;
+        }
+        self.strings.get(id as usize).map(|s| s.as_str())
     }
 
     pub fn get(&self, file_id: FileId) -> Option<FileEntry> {
@@ -28,6 +78,7 @@ impl FileTable {
             path: path.clone(),
             deleted: self.deleted[i],
             gen: self.gens[i],
+            error: self.errors[i].clone(),
         })
     }
 
@@ -42,6 +93,83 @@ impl FileTable {
         self.gens[i] = entry.gen;
         self.deleted[i] = entry.deleted;
         self.paths[i] = Some(entry.path);
+        self.errors[i] = entry.error;
+    }
+
+    pub fn set_meta(&mut self, file_id: FileId, meta: &GameMeta) {
+        self.ensure(file_id);
+        let row = MetaRow {
+            black: self.intern(meta.black.as_deref()),
+            white: self.intern(meta.white.as_deref()),
+            event: self.intern(meta.event.as_deref()),
+            date: meta
+                .start_date
+                .as_deref()
+                .and_then(parse_date_ymd)
+                .unwrap_or(0),
+            result: meta.result,
+        };
+        self.metas[file_id as usize] = Some(row);
+        self.start_dates[file_id as usize] = meta.start_date.clone();
+    }
+
+    pub fn meta(&self, file_id: FileId) -> Option<GameMeta> {
+        let row = self.metas.get(file_id as usize)?.as_ref()?;
+        Some(GameMeta {
+            black: self.string(row.black).map(|s| s.to_string()),
+            white: self.string(row.white).map(|s| s.to_string()),
+            event: self.string(row.event).map(|s| s.to_string()),
+            start_date: self.start_dates[file_id as usize].clone(),
+            result: row.result,
+        })
+    }
+
+    /// 対局情報が filter を満たすか。情報の無いファイルは条件を指定された項目で落ちる
+    pub fn meta_matches(&self, file_id: FileId, f: &MetaFilter) -> bool {
+        let row = self
+            .metas
+            .get(file_id as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_default();
+        let contains = |id: u32, needle: &str| self.string(id).is_some_and(|s| s.contains(needle));
+
+        if let Some(p) = &f.player {
+            if !contains(row.black, p) && !contains(row.white, p) {
+                return false;
+            }
+        }
+        if let Some(ev) = &f.event {
+            if !contains(row.event, ev) {
+                return false;
+            }
+        }
+        if f.date_from.is_some() || f.date_to.is_some() {
+            if row.date == 0 {
+                return false;
+            }
+            if f.date_from.is_some_and(|d| row.date < d) || f.date_to.is_some_and(|d| row.date > d)
+            {
+                return false;
+            }
+        }
+        if let Some(r) = f.result {
+            if row.result != r {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// gen を変えずに path だけ付け替える (内容が同じままの移動)
+    pub fn rename(&mut self, file_id: FileId, path: String) {
+        if let Some(p) = self
+            .paths
+            .get_mut(file_id as usize)
+            .and_then(|p| p.as_mut())
+        {
+            *p = path;
+        }
     }
 
     pub fn tombstone(&mut self, file_id: FileId) {
@@ -80,6 +208,7 @@ impl FileTable {
                     path: path.clone(),
                     deleted: self.deleted[i],
                     gen: self.gens[i],
+                    error: self.errors[i].clone(),
                 },
             ))
         })
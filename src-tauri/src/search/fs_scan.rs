@@ -5,15 +5,19 @@ use std::{
     time::SystemTime,
 };
 
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use walkdir::{DirEntry, WalkDir};
+use walkdir::WalkDir;
 
 #[derive(Debug, Error)]
 pub enum ScanError {
     #[error("root directory does not exist: {0}")]
     RootNotFound(String),
 
+    #[error("invalid scan pattern: {0}")]
+    InvalidPattern(String),
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 }
@@ -47,6 +51,12 @@ pub struct FileRecord {
     pub kind: KifuKind,
     pub size: u64,
     pub mtime_ms: u128,
+    /// 内容の blake3 ハッシュ。stat が変わった時だけ読み直す (未計算・読めなければ None)
+    #[serde(default)]
+    pub hash: Option<[u8; 32]>,
+    /// `hash` を取りにいった時刻 (UNIX ms)。0 は不明
+    #[serde(default)]
+    pub hashed_at_ms: u64,
 }
 
 /// 走査スナップショット
@@ -56,34 +66,162 @@ pub struct ScanSnapshot {
     pub root_dir: PathBuf,
 }
 
+/// ファイルシステムの mtime の粒度として見込む幅 (FAT の 2 秒に合わせる)。
+///
+/// hash を取った時刻からこの幅に収まる mtime のファイルは、読んだ直後に同じ mtime の
+/// まま書き換えられたかもしれないので、stat が同じでも hash を取り直す。
+const MTIME_GRANULARITY_MS: u64 = 2_000;
+
 /// 差分結果
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ScanDiff {
     pub added: Vec<FileRecord>,
     pub modified: Vec<FileRecord>,
     pub removed: Vec<String>,
+    /// 同じ内容のまま移動したファイル: (旧 path_key, 新しいレコード)
+    pub renamed: Vec<(String, FileRecord)>,
 }
 
-/// ignore は「高速化のためのオプション」
-#[derive(Debug, Clone)]
-pub struct ScanOptions {
-    pub ignore_dir_names: HashSet<String>,
+/// 走査ルール (AppConfig に保存する)。
+///
+/// include / exclude は gitignore 風の glob:
+/// - `/` を含まないパターンは各階層の名前に当てる (`.git`, `*.bak`)
+/// - `/` を含むパターンは root からの相対パスに当てる (`pro/**`, `/old`)
+/// - ディレクトリに当たれば配下も全部当たる
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanRules {
+    /// 空なら全部。1 つでもあれば、どれかに当たるファイルだけを対象にする
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
     pub follow_links: bool,
+    /// root からの深さの上限 (1 = root 直下のファイルだけ)
+    pub max_depth: Option<usize>,
+    /// これより大きいファイルは対象外 (bytes)
+    pub max_file_size: Option<u64>,
 }
 
-impl Default for ScanOptions {
+impl Default for ScanRules {
     fn default() -> Self {
-        let mut ignore = HashSet::new();
-        ignore.insert(".git".to_string());
-        ignore.insert("node_modules".to_string());
-        ignore.insert("target".to_string());
         Self {
-            ignore_dir_names: ignore,
+            include: Vec::new(),
+            exclude: vec![
+                ".git".to_string(),
+                "node_modules".to_string(),
+                "target".to_string(),
+            ],
             follow_links: false,
+            max_depth: None,
+            max_file_size: None,
+        }
+    }
+}
+
+/// glob パターン 1 つ分
+#[derive(Debug, Clone)]
+struct RulePattern {
+    pattern: glob::Pattern,
+    /// root からの相対パス全体に当てるか (false なら名前だけ)
+    anchored: bool,
+}
+
+const RULE_MATCH: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl RulePattern {
+    fn parse(s: &str) -> Result<Self, ScanError> {
+        let s = s.trim().trim_end_matches('/');
+        let anchored = s.contains('/');
+        let s = s.trim_start_matches('/');
+        let pattern =
+            glob::Pattern::new(s).map_err(|e| ScanError::InvalidPattern(format!("{s}: {e}")))?;
+        Ok(Self { pattern, anchored })
+    }
+
+    fn matches(&self, rel: &Path) -> bool {
+        if self.anchored {
+            self.pattern.matches_path_with(rel, RULE_MATCH)
+        } else {
+            rel.file_name()
+                .is_some_and(|n| self.pattern.matches_with(&n.to_string_lossy(), RULE_MATCH))
         }
     }
 }
 
+/// `ScanRules` をパターンにコンパイルしたもの。scan / ファイルツリー / watcher で共通に使う
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub rules: ScanRules,
+    include: Vec<RulePattern>,
+    exclude: Vec<RulePattern>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self::from_rules(&ScanRules::default()).expect("default scan rules are valid")
+    }
+}
+
+impl ScanOptions {
+    pub fn from_rules(rules: &ScanRules) -> Result<Self, ScanError> {
+        let compile = |v: &[String]| {
+            v.iter()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| RulePattern::parse(s))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            rules: rules.clone(),
+            include: compile(&rules.include)?,
+            exclude: compile(&rules.exclude)?,
+        })
+    }
+
+    /// ルールの指紋。index cache に書いておき、ルールが変わったら cache を捨てる
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let bytes = serde_json::to_vec(&self.rules).unwrap_or_default();
+        blake3::hash(&bytes).into()
+    }
+
+    /// `p` (またはその祖先) が exclude・深さ制限にかかるか。root の外のパスは対象外として false
+    pub fn excludes(&self, root: &Path, p: &Path) -> bool {
+        let Ok(rel) = p.strip_prefix(root) else {
+            return false;
+        };
+        if self
+            .rules
+            .max_depth
+            .is_some_and(|d| rel.components().count() > d)
+        {
+            return true;
+        }
+        rel.ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .any(|a| self.exclude.iter().any(|pat| pat.matches(a)))
+    }
+
+    /// ファイル `p` が対象か (exclude にかからず、include があればどれかに当たる)
+    pub fn includes_file(&self, root: &Path, p: &Path) -> bool {
+        if self.excludes(root, p) {
+            return false;
+        }
+        if self.include.is_empty() {
+            return true;
+        }
+        let rel = p.strip_prefix(root).unwrap_or(p);
+        rel.ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .any(|a| self.include.iter().any(|pat| pat.matches(a)))
+    }
+
+    pub fn allows_size(&self, size: u64) -> bool {
+        !matches!(self.rules.max_file_size, Some(max) if size > max)
+    }
+}
+
 /// ルート配下を再帰走査し、対象拡張子だけ列挙
 /// - 対象外ファイルはメタ情報すら取得しない（最速優先）
 pub fn scan_kifu_files(root_dir: &Path, opts: &ScanOptions) -> Result<Vec<FileRecord>, ScanError> {
@@ -91,10 +229,26 @@ pub fn scan_kifu_files(root_dir: &Path, opts: &ScanOptions) -> Result<Vec<FileRe
         return Err(ScanError::RootNotFound(root_dir.display().to_string()));
     }
 
-    let walker = WalkDir::new(root_dir)
-        .follow_links(opts.follow_links)
+    // rescan・watcher と同じ形 (canonicalize 済み) の root からの相対パスでルールを当てる
+    let root = normalize_path(root_dir);
+    Ok(walk_kifu_files(&root, &root, opts, None))
+}
+
+/// `root` の中の `dir` 配下を走査する。`max_depth` が Some なら `dir` からその深さまで
+/// (1 = 直下のみ)
+fn walk_kifu_files(
+    root: &Path,
+    dir: &Path,
+    opts: &ScanOptions,
+    max_depth: Option<usize>,
+) -> Vec<FileRecord> {
+    let mut walker = WalkDir::new(dir).follow_links(opts.rules.follow_links);
+    if let Some(d) = max_depth {
+        walker = walker.max_depth(d);
+    }
+    let walker = walker
         .into_iter()
-        .filter_entry(|e| !should_skip_dir(e, opts));
+        .filter_entry(|e| !opts.excludes(root, e.path()));
 
     let mut out = Vec::new();
 
@@ -108,47 +262,136 @@ pub fn scan_kifu_files(root_dir: &Path, opts: &ScanOptions) -> Result<Vec<FileRe
             continue;
         }
 
-        let path = entry.path();
+        if let Some(rec) = record_for(root, entry.path(), opts) {
+            out.push(rec);
+        }
+    }
 
-        // ★対象拡張子以外は即スキップ（メタ取得なし）
-        let Some(kind) = KifuKind::from_path(path) else {
-            continue;
-        };
+    out
+}
 
-        // メタ取得
-        let meta = match fs::metadata(path) {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+/// 1 ファイル分のレコード。対象拡張子・ルールの対象でなければメタ情報を取らずに None
+fn record_for(root: &Path, path: &Path, opts: &ScanOptions) -> Option<FileRecord> {
+    // ★対象拡張子以外は即スキップ（メタ取得なし）
+    let kind = KifuKind::from_path(path)?;
+    if !opts.includes_file(root, path) {
+        return None;
+    }
+
+    // メタ取得
+    let meta = fs::metadata(path).ok()?;
+    if !meta.is_file() {
+        return None;
+    }
 
-        let size = meta.len();
-        let mtime_ms = meta
-            .modified()
-            .ok()
-            .and_then(|t| system_time_to_unix_ms(t).ok())
-            .unwrap_or(0);
+    let size = meta.len();
+    if !opts.allows_size(size) {
+        return None;
+    }
+    let mtime_ms = meta
+        .modified()
+        .ok()
+        .and_then(|t| system_time_to_unix_ms(t).ok())
+        .unwrap_or(0);
 
-        // canonicalizeは可能なら（失敗しても動くの優先）
-        let abs = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    // canonicalizeは可能なら（失敗しても動くの優先）
+    let abs = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
 
-        out.push(FileRecord {
-            path: abs,
-            kind,
-            size,
-            mtime_ms,
-        });
+    Some(FileRecord {
+        path: abs,
+        kind,
+        size,
+        mtime_ms,
+        hash: None,
+        hashed_at_ms: 0,
+    })
+}
+
+/// ファイルの今の (size, mtime_ms)。`FileRecord` と同じ求め方で、読めなければ None
+pub fn file_stat(path: &Path) -> Option<(u64, u128)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime_ms = meta
+        .modified()
+        .ok()
+        .and_then(|t| system_time_to_unix_ms(t).ok())
+        .unwrap_or(0);
+    Some((meta.len(), mtime_ms))
+}
+
+/// watcher が拾ったパスだけを見直して、`prev` から次のスナップショットを作る。
+///
+/// - `paths`: 変更のあったパス。ファイルならその 1 件、ディレクトリなら配下を再帰で
+///   見直し、消えていれば (配下も含めて) スナップショットから外す
+/// - `dirs`: 作成・削除・rename のあったパスの親ディレクトリ。直下だけ見直して、
+///   取りこぼした新しい名前を拾う
+pub fn rescan_paths(
+    prev: &ScanSnapshot,
+    paths: &HashSet<PathBuf>,
+    dirs: &HashSet<PathBuf>,
+    opts: &ScanOptions,
+) -> ScanSnapshot {
+    let mut next = prev.clone();
+    let root = normalize_path(&prev.root_dir);
+
+    for p in paths {
+        let p = normalize_path(p);
+        if opts.excludes(&root, &p) {
+            continue;
+        }
+        // フルスキャンと同じく、follow_links でなければリンクは無いものとして扱う
+        let meta = if opts.rules.follow_links {
+            fs::metadata(&p)
+        } else {
+            fs::symlink_metadata(&p)
+        };
+        match meta {
+            Ok(m) if m.is_dir() => {
+                remove_under(&mut next, &p);
+                for rec in walk_kifu_files(&root, &p, opts, None) {
+                    next.by_path.insert(path_key(&rec.path), rec);
+                }
+            }
+            Ok(m) if m.is_file() => {
+                // サイズ上限を超えた等で対象外になったら外す
+                remove_under(&mut next, &p);
+                if let Some(rec) = record_for(&root, &p, opts) {
+                    next.by_path.insert(path_key(&rec.path), rec);
+                }
+            }
+            _ => remove_under(&mut next, &p),
+        }
+    }
+
+    for d in dirs {
+        let d = normalize_path(d);
+        if opts.excludes(&root, &d) || !d.is_dir() {
+            continue;
+        }
+        for rec in walk_kifu_files(&root, &d, opts, Some(1)) {
+            next.by_path.insert(path_key(&rec.path), rec);
+        }
     }
 
-    Ok(out)
+    next
 }
 
-#[inline]
-fn should_skip_dir(entry: &DirEntry, opts: &ScanOptions) -> bool {
-    if !entry.file_type().is_dir() {
-        return false;
+/// スキャン結果と同じ形 (canonicalize 済み) にそろえる。消えたパスは親だけ canonicalize する
+pub fn normalize_path(p: &Path) -> PathBuf {
+    if let Ok(abs) = p.canonicalize() {
+        return abs;
+    }
+    match (p.parent(), p.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|d| d.join(name))
+            .unwrap_or_else(|_| p.to_path_buf()),
+        _ => p.to_path_buf(),
     }
-    let name = entry.file_name().to_string_lossy();
-    opts.ignore_dir_names.contains(name.as_ref())
+}
+
+/// `p` 自身と `p` 配下のレコードを外す
+fn remove_under(snap: &mut ScanSnapshot, p: &Path) {
+    snap.by_path.retain(|_, r| !r.path.starts_with(p));
 }
 
 #[inline]
@@ -167,29 +410,114 @@ pub fn snapshot_from_records(root_dir: &Path, records: Vec<FileRecord>) -> ScanS
     }
 }
 
-pub fn diff_snapshot(prev: &ScanSnapshot, next: &ScanSnapshot) -> ScanDiff {
+/// 内容の blake3 ハッシュ。読めなければ None
+pub fn content_hash(path: &Path) -> Option<[u8; 32]> {
+    let mut f = fs::File::open(path).ok()?;
+    let mut h = blake3::Hasher::new();
+    std::io::copy(&mut f, &mut h).ok()?;
+    Some(h.finalize().into())
+}
+
+/// hash が未計算のレコードを並列に埋める (full build 用)
+pub fn fill_hashes(records: &mut [FileRecord]) {
+    let now = now_ms();
+    records
+        .par_iter_mut()
+        .filter(|r| r.hash.is_none())
+        .for_each(|r| hash_record(r, now));
+}
+
+/// `hash` を取り直す。`now` は読み始める前の時刻にする (後だと直前の書き換えを見逃す)
+fn hash_record(r: &mut FileRecord, now: u64) {
+    r.hash = content_hash(&r.path);
+    r.hashed_at_ms = now;
+}
+
+fn now_ms() -> u64 {
+    system_time_to_unix_ms(SystemTime::now()).unwrap_or(0) as u64
+}
+
+/// prev → next の差分。
+///
+/// stat (size, mtime) が前回と同じファイルは読まずに前回の hash を `next` に引き継ぎ、
+/// 変わったものと新規のものだけ hash を計算する。ただし mtime が前回 hash を取った
+/// 時刻から `MTIME_GRANULARITY_MS` 以内のもの (時刻が不明なら全部) は stat が同じでも
+/// 読み直す。読み直したものは時刻が進むので、次からは引き継ぎに戻る。stat が変わっても内容が同じなら modified にしない (touch・クラウド同期
+/// 対策)。消えたファイルと同じ内容・種別の新規ファイルは rename として対にする。
+pub fn diff_snapshot(prev: &ScanSnapshot, next: &mut ScanSnapshot) -> ScanDiff {
     let mut diff = ScanDiff::default();
 
+    let now = now_ms();
+    let mut to_hash: Vec<&mut FileRecord> = Vec::new();
+    for (k, r_next) in next.by_path.iter_mut() {
+        match prev.by_path.get(k) {
+            Some(r_prev) if same_stat(r_prev, r_next) && !is_racy(r_prev) => {
+                r_next.hash = r_prev.hash;
+                r_next.hashed_at_ms = r_prev.hashed_at_ms;
+            }
+            _ => to_hash.push(r_next),
+        }
+    }
+    to_hash.par_iter_mut().for_each(|r| hash_record(r, now));
+
+    let mut added = Vec::new();
     for (k, r_next) in &next.by_path {
         match prev.by_path.get(k) {
-            None => diff.added.push(r_next.clone()),
+            None => added.push(r_next.clone()),
             Some(r_prev) => {
-                if r_prev.size != r_next.size || r_prev.mtime_ms != r_next.mtime_ms {
+                // 両方の hash があれば内容で、無ければ stat で比べる
+                let changed = match (r_prev.hash, r_next.hash) {
+                    (Some(a), Some(b)) => a != b,
+                    _ => !same_stat(r_prev, r_next),
+                };
+                if changed {
                     diff.modified.push(r_next.clone());
                 }
             }
         }
     }
 
-    for k in prev.by_path.keys() {
-        if !next.by_path.contains_key(k) {
-            diff.removed.push(k.clone());
+    // 消えたファイルを (hash, 種別) で引けるようにしておく
+    let mut removed_by_hash: HashMap<([u8; 32], KifuKind), Vec<String>> = HashMap::new();
+    for (k, r_prev) in &prev.by_path {
+        if next.by_path.contains_key(k) {
+            continue;
+        }
+        match r_prev.hash {
+            Some(h) => removed_by_hash
+                .entry((h, r_prev.kind))
+                .or_default()
+                .push(k.clone()),
+            None => diff.removed.push(k.clone()),
+        }
+    }
+
+    for r in added {
+        let old = r
+            .hash
+            .and_then(|h| removed_by_hash.get_mut(&(h, r.kind)))
+            .and_then(|v| v.pop());
+        match old {
+            Some(old_key) => diff.renamed.push((old_key, r)),
+            None => diff.added.push(r),
         }
     }
+    diff.removed.extend(removed_by_hash.into_values().flatten());
 
     diff
 }
 
+#[inline]
+fn same_stat(a: &FileRecord, b: &FileRecord) -> bool {
+    a.size == b.size && a.mtime_ms == b.mtime_ms
+}
+
+/// hash を取った時点で mtime が粒度内だった (同じ mtime のまま書き換わりえた)
+#[inline]
+fn is_racy(r: &FileRecord) -> bool {
+    r.mtime_ms >= r.hashed_at_ms.saturating_sub(MTIME_GRANULARITY_MS) as u128
+}
+
 #[inline]
 pub fn path_key(p: &Path) -> String {
     p.to_string_lossy().to_string()
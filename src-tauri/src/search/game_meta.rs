@@ -0,0 +1,133 @@
+use shogi_kifu_converter_obsshogi::jkf::{Color as JkfColor, JsonKifuFormat, MoveSpecial};
+use thiserror::Error;
+
+use super::types::{GameFilter, GameMeta, GameResult};
+
+#[derive(Debug, Error)]
+pub enum GameFilterError {
+    #[error("invalid date: {0}")]
+    InvalidDate(String),
+}
+
+/// 棋譜ヘッダと本譜の終局から対局情報を拾う。
+///
+/// 駒落ちの棋譜は 下手 = 先手 / 上手 = 後手 として扱う。
+pub fn extract_game_meta(jkf: &JsonKifuFormat) -> GameMeta {
+    let header = |keys: &[&str]| {
+        keys.iter()
+            .filter_map(|k| jkf.header.get(*k))
+            .map(|v| v.trim())
+            .find(|v| !v.is_empty())
+            .map(|v| v.to_string())
+    };
+
+    GameMeta {
+        black: header(&["先手", "下手"]),
+        white: header(&["後手", "上手"]),
+        event: header(&["棋戦"]),
+        start_date: header(&["開始日時", "対局日"]),
+        result: game_result(jkf),
+    }
+}
+
+/// 本譜の終局 special から結果を判定する。
+pub fn game_result(jkf: &JsonKifuFormat) -> GameResult {
+    let mut last_mover: Option<JkfColor> = None;
+
+    for node in &jkf.moves {
+        if let Some(m) = node.move_ {
+            last_mover = Some(m.color);
+            continue;
+        }
+        if let Some(special) = node.special {
+            return special_result(&special_name(special), last_mover);
+        }
+    }
+
+    GameResult::Unknown
+}
+
+/// 終局 special の表記と直前に指した側から結果を決める。
+///
+/// 投了・詰み・切れ負け・反則負け (ILLEGAL_MOVE) は手番側 (= 反則した側) の負けで
+/// 直前に指した側の勝ち、入玉宣言は手番側の勝ち。`±ILLEGAL_ACTION` は符号の側 (+ は先手)
+/// の負け。千日手・持将棋は引き分け。それ以外 (中断など) は Unknown。
+pub fn special_result(name: &str, last_mover: Option<JkfColor>) -> GameResult {
+    let mover_wins = match name {
+        "TORYO" | "TSUMI" | "TIME_UP" | "ILLEGAL_MOVE" => true,
+        "KACHI" => false,
+        "+ILLEGAL_ACTION" => return GameResult::WhiteWin,
+        "-ILLEGAL_ACTION" => return GameResult::BlackWin,
+        "SENNICHITE" | "JISHOGI" | "HIKIWAKE" => return GameResult::Draw,
+        _ => return GameResult::Unknown,
+    };
+
+    // 1 手も無ければ手番は判断できない
+    match (last_mover, mover_wins) {
+        (None, _) => GameResult::Unknown,
+        (Some(JkfColor::Black), true) | (Some(JkfColor::White), false) => GameResult::BlackWin,
+        (Some(JkfColor::White), true) | (Some(JkfColor::Black), false) => GameResult::WhiteWin,
+    }
+}
+
+/// JKF 上の表記 ("TORYO" など) を取り出す
+fn special_name(special: MoveSpecial) -> String {
+    serde_json::to_value(special)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
+
+/// "2024/01/02 10:00:00" や "2024年1月2日" から年月日を YYYYMMDD の数値で取り出す
+pub fn parse_date_ymd(s: &str) -> Option<u32> {
+    let mut nums = s
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|t| !t.is_empty());
+
+    let y = nums.next()?;
+    if y.len() != 4 {
+        return None;
+    }
+    let y: u32 = y.parse().ok()?;
+    let m: u32 = nums.next()?.parse().ok()?;
+    let d: u32 = nums.next()?.parse().ok()?;
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    Some(y * 10_000 + m * 100 + d)
+}
+
+/// `GameFilter` を検索中に何度も当てられる形にしたもの
+#[derive(Debug, Clone, Default)]
+pub struct MetaFilter {
+    pub player: Option<String>,
+    pub event: Option<String>,
+    pub date_from: Option<u32>,
+    pub date_to: Option<u32>,
+    pub result: Option<GameResult>,
+}
+
+impl MetaFilter {
+    pub fn compile(f: &GameFilter) -> Result<Self, GameFilterError> {
+        let non_empty = |s: &Option<String>| {
+            s.as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        let date = |s: &Option<String>| match non_empty(s) {
+            None => Ok(None),
+            Some(s) => parse_date_ymd(&s)
+                .map(Some)
+                .ok_or(GameFilterError::InvalidDate(s)),
+        };
+
+        Ok(Self {
+            player: non_empty(&f.player),
+            event: non_empty(&f.event),
+            date_from: date(&f.date_from)?,
+            date_to: date(&f.date_to)?,
+            result: f.result,
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use std::{cmp::Reverse, collections::BTreeMap, fs, time::SystemTime};
+
+use super::{
+    roots::RootSnapshots,
+    types::{FileId, HitSort, PositionHit, RootId},
+};
+
+/// hit をファイル単位で並べ替え、必要ならファイルごとに 1 件へまとめる。
+///
+/// ファイルは (root_id, file_id) で区別し、並べ替えは全 root を通して行う。
+///
+/// - `sort` はファイルの並び順。ファイル内の hit は元の順 (node_id 順) を保つ
+/// - `group_by_file` なら各ファイルの最も浅い手数の hit だけを残し、`hit_count` を付ける
+/// - どちらも指定されなければ何もしない
+pub fn arrange_hits(
+    hits: Vec<PositionHit>,
+    snaps: &RootSnapshots,
+    group_by_file: bool,
+    sort: Option<HitSort>,
+) -> Vec<PositionHit> {
+    if !group_by_file && sort.is_none() {
+        return hits;
+    }
+
+    let mut by_file: BTreeMap<(RootId, FileId), Vec<PositionHit>> = BTreeMap::new();
+    for h in hits {
+        by_file
+            .entry((h.root_id, h.occ.file_id))
+            .or_default()
+            .push(h);
+    }
+    let mut groups: Vec<((RootId, FileId), Vec<PositionHit>)> = by_file.into_iter().collect();
+
+    // sort は stable なので、同順位は (root_id, file_id) 昇順のまま残る
+    match sort {
+        None => {}
+        Some(HitSort::Path) => groups.sort_by(|a, b| path_of(snaps, a.0).cmp(&path_of(snaps, b.0))),
+        Some(HitSort::Mtime) => {
+            // 更新日時の取れないファイルは末尾
+            groups.sort_by_cached_key(|(key, _)| Reverse(file_mtime(snaps, *key)));
+        }
+        Some(HitSort::EarliestPly) => groups.sort_by_key(|(_, v)| min_tesuu(v)),
+        Some(HitSort::HitCount) => groups.sort_by_key(|(_, v)| Reverse(v.len())),
+    }
+
+    if !group_by_file {
+        return groups.into_iter().flat_map(|(_, v)| v).collect();
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|(_, v)| {
+            let n = v.len() as u32;
+            let mut rep = v.into_iter().min_by_key(|h| h.cursor.tesuu)?;
+            rep.hit_count = Some(n);
+            Some(rep)
+        })
+        .collect()
+}
+
+fn min_tesuu(hits: &[PositionHit]) -> u32 {
+    hits.iter()
+        .map(|h| h.cursor.tesuu)
+        .min()
+        .unwrap_or(u32::MAX)
+}
+
+fn path_of(snaps: &RootSnapshots, (root_id, file_id): (RootId, FileId)) -> Option<&str> {
+    snaps
+        .iter()
+        .find(|(id, _)| *id == root_id)?
+        .1
+        .file_table
+        .get_path(file_id)
+}
+
+fn file_mtime(snaps: &RootSnapshots, key: (RootId, FileId)) -> Option<SystemTime> {
+    let path = path_of(snaps, key)?;
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -1,37 +1,77 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use thiserror::Error;
 
-use shogi_core::PartialPosition;
+use shogi_core::{Color, PartialPosition};
 use shogi_kifu_converter_obsshogi::jkf::{JsonKifuFormat, MoveFormat};
 
 use super::{
+    game_meta::extract_game_meta,
     initial_position::{initial_partial_position, InitialPosError},
-    node_table::{NodeTable, NodeTableBuilder},
-    position_apply::{apply_node_action, ApplyError, ApplyStatus},
-    position_key::{key_from_partial_position, PositionKey},
+    node_table::{NodeTable, NodeTableBuilder, NODE_FLAG_PERPETUAL_CHECK, NODE_FLAG_REPETITION},
+    position_apply::{apply_node_action, check_node_legality, ApplyError, ApplyStatus},
+    position_key::{
+        board_key_from_partial_position, key_from_partial_position, pack_hands, PositionKey,
+    },
+    rules::is_in_check,
+    segment::BoardEntry,
     traverse::NodeAction,
-    types::{CursorLite, FileId, ForkPointer, Gen, NodeId, Occurrence},
+    types::{
+        BuildWarnKind, CursorLite, FileId, ForkPointer, GameMeta, Gen, IndexWarnPayload, NodeId,
+        Occurrence,
+    },
 };
 
+/// 同一局面がこの回数現れたら千日手
+const REPETITION_COUNT: u8 = 4;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BuildPolicy {
     Loose,
     Strict,
+    /// Loose に加えて、将棋のルール上指せない手 (王手放置・二歩・打ち歩詰め・
+    /// 行き所のない駒など) でもその系列を打ち切って警告にする
+    Legal,
 }
 
 #[derive(Debug, Clone)]
 pub struct BuildWarn {
+    pub kind: BuildWarnKind,
     pub cursor: CursorLite,
     pub message: String,
 }
 
+impl BuildWarn {
+    pub fn into_payload(self, path: String) -> IndexWarnPayload {
+        IndexWarnPayload {
+            path,
+            message: format!("{:?}: {}", self.cursor, self.message),
+            kind: Some(self.kind),
+            cursor: Some(self.cursor),
+        }
+    }
+}
+
+/// 今たどっている系列 (開始局面からのノード列) の 1 局面
+#[derive(Debug, Clone, Copy)]
+struct LineEntry {
+    key: PositionKey,
+    /// この局面の手番側
+    side: Color,
+    /// 手番側に王手がかかっている (= 直前の手が王手)
+    in_check: bool,
+}
+
 #[derive(Debug)]
 pub struct FileIndexBuild {
     /// (PositionKey, PositionHit)
     pub entries: Vec<(PositionKey, Occurrence)>,
+    /// 持ち駒を無視した盤面キーと、その局面の持ち駒
+    pub board_entries: Vec<BoardEntry>,
     pub node_table: Arc<NodeTable>,
     pub warns: Vec<BuildWarn>,
+    /// ヘッダと本譜の終局から拾った対局情報
+    pub meta: GameMeta,
 }
 
 #[derive(Debug, Error)]
@@ -47,42 +87,68 @@ pub enum BuildError {
     },
 }
 
-#[derive(Debug)]
-struct IndexBuilder {
+/// ノードを採番するたびに (node_id, 局面) で呼ばれるコールバック。
+///
+/// index には局面そのものは残らないので、局面の中身を見る検索 (パターン検索など)
+/// は build をやり直しつつこれで覗く。
+pub type NodeVisitor<'v> = &'v mut dyn FnMut(NodeId, &PartialPosition);
+
+struct IndexBuilder<'v> {
     file_id: FileId,
     gen: Gen,
     policy: BuildPolicy,
     node_table: NodeTableBuilder,
     entries: Vec<(PositionKey, Occurrence)>,
+    board_entries: Vec<BoardEntry>,
     warns: Vec<BuildWarn>,
+    visit: Option<NodeVisitor<'v>>,
+    /// 千日手判定用。分岐に入るたびに伸ばし、抜けるときに戻す
+    line: Vec<LineEntry>,
+    line_counts: HashMap<PositionKey, u8>,
 }
 
-impl IndexBuilder {
-    fn new(file_id: FileId, gen: Gen, policy: BuildPolicy) -> Self {
+impl<'v> IndexBuilder<'v> {
+    fn new(file_id: FileId, gen: Gen, policy: BuildPolicy, visit: Option<NodeVisitor<'v>>) -> Self {
         Self {
             file_id,
             gen,
             policy,
             node_table: NodeTableBuilder::new(),
             entries: Vec::new(),
+            board_entries: Vec::new(),
             warns: Vec::new(),
+            visit,
+            line: Vec::new(),
+            line_counts: HashMap::new(),
         }
     }
 
-    fn finish(self) -> FileIndexBuild {
+    fn finish(self, meta: GameMeta) -> FileIndexBuild {
         FileIndexBuild {
             entries: self.entries,
+            board_entries: self.board_entries,
             node_table: Arc::new(self.node_table.finish()),
             warns: self.warns,
+            meta,
         }
     }
 
     #[inline]
-    fn push_entry(&mut self, tesuu: u32, fork_path: &[ForkPointer], pos: &PartialPosition) {
+    fn push_entry(
+        &mut self,
+        tesuu: u32,
+        fork_path: &[ForkPointer],
+        pos: &PartialPosition,
+    ) -> (NodeId, PositionKey) {
         let key = key_from_partial_position(pos);
+        let board_key = board_key_from_partial_position(pos);
 
         let node_id: NodeId = self.node_table.push_node(tesuu, fork_path);
 
+        if let Some(visit) = self.visit.as_deref_mut() {
+            visit(node_id, pos);
+        }
+
         let occ = Occurrence {
             file_id: self.file_id,
             gen: self.gen,
@@ -90,6 +156,84 @@ impl IndexBuilder {
         };
 
         self.entries.push((key, occ));
+        self.board_entries.push((board_key, occ, pack_hands(pos)));
+
+        (node_id, key)
+    }
+
+    /// 指し手で進んだ局面を系列に積み、千日手・連続王手の千日手ならフラグと警告を付ける。
+    ///
+    /// 王手の連続は「同一局面の 1 回目から今回まで」の区間で、一方の手がすべて
+    /// 王手だったかで見る。
+    fn push_line(
+        &mut self,
+        node_id: NodeId,
+        key: PositionKey,
+        pos: &PartialPosition,
+        cursor: impl FnOnce() -> CursorLite,
+    ) {
+        let side = pos.side_to_move();
+        self.line.push(LineEntry {
+            key,
+            side,
+            in_check: is_in_check(pos, side),
+        });
+        let count = self.line_counts.entry(key).or_insert(0);
+        *count = count.saturating_add(1);
+        if *count < REPETITION_COUNT {
+            return;
+        }
+        let first_time = *count == REPETITION_COUNT;
+
+        let Some(start) = self.line.iter().position(|e| e.key == key) else {
+            return;
+        };
+        // 区間内の各手について、指した側 (= 局面の手番の反対) が王手をかけたか
+        let span = &self.line[start + 1..];
+        let checking = [Color::Black, Color::White]
+            .into_iter()
+            .find(|&c| span.iter().filter(|e| e.side != c).all(|e| e.in_check));
+
+        let mut flags = NODE_FLAG_REPETITION;
+        if checking.is_some() {
+            flags |= NODE_FLAG_PERPETUAL_CHECK;
+        }
+        self.node_table.add_flags(node_id, flags);
+
+        if !first_time {
+            return;
+        }
+        let warn = match checking {
+            Some(c) => BuildWarn {
+                kind: BuildWarnKind::PerpetualCheck,
+                cursor: cursor(),
+                message: format!(
+                    "perpetual check by {}",
+                    if c == Color::Black { "black" } else { "white" }
+                ),
+            },
+            None => BuildWarn {
+                kind: BuildWarnKind::Repetition,
+                cursor: cursor(),
+                message: "fourfold repetition".to_string(),
+            },
+        };
+        self.warns.push(warn);
+    }
+
+    /// 系列を len 局面まで戻す
+    fn truncate_line(&mut self, len: usize) {
+        while self.line.len() > len {
+            let Some(e) = self.line.pop() else {
+                break;
+            };
+            if let Some(n) = self.line_counts.get_mut(&e.key) {
+                *n -= 1;
+                if *n == 0 {
+                    self.line_counts.remove(&e.key);
+                }
+            }
+        }
     }
 
     fn walk_sequence(
@@ -99,6 +243,8 @@ impl IndexBuilder {
         mut pos: PartialPosition,
         fork_path: Vec<ForkPointer>,
     ) -> Result<(), BuildError> {
+        let base = self.line.len();
+
         for (offset, node) in seq.iter().enumerate() {
             let tesuu = start_tesuu + offset as u32;
             let parent_pos = pos.clone();
@@ -112,16 +258,38 @@ impl IndexBuilder {
                     let mut fork_path2 = fork_path.clone();
                     push_or_replace_fork(&mut fork_path2, tesuu, i as u32);
 
+                    let mark = self.line.len();
                     self.walk_sequence(fork_line, tesuu, parent_pos.clone(), fork_path2)?;
+                    self.truncate_line(mark);
                 }
             }
 
             // mainline
             let action = node_action(node);
 
+            if self.policy == BuildPolicy::Legal {
+                if let Some(reason) = check_node_legality(&pos, action) {
+                    self.warns.push(BuildWarn {
+                        kind: BuildWarnKind::IllegalMove,
+                        cursor: CursorLite {
+                            tesuu,
+                            fork_pointers: fork_path.clone(),
+                        },
+                        message: reason.to_string(),
+                    });
+                    break;
+                }
+            }
+
             match apply_node_action(&mut pos, action) {
                 Ok(status) => {
-                    self.push_entry(tesuu, &fork_path, &pos);
+                    let (node_id, key) = self.push_entry(tesuu, &fork_path, &pos);
+                    if status == ApplyStatus::Applied {
+                        self.push_line(node_id, key, &pos, || CursorLite {
+                            tesuu,
+                            fork_pointers: fork_path.clone(),
+                        });
+                    }
                     if status == ApplyStatus::Terminal {
                         break;
                     }
@@ -134,8 +302,9 @@ impl IndexBuilder {
                         };
                         return Err(BuildError::Apply { cursor, source: e });
                     }
-                    BuildPolicy::Loose => {
+                    BuildPolicy::Loose | BuildPolicy::Legal => {
                         self.warns.push(BuildWarn {
+                            kind: BuildWarnKind::ApplyFailed,
                             cursor: CursorLite {
                                 tesuu,
                                 fork_pointers: fork_path.clone(),
@@ -147,6 +316,8 @@ impl IndexBuilder {
                 },
             }
         }
+
+        self.truncate_line(base);
         Ok(())
     }
 }
@@ -160,19 +331,41 @@ pub fn build_index_for_jkf(
     gen: Gen,
     jkf: &JsonKifuFormat,
     policy: BuildPolicy,
+) -> Result<FileIndexBuild, BuildError> {
+    build_index_inner(file_id, gen, jkf, policy, None)
+}
+
+/// `build_index_for_jkf` と同じ走査をしつつ、各ノードの局面を `visit` に渡す。
+pub fn build_index_for_jkf_with_visitor(
+    file_id: FileId,
+    gen: Gen,
+    jkf: &JsonKifuFormat,
+    policy: BuildPolicy,
+    visit: NodeVisitor<'_>,
+) -> Result<FileIndexBuild, BuildError> {
+    build_index_inner(file_id, gen, jkf, policy, Some(visit))
+}
+
+fn build_index_inner(
+    file_id: FileId,
+    gen: Gen,
+    jkf: &JsonKifuFormat,
+    policy: BuildPolicy,
+    visit: Option<NodeVisitor<'_>>,
 ) -> Result<FileIndexBuild, BuildError> {
     let init_pos = initial_partial_position(jkf)?;
 
-    let mut b = IndexBuilder::new(file_id, gen, policy);
+    let mut b = IndexBuilder::new(file_id, gen, policy, visit);
 
     // root
-    b.push_entry(0, &[], &init_pos);
+    let (root_id, root_key) = b.push_entry(0, &[], &init_pos);
+    b.push_line(root_id, root_key, &init_pos, CursorLite::root);
 
     if jkf.moves.len() > 1 {
         b.walk_sequence(&jkf.moves[1..], 1, init_pos, vec![])?;
     }
 
-    Ok(b.finish())
+    Ok(b.finish(extract_game_meta(jkf)))
 }
 
 #[inline]
@@ -215,3 +408,18 @@ pub fn bucketize_entries(
 
     buckets
 }
+
+/// `bucketize_entries` の board index 版。
+pub fn bucketize_board_entries(entries: Vec<BoardEntry>) -> [Vec<BoardEntry>; 256] {
+    let mut buckets: [Vec<BoardEntry>; 256] = std::array::from_fn(|_| Vec::new());
+
+    for e in entries {
+        buckets[e.0.bucket() as usize].push(e);
+    }
+
+    for b in &mut buckets {
+        b.sort_by_key(|(k, _, _)| (k.z0, k.z1));
+    }
+
+    buckets
+}
@@ -2,22 +2,23 @@ use std::{
     cmp::Ordering,
     collections::{BinaryHeap, HashMap},
     fs,
-    io::Write,
+    io::{Read, Write},
     path::{Path, PathBuf},
-    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use tauri::{AppHandle, Manager};
 
 use super::{
+    delta_log::{append_delta, replay_delta, start_delta_log},
     file_table::FileTable,
     fs_scan::{snapshot_from_records, FileRecord, KifuKind, ScanSnapshot},
     index_store::{IndexSnapshot, NodeTables},
     node_table::NodeTable,
     position_key::PositionKey,
-    segment::SegmentArc,
-    types::{FileEntry, FileId, Occurrence},
+    segment::{BoardEntry, SegmentArc},
+    segment_file::{open_segment_file, write_segment_file},
+    types::{CacheStats, FileEntry, FileId, GameMeta, GameResult, Occurrence},
 };
 
 macro_rules! trace {
@@ -27,34 +28,44 @@ macro_rules! trace {
 }
 
 const MAGIC: [u8; 8] = *b"OBSIXv01"; // 8 bytes
-const VERSION: u32 = 1;
+const VERSION: u32 = 11;
+
+/// delta log がこれを超えたら本体を書き直して (compaction) ログを空にする
+const DELTA_COMPACT_BYTES: u64 = 16 * 1024 * 1024;
 
 pub struct RestoredCache {
     pub file_table: FileTable,
     pub node_tables: NodeTables,
-    pub buckets: [Vec<(PositionKey, Occurrence)>; 256], // compacted
+    /// セグメントファイル上の on-disk セグメント (bucket ごとに 1 本)
+    pub buckets: [Vec<SegmentArc>; 256],
+    pub board_buckets: [Vec<SegmentArc>; 256],
     pub scan: ScanSnapshot,
     pub path_to_id: HashMap<String, FileId>,
     pub next_file_id: FileId,
+    /// 本体とセグメントファイル・delta log を対応づける token
+    segments_token: u64,
+    /// 保存時の走査ルールの指紋 (`ScanOptions::fingerprint`)
+    scan_rules: [u8; 32],
 }
 
 struct EncodeCtx<'a> {
     root_dir: &'a Path,
+    scan_rules: &'a [u8; 32],
+    segments_token: u64,
     scan: &'a ScanSnapshot,
     path_to_id: &'a HashMap<String, FileId>,
     next_file_id: FileId,
     ft: &'a FileTable,
-    nts: &'a NodeTables,
 }
 
-fn now_ms() -> u64 {
+pub(super) fn now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_millis() as u64
 }
 
-fn root_hash(root_dir: &Path) -> [u8; 32] {
+pub(super) fn root_hash(root_dir: &Path) -> [u8; 32] {
     let s = root_dir.to_string_lossy();
     blake3::hash(s.as_bytes()).into()
 }
@@ -80,6 +91,48 @@ fn cache_paths(app: &AppHandle, root_dir: &Path) -> Result<(PathBuf, PathBuf, Pa
     Ok((proj, final_path, bak_path))
 }
 
+/// index 本体 (zst) と対になるセグメントファイル。token は保存のたびに変える
+fn segments_path(proj_dir: &Path, token: u64) -> PathBuf {
+    proj_dir.join(format!("segments.{token:016x}.bin"))
+}
+
+/// 本体の保存以降の差分を追記するログ (`delta_log` を参照)
+fn delta_path(proj_dir: &Path) -> PathBuf {
+    proj_dir.join("index.v1.delta")
+}
+
+/// `keep` 以外のセグメントファイルを消す (古い保存・書きかけの残骸)
+fn remove_stale_segments(proj_dir: &Path, keep: &[PathBuf]) {
+    let Ok(rd) = fs::read_dir(proj_dir) else {
+        return;
+    };
+    for ent in rd.flatten() {
+        let path = ent.path();
+        let name = ent.file_name().to_string_lossy().to_string();
+        if name.starts_with("segments.") && !keep.contains(&path) {
+            if let Err(e) = fs::remove_file(&path) {
+                trace!("remove stale {} FAILED: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// 保存済みの本体が参照するセグメントファイルの token。header だけを展開して読む
+fn read_segments_token(path: &Path) -> Option<u64> {
+    // MAGIC, VERSION, created_ms, root_hash, scan_rules, segments_token
+    const HEAD_LEN: usize = 8 + 4 + 8 + 32 + 32 + 8;
+    let file = fs::File::open(path).ok()?;
+    let mut head = [0u8; HEAD_LEN];
+    zstd::stream::read::Decoder::new(file)
+        .ok()?
+        .read_exact(&mut head)
+        .ok()?;
+    if head[0..8] != MAGIC || head[8..12] != VERSION.to_le_bytes() {
+        return None;
+    }
+    Some(u64::from_le_bytes(head[HEAD_LEN - 8..].try_into().ok()?))
+}
+
 fn hex32(h: &[u8; 32]) -> String {
     const HEX: &[u8; 16] = b"0123456789abcdef";
     let mut out = String::with_capacity(64);
@@ -97,6 +150,7 @@ fn hex32(h: &[u8; 32]) -> String {
 pub fn save_checkpoint(
     app: &AppHandle,
     root_dir: &Path,
+    scan_rules: &[u8; 32],
     snap: &IndexSnapshot,
     scan: &ScanSnapshot,
     path_to_id: &HashMap<String, FileId>,
@@ -117,10 +171,30 @@ pub fn save_checkpoint(
     })?;
     trace!("create_dir_all OK");
 
-    trace!("compact_all_buckets...");
-    // 1) コンパクション（bucketごとに1本化）
-    let buckets = compact_all_buckets(snap);
-    trace!("compact_all_buckets OK");
+    // 1) セグメントファイル: bucket ごとにコンパクション（1本化）して列で書く
+    let segments_token = uuid::Uuid::new_v4().as_u64_pair().0;
+    let seg_path = segments_path(&proj_dir, segments_token);
+    let seg_tmp = seg_path.with_extension("bin.tmp");
+    trace!("write segments {}", seg_tmp.display());
+    let ft = snap.file_table.as_ref();
+    write_segment_file(
+        &seg_tmp,
+        &root_hash(root_dir),
+        segments_token,
+        |b| compact_bucket(&snap.buckets[b], ft),
+        |b| compact_board_bucket(&snap.board_buckets[b], ft),
+        snap.node_tables.as_ref(),
+    )
+    .map_err(|e| {
+        trace!("write segments FAILED: {e}");
+        let _ = fs::remove_file(&seg_tmp);
+        e.to_string()
+    })?;
+    fs::rename(&seg_tmp, &seg_path).map_err(|e| {
+        trace!("rename segments FAILED: {e}");
+        e.to_string()
+    })?;
+    trace!("write segments OK");
 
     trace!("encode_all...");
     // 2) エンコード（非圧縮 body）
@@ -128,14 +202,15 @@ pub fn save_checkpoint(
 
     let ctx = EncodeCtx {
         root_dir,
+        scan_rules,
+        segments_token,
         scan,
         path_to_id,
         next_file_id,
         ft: snap.file_table.as_ref(),
-        nts: snap.node_tables.as_ref(),
     };
 
-    encode_all(&mut body, &ctx, &buckets).map_err(|e| {
+    encode_all(&mut body, &ctx).map_err(|e| {
         trace!("encode_all FAILED: {e}");
         e
     })?;
@@ -181,14 +256,75 @@ pub fn save_checkpoint(
         e.to_string()
     })?;
     trace!("rename tmp->final OK");
-    let _ = fs::remove_file(&bak_path);
+    // bak は次の保存が成功するまで残すので、bak が参照するセグメントファイルも残す
+    let mut keep = vec![seg_path];
+    if let Some(token) = read_segments_token(&bak_path) {
+        keep.push(segments_path(&proj_dir, token));
+    }
+    remove_stale_segments(&proj_dir, &keep);
+
+    // 4) 差分は全部本体に入ったので、新しい token で delta log を空にする。
+    // 作れなければ古いログを消す (token が合わないので replay されないが、追記も止める)
+    let delta = delta_path(&proj_dir);
+    if let Err(e) = start_delta_log(&delta, &root_hash(root_dir), segments_token) {
+        trace!("start delta log FAILED: {e}");
+        let _ = fs::remove_file(&delta);
+    }
     trace!("save_checkpoint END OK");
 
     Ok(())
 }
 
-pub fn try_restore(app: &AppHandle, root_dir: &Path) -> Result<RestoredCache, String> {
-    let (_proj_dir, final_path, bak_path) = cache_paths(app, root_dir)?;
+/// root の cache のディスク上のサイズと最後の checkpoint 時刻。まだ保存していなければ None
+pub fn cache_stats(app: &AppHandle, root_dir: &Path) -> Result<Option<CacheStats>, String> {
+    let (proj_dir, final_path, _) = cache_paths(app, root_dir)?;
+    let Ok(meta) = fs::metadata(&final_path) else {
+        return Ok(None);
+    };
+    let file_len = |p: &Path| fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+
+    let mut segment_bytes = 0;
+    if let Ok(rd) = fs::read_dir(&proj_dir) {
+        for ent in rd.flatten() {
+            if ent.file_name().to_string_lossy().starts_with("segments.") {
+                segment_bytes += file_len(&ent.path());
+            }
+        }
+    }
+    let index_bytes = meta.len();
+    let delta_bytes = file_len(&delta_path(&proj_dir));
+    Ok(Some(CacheStats {
+        total_bytes: index_bytes + segment_bytes + delta_bytes,
+        index_bytes,
+        segment_bytes,
+        delta_bytes,
+        last_checkpoint_ms: meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64),
+    }))
+}
+
+/// 差分反映 1 回分の payload (`delta_log::encode_delta`) を delta log に追記する。
+///
+/// 本体を丸ごと書き直すより安いので、watcher の差分反映ごとに呼ぶ。ログが
+/// 大きくなって compaction (save_checkpoint) すべきなら true を返す。
+pub fn save_delta(app: &AppHandle, root_dir: &Path, payload: &[u8]) -> Result<bool, String> {
+    let (proj_dir, _, _) = cache_paths(app, root_dir)?;
+    let len = append_delta(&delta_path(&proj_dir), &root_hash(root_dir), payload)?;
+    trace!("save_delta OK log_bytes={len}");
+    Ok(len > DELTA_COMPACT_BYTES)
+}
+
+/// cache から復元する。`scan_rules` (走査ルールの指紋) が保存時と違えば、対象ファイルの
+/// 集合が変わっているので cache は使わずに Err を返す (呼び出し側は full build する)
+pub fn try_restore(
+    app: &AppHandle,
+    root_dir: &Path,
+    scan_rules: &[u8; 32],
+) -> Result<RestoredCache, String> {
+    let (proj_dir, final_path, bak_path) = cache_paths(app, root_dir)?;
     trace!("try_restore BEGIN root_dir={}", root_dir.display());
     trace!(
         "final={} exists={}",
@@ -198,7 +334,36 @@ pub fn try_restore(app: &AppHandle, root_dir: &Path) -> Result<RestoredCache, St
     trace!("bak  ={} exists={}", bak_path.display(), bak_path.exists());
 
     // final → 失敗したら bak
-    match read_decode(&final_path, root_dir) {
+    let mut restored = read_decode_any(&final_path, &bak_path, root_dir, &proj_dir)?;
+    if restored.scan_rules != *scan_rules {
+        return Err("scan rules changed".to_string());
+    }
+
+    // 本体の保存以降の差分を積む。token が合わない (bak から戻した等)・ログが無い
+    // 場合は使わずに空のログを作り直す。失った差分は直後の再スキャンが拾い直して
+    // 新しいログに追記する
+    let delta = delta_path(&proj_dir);
+    let hash = root_hash(root_dir);
+    match replay_delta(&delta, &hash, restored.segments_token, &mut restored) {
+        Ok(n) => trace!("replay delta OK records={n}"),
+        Err(e) => {
+            trace!("replay delta SKIPPED: {e}");
+            if let Err(e) = start_delta_log(&delta, &hash, restored.segments_token) {
+                trace!("start delta log FAILED: {e}");
+            }
+        }
+    }
+
+    Ok(restored)
+}
+
+fn read_decode_any(
+    final_path: &Path,
+    bak_path: &Path,
+    root_dir: &Path,
+    proj_dir: &Path,
+) -> Result<RestoredCache, String> {
+    match read_decode(final_path, root_dir, proj_dir) {
         Ok(v) => {
             trace!("try_restore OK (final)");
             Ok(v)
@@ -206,7 +371,7 @@ pub fn try_restore(app: &AppHandle, root_dir: &Path) -> Result<RestoredCache, St
         Err(e_final) => {
             trace!("try_restore FAILED (final): {e_final}");
             if bak_path.exists() {
-                match read_decode(&bak_path, root_dir) {
+                match read_decode(bak_path, root_dir, proj_dir) {
                     Ok(v) => {
                         trace!("try_restore OK (bak)");
                         Ok(v)
@@ -223,7 +388,7 @@ pub fn try_restore(app: &AppHandle, root_dir: &Path) -> Result<RestoredCache, St
     }
 }
 
-fn read_decode(path: &Path, root_dir: &Path) -> Result<RestoredCache, String> {
+fn read_decode(path: &Path, root_dir: &Path, proj_dir: &Path) -> Result<RestoredCache, String> {
     trace!("read_decode path={}", path.display());
     let bytes = fs::read(path).map_err(|e| {
         let msg = format!("read failed {}: {e}", path.display());
@@ -236,7 +401,7 @@ fn read_decode(path: &Path, root_dir: &Path) -> Result<RestoredCache, String> {
         msg
     })?;
     trace!("zstd decode OK bytes={}", decompressed.len());
-    decode_all(&decompressed, root_dir).map_err(|e| {
+    decode_all(&decompressed, root_dir, proj_dir).map_err(|e| {
         trace!("decode_all FAILED: {e}");
         e
     })
@@ -246,8 +411,20 @@ fn read_decode(path: &Path, root_dir: &Path) -> Result<RestoredCache, String> {
 // compaction
 // --------------------
 
-fn compact_all_buckets(snap: &IndexSnapshot) -> [Vec<(PositionKey, Occurrence)>; 256] {
-    std::array::from_fn(|b| compact_bucket(b, &snap.buckets[b], snap.file_table.as_ref()))
+/// board index は hit 数が少ない用途なので、alive を集めて sort するだけで済ます。
+fn compact_board_bucket(segs: &[SegmentArc], ft: &FileTable) -> Vec<BoardEntry> {
+    let mut out: Vec<BoardEntry> = Vec::new();
+    for seg in segs {
+        let seg = seg.in_memory();
+        for i in 0..seg.len() {
+            let occ = seg.occ_at(i);
+            if ft.is_occ_alive(occ.file_id, occ.r#gen) {
+                out.push((seg.key_at(i), occ, seg.hands_at(i).unwrap_or(0)));
+            }
+        }
+    }
+    out.sort_by_key(|(k, occ, _)| (k.z0, k.z1, occ.file_id, occ.node_id));
+    out
 }
 
 #[derive(Clone, Copy)]
@@ -286,14 +463,12 @@ impl PartialEq for HeapItem {
 }
 impl Eq for HeapItem {}
 
-fn compact_bucket(
-    bucket_idx: usize,
-    segs: &[SegmentArc],
-    ft: &FileTable,
-) -> Vec<(PositionKey, Occurrence)> {
+fn compact_bucket(segs: &[SegmentArc], ft: &FileTable) -> Vec<(PositionKey, Occurrence)> {
     if segs.is_empty() {
         return Vec::new();
     }
+    // on-disk セグメントは列ごと読んでから merge する
+    let segs: Vec<SegmentArc> = segs.iter().map(|s| s.in_memory()).collect();
 
     let mut heap = BinaryHeap::<HeapItem>::new();
 
@@ -332,34 +507,28 @@ fn compact_bucket(
         }
     }
 
-    let _ = bucket_idx;
     out
 }
 
 // --------------------
 // binary encode/decode
 // --------------------
-fn encode_all(
-    w: &mut Vec<u8>,
-    ctx: &EncodeCtx<'_>,
-    buckets: &[Vec<(PositionKey, Occurrence)>; 256],
-) -> Result<(), String> {
+fn encode_all(w: &mut Vec<u8>, ctx: &EncodeCtx<'_>) -> Result<(), String> {
     w.extend_from_slice(&MAGIC);
     write_u32(w, VERSION);
     write_u64(w, now_ms());
 
     let rh = root_hash(ctx.root_dir);
     w.extend_from_slice(&rh);
+    w.extend_from_slice(ctx.scan_rules);
+    write_u64(w, ctx.segments_token);
 
     // file_table
     let mut entries: Vec<FileEntry> = ctx.ft.iter_all().map(|(_, e)| e).collect();
     entries.sort_by_key(|e| e.file_id);
     write_u32(w, entries.len() as u32);
     for e in &entries {
-        write_u32(w, e.file_id);
-        write_u32(w, e.r#gen);
-        write_u8(w, if e.deleted { 1 } else { 0 });
-        write_string(w, &e.path);
+        write_file_entry(w, e, ctx.ft.meta(e.file_id).as_ref());
     }
 
     // scan
@@ -367,10 +536,7 @@ fn encode_all(
     recs.sort_by(|a, b| a.path.cmp(&b.path));
     write_u32(w, recs.len() as u32);
     for r in &recs {
-        write_string(w, &r.path.to_string_lossy());
-        write_u8(w, kind_to_u8(r.kind));
-        write_u64(w, r.size);
-        write_u64(w, r.mtime_ms as u64);
+        write_file_record(w, r);
     }
 
     // path_to_id + next_file_id
@@ -381,46 +547,12 @@ fn encode_all(
         write_u32(w, *id);
     }
 
-    // node tables
-    let mut nt_items: Vec<(FileId, Arc<NodeTable>)> = Vec::new();
-    for (i, opt) in ctx.nts.by_id_iter().enumerate() {
-        if let Some(nt) = opt {
-            nt_items.push((i as u32, nt.clone()));
-        }
-    }
-    write_u32(w, nt_items.len() as u32);
-    for (file_id, nt) in nt_items {
-        write_u32(w, file_id);
-        write_u32(w, nt.nodes.len() as u32);
-        write_u32(w, nt.forks.len() as u32);
-        for n in &nt.nodes {
-            write_u32(w, n.tesuu);
-            write_u32(w, n.fork_off);
-            write_u16(w, n.fork_len);
-            write_u16(w, 0);
-        }
-        for f in &nt.forks {
-            write_u32(w, f.te);
-            write_u32(w, f.fork_index);
-        }
-    }
-
-    // buckets
-    for v in buckets.iter() {
-        write_u32(w, v.len() as u32);
-        for (k, occ) in v {
-            write_u64(w, k.z0);
-            write_u64(w, k.z1);
-            write_u32(w, occ.file_id);
-            write_u32(w, occ.r#gen);
-            write_u32(w, occ.node_id);
-        }
-    }
+    // node tables はセグメントファイル側に書く
 
     Ok(())
 }
 
-fn decode_all(bytes: &[u8], root_dir: &Path) -> Result<RestoredCache, String> {
+fn decode_all(bytes: &[u8], root_dir: &Path, proj_dir: &Path) -> Result<RestoredCache, String> {
     let mut r = Reader::new(bytes);
 
     let magic = r.read_fixed::<8>()?;
@@ -441,36 +573,25 @@ fn decode_all(bytes: &[u8], root_dir: &Path) -> Result<RestoredCache, String> {
     if saved_root_hash != expect {
         return Err("root hash mismatch (different project root)".to_string());
     }
+    let scan_rules = r.read_fixed::<32>()?;
+    let segments_token = r.read_u64()?;
     // ---- file_table ----
     let ft_len = r.read_u32()? as usize;
     let mut ft = FileTable::default();
     for _ in 0..ft_len {
-        let file_id = r.read_u32()?;
-        let gen_val = r.read_u32()?;
-        let deleted = r.read_u8()? != 0;
-        let path = r.read_string()?;
-        ft.upsert(FileEntry {
-            file_id,
-            r#gen: gen_val,
-            deleted,
-            path,
-        });
+        let (entry, meta) = read_file_entry(&mut r)?;
+        let file_id = entry.file_id;
+        ft.upsert(entry);
+        if let Some(meta) = meta {
+            ft.set_meta(file_id, &meta);
+        }
     }
 
     // ---- scan snapshot ----
     let rec_len = r.read_u32()? as usize;
     let mut records: Vec<FileRecord> = Vec::with_capacity(rec_len);
     for _ in 0..rec_len {
-        let path = PathBuf::from(r.read_string()?);
-        let kind = u8_to_kind(r.read_u8()?)?;
-        let size = r.read_u64()?;
-        let mtime_ms = r.read_u64()? as u128;
-        records.push(FileRecord {
-            path,
-            kind,
-            size,
-            mtime_ms,
-        });
+        records.push(read_file_record(&mut r)?);
     }
 
     let scan = snapshot_from_records(root_dir, records);
@@ -485,67 +606,20 @@ fn decode_all(bytes: &[u8], root_dir: &Path) -> Result<RestoredCache, String> {
         path_to_id.insert(p, id);
     }
 
-    // ---- node tables ----
-    let nt_len = r.read_u32()? as usize;
-    let mut nts = NodeTables::default();
-    for _ in 0..nt_len {
-        let file_id = r.read_u32()?;
-        let nodes_len = r.read_u32()? as usize;
-        let forks_len = r.read_u32()? as usize;
-
-        let mut nt = NodeTable::empty();
-        nt.nodes.reserve(nodes_len);
-        nt.forks.reserve(forks_len);
-
-        for _ in 0..nodes_len {
-            let tesuu = r.read_u32()?;
-            let fork_off = r.read_u32()?;
-            let fork_len = r.read_u16()?;
-            let _pad = r.read_u16()?;
-            nt.nodes.push(super::node_table::NodeCursor {
-                tesuu,
-                fork_off,
-                fork_len,
-            });
-        }
-        for _ in 0..forks_len {
-            let te = r.read_u32()?;
-            let fork_index = r.read_u32()?;
-            nt.forks.push(super::node_table::ForkPtr { te, fork_index });
-        }
+    // ---- segments / node tables (mmap して開くだけ) ----
+    let segs = open_segment_file(
+        &segments_path(proj_dir, segments_token),
+        &expect,
+        segments_token,
+    )?;
 
-        nts.upsert(file_id, Arc::new(nt));
-    }
-
-    // ---- buckets ----
-    let mut buckets: [Vec<(PositionKey, Occurrence)>; 256] = std::array::from_fn(|_| Vec::new());
-    for bucket in buckets.iter_mut() {
-        let n = r.read_u32()? as usize;
-        let mut v = Vec::with_capacity(n);
-        for _ in 0..n {
-            let z0 = r.read_u64()?;
-            let z1 = r.read_u64()?;
-            let file_id = r.read_u32()?;
-            let gen_val = r.read_u32()?;
-            let node_id = r.read_u32()?;
-            v.push((
-                PositionKey { z0, z1 },
-                Occurrence {
-                    file_id,
-                    r#gen: gen_val,
-                    node_id,
-                },
-            ));
-        }
-        *bucket = v;
-    }
-    let total_bucket_entries: usize = buckets.iter().map(|v| v.len()).sum();
-    let nt_some: usize = nts.by_id_iter().filter(|x| x.is_some()).count();
+    let total_bucket_entries: usize = segs.buckets.iter().flatten().map(|seg| seg.len()).sum();
+    let nts = NodeTables::on_disk(segs.node_tables);
 
     log::info!(
-    "[index_cache] restored stats: file_table_len={} node_tables_some={} scan_paths={} path_to_id_len={} next_file_id={} bucket_entries_total={}",
+    "[index_cache] restored stats: file_table_len={} node_table_ids={} scan_paths={} path_to_id_len={} next_file_id={} bucket_entries_total={}",
     ft.len(),
-    nt_some,
+    nts.id_bound(),
     scan.by_path.len(),
     path_to_id.len(),
     next_file_id,
@@ -555,10 +629,13 @@ fn decode_all(bytes: &[u8], root_dir: &Path) -> Result<RestoredCache, String> {
     Ok(RestoredCache {
         file_table: ft,
         node_tables: nts,
-        buckets,
+        buckets: segs.buckets,
+        board_buckets: segs.board_buckets,
         scan,
         path_to_id,
         next_file_id,
+        segments_token,
+        scan_rules,
     })
 }
 
@@ -584,36 +661,183 @@ fn u8_to_kind(v: u8) -> Result<KifuKind, String> {
     })
 }
 
+fn result_to_u8(r: GameResult) -> u8 {
+    match r {
+        GameResult::Unknown => 0,
+        GameResult::BlackWin => 1,
+        GameResult::WhiteWin => 2,
+        GameResult::Draw => 3,
+    }
+}
+fn u8_to_result(v: u8) -> Result<GameResult, String> {
+    Ok(match v {
+        0 => GameResult::Unknown,
+        1 => GameResult::BlackWin,
+        2 => GameResult::WhiteWin,
+        3 => GameResult::Draw,
+        _ => return Err(format!("bad game result: {v}")),
+    })
+}
+
 // FileTable から全エントリを列挙したいので helper を FileTable に追加する（Step5参照）
 
-fn write_u8(w: &mut Vec<u8>, v: u8) {
+pub(super) fn write_file_entry(w: &mut Vec<u8>, e: &FileEntry, meta: Option<&GameMeta>) {
+    write_u32(w, e.file_id);
+    write_u32(w, e.r#gen);
+    write_u8(w, if e.deleted { 1 } else { 0 });
+    write_string(w, &e.path);
+    match meta {
+        Some(m) => {
+            write_u8(w, 1);
+            write_opt_string(w, m.black.as_deref());
+            write_opt_string(w, m.white.as_deref());
+            write_opt_string(w, m.event.as_deref());
+            write_opt_string(w, m.start_date.as_deref());
+            write_u8(w, result_to_u8(m.result));
+        }
+        None => write_u8(w, 0),
+    }
+    write_opt_string(w, e.error.as_deref());
+}
+
+pub(super) fn read_file_entry(r: &mut Reader<'_>) -> Result<(FileEntry, Option<GameMeta>), String> {
+    let file_id = r.read_u32()?;
+    let gen_val = r.read_u32()?;
+    let deleted = r.read_u8()? != 0;
+    let path = r.read_string()?;
+    let meta = if r.read_u8()? != 0 {
+        Some(GameMeta {
+            black: r.read_opt_string()?,
+            white: r.read_opt_string()?,
+            event: r.read_opt_string()?,
+            start_date: r.read_opt_string()?,
+            result: u8_to_result(r.read_u8()?)?,
+        })
+    } else {
+        None
+    };
+    let entry = FileEntry {
+        file_id,
+        r#gen: gen_val,
+        deleted,
+        path,
+        error: r.read_opt_string()?,
+    };
+    Ok((entry, meta))
+}
+
+pub(super) fn write_file_record(w: &mut Vec<u8>, rec: &FileRecord) {
+    write_string(w, &rec.path.to_string_lossy());
+    write_u8(w, kind_to_u8(rec.kind));
+    write_u64(w, rec.size);
+    write_u64(w, rec.mtime_ms as u64);
+    match &rec.hash {
+        Some(h) => {
+            write_u8(w, 1);
+            w.extend_from_slice(h);
+        }
+        None => write_u8(w, 0),
+    }
+    write_u64(w, rec.hashed_at_ms);
+}
+
+pub(super) fn read_file_record(r: &mut Reader<'_>) -> Result<FileRecord, String> {
+    Ok(FileRecord {
+        path: PathBuf::from(r.read_string()?),
+        kind: u8_to_kind(r.read_u8()?)?,
+        size: r.read_u64()?,
+        mtime_ms: r.read_u64()? as u128,
+        hash: if r.read_u8()? != 0 {
+            Some(r.read_fixed::<32>()?)
+        } else {
+            None
+        },
+        hashed_at_ms: r.read_u64()?,
+    })
+}
+
+pub(super) fn write_node_table(w: &mut Vec<u8>, nt: &NodeTable) {
+    write_u32(w, nt.nodes.len() as u32);
+    write_u32(w, nt.forks.len() as u32);
+    for n in &nt.nodes {
+        write_u32(w, n.tesuu);
+        write_u32(w, n.fork_off);
+        write_u16(w, n.fork_len);
+        write_u16(w, n.flags);
+    }
+    for f in &nt.forks {
+        write_u32(w, f.te);
+        write_u32(w, f.fork_index);
+    }
+}
+
+pub(super) fn read_node_table(r: &mut Reader<'_>) -> Result<NodeTable, String> {
+    let nodes_len = r.read_u32()? as usize;
+    let forks_len = r.read_u32()? as usize;
+
+    let mut nt = NodeTable::empty();
+    nt.nodes.reserve(nodes_len);
+    nt.forks.reserve(forks_len);
+
+    for _ in 0..nodes_len {
+        let tesuu = r.read_u32()?;
+        let fork_off = r.read_u32()?;
+        let fork_len = r.read_u16()?;
+        let flags = r.read_u16()?;
+        nt.nodes.push(super::node_table::NodeCursor {
+            tesuu,
+            fork_off,
+            fork_len,
+            flags,
+        });
+    }
+    for _ in 0..forks_len {
+        let te = r.read_u32()?;
+        let fork_index = r.read_u32()?;
+        nt.forks.push(super::node_table::ForkPtr { te, fork_index });
+    }
+
+    Ok(nt)
+}
+
+pub(super) fn write_u8(w: &mut Vec<u8>, v: u8) {
     w.push(v);
 }
-fn write_u16(w: &mut Vec<u8>, v: u16) {
+pub(super) fn write_u16(w: &mut Vec<u8>, v: u16) {
     w.extend_from_slice(&v.to_le_bytes());
 }
-fn write_u32(w: &mut Vec<u8>, v: u32) {
+pub(super) fn write_u32(w: &mut Vec<u8>, v: u32) {
     w.extend_from_slice(&v.to_le_bytes());
 }
-fn write_u64(w: &mut Vec<u8>, v: u64) {
+pub(super) fn write_u64(w: &mut Vec<u8>, v: u64) {
     w.extend_from_slice(&v.to_le_bytes());
 }
 
-fn write_string(w: &mut Vec<u8>, s: &str) {
+pub(super) fn write_string(w: &mut Vec<u8>, s: &str) {
     let b = s.as_bytes();
     write_u32(w, b.len() as u32);
     w.extend_from_slice(b);
 }
 
-struct Reader<'a> {
+pub(super) fn write_opt_string(w: &mut Vec<u8>, s: Option<&str>) {
+    match s {
+        Some(s) => {
+            write_u8(w, 1);
+            write_string(w, s);
+        }
+        None => write_u8(w, 0),
+    }
+}
+
+pub(super) struct Reader<'a> {
     b: &'a [u8],
     i: usize,
 }
 impl<'a> Reader<'a> {
-    fn new(b: &'a [u8]) -> Self {
+    pub(super) fn new(b: &'a [u8]) -> Self {
         Self { b, i: 0 }
     }
-    fn read_u8(&mut self) -> Result<u8, String> {
+    pub(super) fn read_u8(&mut self) -> Result<u8, String> {
         if self.i + 1 > self.b.len() {
             return Err("unexpected eof".to_string());
         }
@@ -621,19 +845,19 @@ impl<'a> Reader<'a> {
         self.i += 1;
         Ok(v)
     }
-    fn read_u16(&mut self) -> Result<u16, String> {
+    pub(super) fn read_u16(&mut self) -> Result<u16, String> {
         let a = self.read_fixed::<2>()?;
         Ok(u16::from_le_bytes(a))
     }
-    fn read_u32(&mut self) -> Result<u32, String> {
+    pub(super) fn read_u32(&mut self) -> Result<u32, String> {
         let a = self.read_fixed::<4>()?;
         Ok(u32::from_le_bytes(a))
     }
-    fn read_u64(&mut self) -> Result<u64, String> {
+    pub(super) fn read_u64(&mut self) -> Result<u64, String> {
         let a = self.read_fixed::<8>()?;
         Ok(u64::from_le_bytes(a))
     }
-    fn read_string(&mut self) -> Result<String, String> {
+    pub(super) fn read_string(&mut self) -> Result<String, String> {
         let n = self.read_u32()? as usize;
         if self.i + n > self.b.len() {
             return Err("unexpected eof".to_string());
@@ -642,7 +866,13 @@ impl<'a> Reader<'a> {
         self.i += n;
         Ok(s.to_string())
     }
-    fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N], String> {
+    pub(super) fn read_opt_string(&mut self) -> Result<Option<String>, String> {
+        if self.read_u8()? == 0 {
+            return Ok(None);
+        }
+        self.read_string().map(Some)
+    }
+    pub(super) fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N], String> {
         if self.i + N > self.b.len() {
             return Err("unexpected eof".to_string());
         }
@@ -7,12 +7,20 @@ use crate::search::{node_table::NodeTableArc, types::Occurrence};
 use super::{
     file_table::FileTable,
     position_key::PositionKey,
-    segment::{Segment, SegmentArc},
-    types::{FileEntry, FileId},
+    segment::{BoardEntry, Segment, SegmentArc},
+    segment_file::DiskNodeTables,
+    types::{FileEntry, FileId, GameMeta},
 };
 
 pub type BucketEntries = [Vec<(PositionKey, Occurrence)>; 256];
-pub type FileBucketEntries = (FileEntry, NodeTableArc, BucketEntries);
+pub type BoardBucketEntries = [Vec<BoardEntry>; 256];
+pub type FileBucketEntries = (
+    FileEntry,
+    NodeTableArc,
+    BucketEntries,
+    BoardBucketEntries,
+    GameMeta,
+);
 
 /// bucket 内のセグメント数がこれを超えたら k-way merge で 1 本に圧縮する。
 const COMPACT_THRESHOLD: usize = 64;
@@ -26,14 +34,38 @@ pub enum IndexState {
     Updating,
 }
 
+impl From<IndexState> for super::types::IndexState {
+    fn from(s: IndexState) -> Self {
+        match s {
+            IndexState::Empty => Self::Empty,
+            IndexState::Restoring => Self::Restoring,
+            IndexState::Building => Self::Building,
+            IndexState::Ready => Self::Ready,
+            IndexState::Updating => Self::Updating,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct NodeTables {
     by_id: Vec<Option<NodeTableArc>>,
+    /// cache から復元した分。`by_id` に無い file_id はここから 1 件ずつ読む
+    disk: Option<Arc<DiskNodeTables>>,
 }
 
 impl NodeTables {
-    pub fn get(&self, file_id: FileId) -> Option<&NodeTableArc> {
-        self.by_id.get(file_id as usize)?.as_ref()
+    pub fn on_disk(disk: DiskNodeTables) -> Self {
+        Self {
+            by_id: Vec::new(),
+            disk: Some(Arc::new(disk)),
+        }
+    }
+
+    pub fn get(&self, file_id: FileId) -> Option<NodeTableArc> {
+        if let Some(Some(nt)) = self.by_id.get(file_id as usize) {
+            return Some(nt.clone());
+        }
+        self.disk.as_ref()?.get(file_id).map(Arc::new)
     }
 
     pub fn upsert(&mut self, file_id: FileId, nt: NodeTableArc) {
@@ -44,8 +76,20 @@ impl NodeTables {
         self.by_id[idx] = Some(nt);
     }
 
-    pub fn by_id_iter(&self) -> impl Iterator<Item = &Option<NodeTableArc>> {
-        self.by_id.iter()
+    /// 持ちうる file_id の上限
+    pub fn id_bound(&self) -> usize {
+        let disk = self.disk.as_ref().map_or(0, |d| d.id_bound());
+        self.by_id.len().max(disk)
+    }
+
+    /// 全 node table のノード数の合計
+    pub fn total_nodes(&self) -> u64 {
+        (0..self.id_bound() as FileId)
+            .map(|id| match self.by_id.get(id as usize) {
+                Some(Some(nt)) => nt.nodes.len() as u64,
+                _ => self.disk.as_ref().map_or(0, |d| d.nodes_len(id) as u64),
+            })
+            .sum()
     }
 }
 
@@ -55,6 +99,8 @@ pub struct IndexSnapshot {
     pub file_table: Arc<FileTable>,
     pub node_tables: Arc<NodeTables>,
     pub buckets: [Vec<SegmentArc>; 256],
+    /// 持ち駒を無視した盤面キーの index (hands 列つきセグメント)
+    pub board_buckets: [Vec<SegmentArc>; 256],
 }
 
 impl Default for IndexSnapshot {
@@ -70,6 +116,7 @@ impl IndexSnapshot {
             file_table: Arc::new(FileTable::default()),
             node_tables: Arc::new(NodeTables::default()),
             buckets: std::array::from_fn(|_| Vec::new()),
+            board_buckets: std::array::from_fn(|_| Vec::new()),
         }
     }
 
@@ -78,38 +125,53 @@ impl IndexSnapshot {
     /// - alive 判定は file_table の O(1) 配列アクセス
     /// - segments が 2 本以上のときは k-way merge で `(file_id, node_id)` 昇順に出す
     pub fn search_occurrences_by_key(&self, key: PositionKey) -> Vec<Occurrence> {
+        let mut out: Vec<Occurrence> = Vec::new();
+        self.for_each_alive_hit(&self.buckets, key, |seg, idx| out.push(seg.occ_at(idx)));
+        out
+    }
+
+    /// 持ち駒を無視した盤面キーで検索し、各 hit の持ち駒 (pack_hands 形式) も返す。
+    pub fn search_board_occurrences(&self, board_key: PositionKey) -> Vec<(Occurrence, u64)> {
+        let mut out: Vec<(Occurrence, u64)> = Vec::new();
+        self.for_each_alive_hit(&self.board_buckets, board_key, |seg, idx| {
+            out.push((seg.occ_at(idx), seg.hands_at(idx).unwrap_or(0)));
+        });
+        out
+    }
+
+    /// `buckets` から key に一致する alive なエントリを `(file_id, node_id)` 昇順で
+    /// `f(segment, idx)` に渡す。
+    fn for_each_alive_hit<F>(&self, buckets: &[Vec<SegmentArc>; 256], key: PositionKey, mut f: F)
+    where
+        F: FnMut(&Segment, usize),
+    {
         let bucket = key.bucket() as usize;
-        let segs = &self.buckets[bucket];
+        let segs = &buckets[bucket];
 
         let mut ranges: Vec<(SegmentArc, usize, usize)> = Vec::with_capacity(segs.len());
-        let mut estimated = 0usize;
         for seg in segs {
             let (lo, hi) = seg.range_by_key(key);
             if lo < hi {
-                estimated += hi - lo;
                 ranges.push((seg.clone(), lo, hi));
             }
         }
 
-        let mut out: Vec<Occurrence> = Vec::with_capacity(estimated);
-
         if ranges.len() <= 1 {
             for (seg, lo, hi) in &ranges {
                 for i in *lo..*hi {
                     let occ = seg.occ_at(i);
                     if self.file_table.is_occ_alive(occ.file_id, occ.r#gen) {
-                        out.push(occ);
+                        f(seg.as_ref(), i);
                     }
                 }
             }
-            return out;
+            return;
         }
 
         #[derive(Clone, Copy)]
         struct HeapItem {
             file_id: u32,
             node_id: u32,
-            occ: Occurrence,
             ri: usize,
             idx: usize,
         }
@@ -144,7 +206,6 @@ impl IndexSnapshot {
                     heap.push(HeapItem {
                         file_id: occ.file_id,
                         node_id: occ.node_id,
-                        occ,
                         ri,
                         idx,
                     });
@@ -159,12 +220,10 @@ impl IndexSnapshot {
         }
 
         while let Some(item) = heap.pop() {
-            out.push(item.occ);
             let (seg, _lo, hi) = &ranges[item.ri];
+            f(seg.as_ref(), item.idx);
             push_first_alive(&mut heap, item.ri, seg, item.idx + 1, *hi, &self.file_table);
         }
-
-        out
     }
 }
 
@@ -191,31 +250,26 @@ impl IndexStore {
             file_table: Arc::new(FileTable::default()),
             node_tables: Arc::new(NodeTables::default()),
             buckets: std::array::from_fn(|_| Vec::new()),
+            board_buckets: std::array::from_fn(|_| Vec::new()),
         })
     }
 
+    /// cache から復元したセグメント (セグメントファイル上の on-disk セグメント) を載せる。
     pub fn install_restored(
         &self,
         state: IndexState,
         file_table: FileTable,
         node_tables: NodeTables,
-        mut buckets_entries: [Vec<(PositionKey, Occurrence)>; 256],
+        buckets: [Vec<SegmentArc>; 256],
+        board_buckets: [Vec<SegmentArc>; 256],
     ) {
-        let buckets: [Vec<SegmentArc>; 256] = std::array::from_fn(|i| {
-            let v = std::mem::take(&mut buckets_entries[i]);
-            if v.is_empty() {
-                Vec::new()
-            } else {
-                vec![Arc::new(Segment::new_sorted(v))]
-            }
-        });
-
         let mut guard = self.snap.write();
         *guard = Arc::new(IndexSnapshot {
             state,
             file_table: Arc::new(file_table),
             node_tables: Arc::new(node_tables),
             buckets,
+            board_buckets,
         });
     }
 
@@ -226,6 +280,7 @@ impl IndexStore {
             file_table: Arc::new(FileTable::default()),
             node_tables: Arc::new(NodeTables::default()),
             buckets: std::array::from_fn(|_| Vec::new()),
+            board_buckets: std::array::from_fn(|_| Vec::new()),
         });
     }
 
@@ -237,6 +292,7 @@ impl IndexStore {
             file_table: old.file_table.clone(),
             node_tables: old.node_tables.clone(),
             buckets: old.buckets.clone(),
+            board_buckets: old.board_buckets.clone(),
         });
     }
 
@@ -246,8 +302,10 @@ impl IndexStore {
         file_entry: FileEntry,
         nt: NodeTableArc,
         by_bucket: BucketEntries,
+        board_by_bucket: BoardBucketEntries,
+        meta: GameMeta,
     ) {
-        self.insert_many_file_segments(vec![(file_entry, nt, by_bucket)]);
+        self.insert_many_file_segments(vec![(file_entry, nt, by_bucket, board_by_bucket, meta)]);
     }
 
     pub fn insert_many_file_segments(&self, items: Vec<FileBucketEntries>) {
@@ -261,10 +319,13 @@ impl IndexStore {
         let mut ft = (*old.file_table).clone();
         let mut nts = (*old.node_tables).clone();
         let mut buckets = old.buckets.clone();
+        let mut board_buckets = old.board_buckets.clone();
         let mut touched: Vec<bool> = vec![false; 256];
+        let mut board_touched: Vec<bool> = vec![false; 256];
 
-        for (file_entry, nt, by_bucket) in items {
+        for (file_entry, nt, by_bucket, board_by_bucket, meta) in items {
             ft.upsert(file_entry.clone());
+            ft.set_meta(file_entry.file_id, &meta);
             nts.upsert(file_entry.file_id, nt);
 
             for (b, v) in by_bucket.into_iter().enumerate() {
@@ -274,23 +335,48 @@ impl IndexStore {
                 buckets[b].push(Arc::new(Segment::new_sorted(v)));
                 touched[b] = true;
             }
-        }
 
-        for (b, is_touched) in touched.iter().enumerate() {
-            if *is_touched && buckets[b].len() > COMPACT_THRESHOLD {
-                if let Some(merged) = compact_bucket(&buckets[b], &ft) {
-                    buckets[b] = vec![Arc::new(merged)];
-                } else {
-                    buckets[b].clear();
+            for (b, v) in board_by_bucket.into_iter().enumerate() {
+                if v.is_empty() {
+                    continue;
                 }
+                board_buckets[b].push(Arc::new(Segment::new_sorted_with_hands(v)));
+                board_touched[b] = true;
             }
         }
 
+        compact_touched(&mut buckets, &touched, &ft);
+        compact_touched(&mut board_buckets, &board_touched, &ft);
+
         *guard = Arc::new(IndexSnapshot {
             state: old.state,
             file_table: Arc::new(ft),
             node_tables: Arc::new(nts),
             buckets,
+            board_buckets,
+        });
+    }
+
+    /// 内容が同じままの移動。gen を変えないので既存セグメントはそのまま生きる。
+    /// FileTable の clone を 1 回で済ますため、まとめて受け取る
+    pub fn rename_files(&self, renames: &[(FileId, String)]) {
+        if renames.is_empty() {
+            return;
+        }
+        let mut guard = self.snap.write();
+        let old = guard.clone();
+
+        let mut ft = (*old.file_table).clone();
+        for (file_id, path) in renames {
+            ft.rename(*file_id, path.clone());
+        }
+
+        *guard = Arc::new(IndexSnapshot {
+            state: old.state,
+            file_table: Arc::new(ft),
+            node_tables: old.node_tables.clone(),
+            buckets: old.buckets.clone(),
+            board_buckets: old.board_buckets.clone(),
         });
     }
 
@@ -306,16 +392,31 @@ impl IndexStore {
             file_table: Arc::new(ft),
             node_tables: old.node_tables.clone(),
             buckets: old.buckets.clone(),
+            board_buckets: old.board_buckets.clone(),
         });
     }
 }
 
+fn compact_touched(buckets: &mut [Vec<SegmentArc>; 256], touched: &[bool], ft: &FileTable) {
+    for (b, is_touched) in touched.iter().enumerate() {
+        if *is_touched && buckets[b].len() > COMPACT_THRESHOLD {
+            if let Some(merged) = compact_bucket(&buckets[b], ft) {
+                buckets[b] = vec![Arc::new(merged)];
+            } else {
+                buckets[b].clear();
+            }
+        }
+    }
+}
+
 /// bucket 内の全 segment を k-way merge し、alive Occurrence のみ残した 1 本の
 /// Segment を返す。エントリが 1 件もなければ None。
 fn compact_bucket(segs: &[SegmentArc], ft: &FileTable) -> Option<Segment> {
     if segs.is_empty() {
         return None;
     }
+    // on-disk セグメントは 1 件ずつ読むと遅いので、先に列ごと読んでおく
+    let segs: Vec<SegmentArc> = segs.iter().map(|s| s.in_memory()).collect();
 
     #[derive(Clone, Copy)]
     struct HeapItem {
@@ -350,6 +451,9 @@ fn compact_bucket(segs: &[SegmentArc], ft: &FileTable) -> Option<Segment> {
     let mut file_ids = Vec::with_capacity(total);
     let mut gens = Vec::with_capacity(total);
     let mut node_ids = Vec::with_capacity(total);
+    // board index の bucket なら hands 列も引き継ぐ
+    let with_hands = segs.iter().any(|s| s.has_hands());
+    let mut hands = Vec::with_capacity(if with_hands { total } else { 0 });
 
     let mut heap = BinaryHeap::<HeapItem>::with_capacity(segs.len());
     for (si, seg) in segs.iter().enumerate() {
@@ -374,6 +478,9 @@ fn compact_bucket(segs: &[SegmentArc], ft: &FileTable) -> Option<Segment> {
             file_ids.push(occ.file_id);
             gens.push(occ.r#gen);
             node_ids.push(occ.node_id);
+            if with_hands {
+                hands.push(seg.hands_at(item.idx).unwrap_or(0));
+            }
         }
 
         let next = item.idx + 1;
@@ -391,6 +498,6 @@ fn compact_bucket(segs: &[SegmentArc], ft: &FileTable) -> Option<Segment> {
     if z0.is_empty() {
         None
     } else {
-        Some(Segment::from_soa(z0, z1, file_ids, gens, node_ids))
+        Some(Segment::from_soa(z0, z1, file_ids, gens, node_ids, hands))
     }
 }
@@ -1,17 +1,32 @@
 pub mod api;
+pub mod delta_log;
+pub mod export;
 pub mod file_table;
 pub mod fs_scan;
+pub mod game_meta;
+pub mod grouping;
 pub mod index_builder;
 pub mod index_cache;
 pub mod index_store;
 pub mod initial_position;
 pub mod kifu_reader;
+pub mod move_stats;
 pub mod node_table;
+pub mod opening_tree;
+pub mod pattern;
 pub mod position_apply;
 pub mod position_key;
+pub mod position_transform;
 pub mod project_manager;
 pub mod query_service;
+pub mod roots;
+pub mod rules;
 pub mod segment;
+pub mod segment_file;
+pub mod sequence;
 pub mod sfen_position;
+pub mod stats;
+pub mod transposition;
 pub mod traverse;
 pub mod types;
+pub mod validate;
@@ -0,0 +1,109 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use shogi_core::PartialPosition;
+
+use super::{
+    fs_scan::KifuKind,
+    game_meta::game_result,
+    index_store::IndexSnapshot,
+    kifu_reader::read_path_to_jkf,
+    position_apply::jkf_move_to_usi,
+    position_key::{board_key_from_partial_position, key_from_partial_position},
+    roots::RootSnapshots,
+    sfen_position::{partial_position_from_sfen, SfenParseError},
+    traverse::next_moves_in_jkf,
+    types::{FileId, MoveStat, MoveStatsInput, MoveStatsOutput, NodeId, ResultCounts},
+};
+
+/// SFEN の局面について、開いている全 root の出現箇所から次の一手と勝敗を集計する。
+///
+/// 出現箇所はファイルごとにまとめ、棋譜は 1 回だけ読み直す。
+/// 読めない / index 時から変わってノードを辿れないファイルは飛ばす。
+pub fn collect_move_stats(
+    snaps: &RootSnapshots,
+    input: &MoveStatsInput,
+) -> Result<MoveStatsOutput, SfenParseError> {
+    let pos = partial_position_from_sfen(&input.sfen)?;
+
+    let mut out = MoveStatsOutput::default();
+    let mut stats: HashMap<String, MoveStat> = HashMap::new();
+    for (_, snap) in snaps {
+        collect_in_snapshot(snap, &pos, input.ignore_hands, &mut out, &mut stats);
+    }
+
+    let mut moves: Vec<MoveStat> = stats.into_values().collect();
+    moves.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.usi.cmp(&b.usi)));
+    out.moves = moves;
+
+    Ok(out)
+}
+
+/// 1 つの root の snapshot 分を `out` / `stats` に足し込む
+fn collect_in_snapshot(
+    snap: &IndexSnapshot,
+    pos: &PartialPosition,
+    ignore_hands: bool,
+    out: &mut MoveStatsOutput,
+    stats: &mut HashMap<String, MoveStat>,
+) {
+    let occs = if ignore_hands {
+        snap.search_board_occurrences(board_key_from_partial_position(pos))
+            .into_iter()
+            .map(|(occ, _)| occ)
+            .collect()
+    } else {
+        snap.search_occurrences_by_key(key_from_partial_position(pos))
+    };
+
+    let mut by_file: BTreeMap<FileId, Vec<NodeId>> = BTreeMap::new();
+    for occ in occs {
+        by_file.entry(occ.file_id).or_default().push(occ.node_id);
+    }
+
+    for (file_id, node_ids) in by_file {
+        let (Some(path), Some(nt)) = (
+            snap.file_table.get_path(file_id),
+            snap.node_tables.get(file_id),
+        ) else {
+            continue;
+        };
+        let path = Path::new(path);
+        let Some(kind) = KifuKind::from_path(path) else {
+            continue;
+        };
+        let jkf = match read_path_to_jkf(path, kind) {
+            Ok(jkf) => jkf,
+            Err(e) => {
+                log::debug!("[move_stats] skip unreadable file: {e}");
+                continue;
+            }
+        };
+
+        let result = game_result(&jkf);
+        out.total_games += 1;
+        out.results.add(result);
+
+        for node_id in node_ids {
+            out.total_hits += 1;
+            let Some(cursor) = nt.cursor_lite(node_id) else {
+                continue;
+            };
+
+            for m in next_moves_in_jkf(&jkf.moves, &cursor) {
+                let Ok(usi) = jkf_move_to_usi(m) else {
+                    continue;
+                };
+                let stat = stats.entry(usi.clone()).or_insert_with(|| MoveStat {
+                    usi,
+                    count: 0,
+                    results: ResultCounts::default(),
+                });
+                stat.count += 1;
+                stat.results.add(result);
+            }
+        }
+    }
+}
@@ -4,6 +4,11 @@ use super::types::{CursorLite, ForkPointer};
 
 pub type NodeTableArc = Arc<NodeTable>;
 
+/// 同一局面がこの系列上で 4 回目 (以降) に現れたノード (千日手)
+pub const NODE_FLAG_REPETITION: u16 = 1 << 0;
+/// 千日手のうち、一方が王手をかけ続けていたもの (連続王手の千日手)
+pub const NODE_FLAG_PERPETUAL_CHECK: u16 = 1 << 1;
+
 #[derive(Debug, Clone, Default)]
 pub struct NodeTable {
     pub nodes: Vec<NodeCursor>,
@@ -36,6 +41,14 @@ impl NodeTable {
             fork_pointers: fps,
         })
     }
+
+    /// node_id の `NODE_FLAG_*`。範囲外は 0
+    pub fn flags(&self, node_id: u32) -> u16 {
+        self.nodes
+            .get(node_id as usize)
+            .map(|n| n.flags)
+            .unwrap_or(0)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -43,6 +56,7 @@ pub struct NodeCursor {
     pub tesuu: u32,
     pub fork_off: u32,
     pub fork_len: u16,
+    pub flags: u16,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -87,11 +101,18 @@ impl NodeTableBuilder {
             tesuu,
             fork_off: off,
             fork_len: len as u16,
+            flags: 0,
         });
 
         node_id
     }
 
+    pub fn add_flags(&mut self, node_id: u32, flags: u16) {
+        if let Some(n) = self.nodes.get_mut(node_id as usize) {
+            n.flags |= flags;
+        }
+    }
+
     pub fn finish(self) -> NodeTable {
         NodeTable {
             nodes: self.nodes,
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, path::Path};
+
+use shogi_kifu_converter_obsshogi::jkf::{Initial, JsonKifuFormat, MoveFormat, MoveMoveFormat};
+
+use super::{
+    fs_scan::{scan_kifu_files, ScanOptions},
+    game_meta::game_result,
+    initial_position::initial_partial_position,
+    kifu_reader::read_to_jkf,
+    position_apply::{apply_node_action, ApplyStatus},
+    position_key::{key_from_partial_position, PositionKey},
+    traverse::NodeAction,
+    types::{GameResult, OpeningTreeInput, OpeningTreeOutput, ResultCounts},
+};
+
+/// 木の 1 ノード (= 1 手)。同じ親の下では指した後の局面キーで合流する
+#[derive(Debug)]
+struct TreeNode {
+    key: PositionKey,
+    mv: MoveMoveFormat,
+    count: u32,
+    results: ResultCounts,
+    children: Vec<TreeNode>,
+}
+
+/// 1 局分の本譜 (max_plies まで)
+struct GameLine {
+    init_key: PositionKey,
+    initial: Option<Initial>,
+    moves: Vec<(PositionKey, MoveMoveFormat)>,
+    result: GameResult,
+}
+
+/// `dir` 配下の棋譜の本譜を max_plies 手までまとめ、分岐つきの 1 つの JKF にする。
+///
+/// - 開始局面が異なる棋譜は混ぜられないので、最も多い開始局面の棋譜だけを使う
+/// - 各手の出現数が多い順に本譜 → 分岐と並べ、出現数と勝敗をコメントに書く
+/// - 木なので、手順違いで同じ局面に合流しても別ノードのまま
+pub fn build_opening_tree(
+    input: &OpeningTreeInput,
+    opts: &ScanOptions,
+) -> Result<OpeningTreeOutput, String> {
+    let records = scan_kifu_files(Path::new(&input.dir), opts).map_err(|e| e.to_string())?;
+
+    let max_plies = input.max_plies.max(1) as usize;
+    let mut lines: Vec<GameLine> = Vec::with_capacity(records.len());
+    let mut skipped: u32 = 0;
+
+    for rec in &records {
+        let line = read_to_jkf(rec)
+            .map_err(|e| e.to_string())
+            .and_then(|jkf| game_line(&jkf, max_plies));
+        match line {
+            Ok(line) => lines.push(line),
+            Err(e) => {
+                log::debug!("[opening_tree] skip {}: {e}", rec.path.display());
+                skipped += 1;
+            }
+        }
+    }
+
+    // 最も多い開始局面 (同数なら先に出たもの)
+    let mut init_counts: Vec<(PositionKey, u32)> = Vec::new();
+    for l in &lines {
+        match init_counts.iter_mut().find(|(k, _)| *k == l.init_key) {
+            Some((_, n)) => *n += 1,
+            None => init_counts.push((l.init_key, 1)),
+        }
+    }
+    let Some(&(root_key, _)) = init_counts.iter().rev().max_by_key(|(_, n)| *n) else {
+        return Err("no readable kifu files".to_string());
+    };
+
+    let mut initial: Option<Initial> = None;
+    let mut root_results = ResultCounts::default();
+    let mut root_children: Vec<TreeNode> = Vec::new();
+    let mut games: u32 = 0;
+
+    for l in lines {
+        if l.init_key != root_key {
+            skipped += 1;
+            continue;
+        }
+        if games == 0 {
+            initial = l.initial;
+        }
+        games += 1;
+        root_results.add(l.result);
+        insert_line(&mut root_children, &l.moves, l.result);
+    }
+
+    let min_count = input.min_count.max(1);
+    prune_and_sort(&mut root_children, min_count);
+
+    let mut moves: Vec<MoveFormat> = vec![move_format(None, summary(games, &root_results))];
+    push_line(&root_children, &mut moves);
+
+    let mut jkf = JsonKifuFormat {
+        header: HashMap::new(),
+        initial,
+        moves,
+    };
+    jkf.normalize()
+        .map_err(|e| format!("正規化エラー: {:?}", e))?;
+
+    Ok(OpeningTreeOutput {
+        jkf,
+        games,
+        skipped,
+    })
+}
+
+fn game_line(jkf: &JsonKifuFormat, max_plies: usize) -> Result<GameLine, String> {
+    let mut pos = initial_partial_position(jkf).map_err(|e| e.to_string())?;
+    let init_key = key_from_partial_position(&pos);
+
+    let mut moves: Vec<(PositionKey, MoveMoveFormat)> = Vec::new();
+    for node in jkf.moves.iter().skip(1) {
+        if moves.len() >= max_plies {
+            break;
+        }
+        let Some(mv) = node.move_ else {
+            break;
+        };
+        match apply_node_action(&mut pos, NodeAction::Move(mv)) {
+            Ok(ApplyStatus::Applied) => moves.push((key_from_partial_position(&pos), mv)),
+            _ => break,
+        }
+    }
+
+    Ok(GameLine {
+        init_key,
+        initial: jkf.initial,
+        moves,
+        result: game_result(jkf),
+    })
+}
+
+fn insert_line(
+    children: &mut Vec<TreeNode>,
+    line: &[(PositionKey, MoveMoveFormat)],
+    result: GameResult,
+) {
+    let Some(((key, mv), rest)) = line.split_first() else {
+        return;
+    };
+
+    let i = match children.iter().position(|c| c.key == *key) {
+        Some(i) => i,
+        None => {
+            children.push(TreeNode {
+                key: *key,
+                mv: *mv,
+                count: 0,
+                results: ResultCounts::default(),
+                children: Vec::new(),
+            });
+            children.len() - 1
+        }
+    };
+
+    let node = &mut children[i];
+    node.count += 1;
+    node.results.add(result);
+    insert_line(&mut node.children, rest, result);
+}
+
+/// 出現数が min_count 未満の手を落とし、多い順に並べる
+fn prune_and_sort(children: &mut Vec<TreeNode>, min_count: u32) {
+    children.retain(|c| c.count >= min_count);
+    children.sort_by(|a, b| b.count.cmp(&a.count));
+    for c in children.iter_mut() {
+        prune_and_sort(&mut c.children, min_count);
+    }
+}
+
+/// 先頭の子を本譜、残りをその手の分岐として JKF の系列に積む
+fn push_line(children: &[TreeNode], out: &mut Vec<MoveFormat>) {
+    let Some((main, alts)) = children.split_first() else {
+        return;
+    };
+
+    let mut mf = tree_move_format(main);
+    if !alts.is_empty() {
+        mf.forks = Some(
+            alts.iter()
+                .map(|alt| {
+                    let mut line = vec![tree_move_format(alt)];
+                    push_line(&alt.children, &mut line);
+                    line
+                })
+                .collect(),
+        );
+    }
+    out.push(mf);
+    push_line(&main.children, out);
+}
+
+fn tree_move_format(node: &TreeNode) -> MoveFormat {
+    move_format(Some(node.mv), summary(node.count, &node.results))
+}
+
+fn move_format(mv: Option<MoveMoveFormat>, comment: String) -> MoveFormat {
+    MoveFormat {
+        comments: Some(vec![comment]),
+        move_: mv,
+        time: None,
+        special: None,
+        forks: None,
+    }
+}
+
+fn summary(count: u32, r: &ResultCounts) -> String {
+    format!(
+        "{count}局 (先手勝ち {} / 後手勝ち {} / 引き分け {})",
+        r.black_wins, r.white_wins, r.draws
+    )
+}
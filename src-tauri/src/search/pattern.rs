@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use shogi_core::{Color, PartialPosition, Piece, PieceKind, Square};
+use thiserror::Error;
+
+use super::{
+    fs_scan::{file_stat, KifuKind},
+    index_builder::{build_index_for_jkf_with_visitor, BuildPolicy},
+    kifu_reader::read_path_to_jkf,
+    node_table::{NODE_FLAG_PERPETUAL_CHECK, NODE_FLAG_REPETITION},
+    sfen_position::{hand_piecekind_from_letter, piecekind_from_sfen_letter, SfenParseError},
+    types::{CursorLite, FileId, Gen, NodeId, Occurrence, PositionHit, SearchPatternInput},
+};
+
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("pattern has no constraints")]
+    Empty,
+
+    #[error("invalid square: file={file}, rank={rank}")]
+    InvalidSquare { file: u8, rank: u8 },
+
+    #[error("invalid hand range: {piece} min={min} max={max}")]
+    InvalidHandRange { piece: String, min: u8, max: u8 },
+
+    #[error("invalid side to move: {0}")]
+    InvalidSide(String),
+
+    #[error(transparent)]
+    Sfen(#[from] SfenParseError),
+}
+
+/// 持ち駒の条件。`min..=max` 枚
+#[derive(Debug, Clone, Copy)]
+struct HandRule {
+    color: Color,
+    kind: PieceKind,
+    min: u8,
+    max: u8,
+}
+
+/// 入力を解釈済みのパターン。局面 1 つに対して `matches` で判定する。
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    /// None は「空きマス」の指定
+    squares: Vec<(Square, Option<Piece>)>,
+    hands: Vec<HandRule>,
+    side_to_move: Option<Color>,
+}
+
+impl Pattern {
+    pub fn compile(input: &SearchPatternInput) -> Result<Self, PatternError> {
+        if input.squares.is_empty() && input.hands.is_empty() {
+            return Err(PatternError::Empty);
+        }
+
+        let mut squares = Vec::with_capacity(input.squares.len());
+        for s in &input.squares {
+            let sq = Square::new(s.file, s.rank).ok_or(PatternError::InvalidSquare {
+                file: s.file,
+                rank: s.rank,
+            })?;
+            let piece = match s.piece.as_deref() {
+                None | Some("") => None,
+                Some(p) => Some(parse_piece(p)?),
+            };
+            squares.push((sq, piece));
+        }
+
+        let mut hands = Vec::with_capacity(input.hands.len());
+        for h in &input.hands {
+            let mut chars = h.piece.chars();
+            let (Some(ch), None) = (chars.next(), chars.next()) else {
+                return Err(SfenParseError::InvalidPiece(h.piece.clone()).into());
+            };
+            let (color, kind) = hand_piecekind_from_letter(ch)?;
+            let min = h.min.unwrap_or(0);
+            let max = h.max.unwrap_or(u8::MAX);
+            if min > max {
+                return Err(PatternError::InvalidHandRange {
+                    piece: h.piece.clone(),
+                    min,
+                    max,
+                });
+            }
+            hands.push(HandRule {
+                color,
+                kind,
+                min,
+                max,
+            });
+        }
+
+        let side_to_move = match input.side_to_move.as_deref() {
+            None => None,
+            Some("b") => Some(Color::Black),
+            Some("w") => Some(Color::White),
+            Some(s) => return Err(PatternError::InvalidSide(s.to_string())),
+        };
+
+        Ok(Self {
+            squares,
+            hands,
+            side_to_move,
+        })
+    }
+
+    pub fn matches(&self, pos: &PartialPosition) -> bool {
+        if let Some(side) = self.side_to_move {
+            if pos.side_to_move() != side {
+                return false;
+            }
+        }
+
+        if self
+            .squares
+            .iter()
+            .any(|&(sq, piece)| pos.piece_at(sq) != piece)
+        {
+            return false;
+        }
+
+        self.hands.iter().all(|r| {
+            let n = pos.hand_of_a_player(r.color).count(r.kind).unwrap_or(0);
+            (r.min..=r.max).contains(&n)
+        })
+    }
+}
+
+/// "S" / "+r" のような SFEN の駒表記 1 つを読む
+fn parse_piece(s: &str) -> Result<Piece, SfenParseError> {
+    let mut chars = s.chars();
+    let (promoted, ch) = match (chars.next(), chars.next(), chars.next()) {
+        (Some('+'), Some(c), None) => (true, c),
+        (Some(c), None, None) => (false, c),
+        _ => return Err(SfenParseError::InvalidPiece(s.to_string())),
+    };
+    let (color, pk) = piecekind_from_sfen_letter(ch, promoted)?;
+    Ok(Piece::new(pk, color))
+}
+
+/// 1 ファイルを読み直して全ノードにパターンを当てる。
+///
+/// index には局面そのものが残っていないので、棋譜を parse → build し直して
+/// visitor で局面を覗く。読めないファイルは hit なし扱い。`expect_stat` が Some なら、
+/// 今の stat がそれ (index に載せた時の stat) と違うファイルも hit なし扱いにする。
+pub fn scan_file_for_pattern(
+    file_id: FileId,
+    gen: Gen,
+    path: &str,
+    expect_stat: Option<(u64, u128)>,
+    pattern: &Pattern,
+) -> Vec<PositionHit> {
+    let path = Path::new(path);
+    let Some(kind) = KifuKind::from_path(path) else {
+        return Vec::new();
+    };
+    if let Some(expect) = expect_stat {
+        if file_stat(path) != Some(expect) {
+            log::debug!("[pattern] skip changed file: {}", path.display());
+            return Vec::new();
+        }
+    }
+    let jkf = match read_path_to_jkf(path, kind) {
+        Ok(jkf) => jkf,
+        Err(e) => {
+            log::debug!("[pattern] skip unreadable file: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut matched: Vec<NodeId> = Vec::new();
+    let built = match build_index_for_jkf_with_visitor(
+        file_id,
+        gen,
+        &jkf,
+        BuildPolicy::Loose,
+        &mut |node_id, pos| {
+            if pattern.matches(pos) {
+                matched.push(node_id);
+            }
+        },
+    ) {
+        Ok(b) => b,
+        Err(e) => {
+            log::debug!("[pattern] skip file_id={file_id}: {e}");
+            return Vec::new();
+        }
+    };
+
+    matched
+        .into_iter()
+        .map(|node_id| {
+            let flags = built.node_table.flags(node_id);
+            PositionHit {
+                root_id: 0,
+                occ: Occurrence {
+                    file_id,
+                    gen,
+                    node_id,
+                },
+                cursor: built
+                    .node_table
+                    .cursor_lite(node_id)
+                    .unwrap_or_else(CursorLite::root),
+                mirrored: false,
+                color_flipped: false,
+                hands: None,
+                hit_count: None,
+                repetition: flags & NODE_FLAG_REPETITION != 0,
+                perpetual_check: flags & NODE_FLAG_PERPETUAL_CHECK != 0,
+            }
+        })
+        .collect()
+}
@@ -5,7 +5,10 @@ use shogi_kifu_converter_obsshogi::jkf::{
     Color as JkfColor, Kind as JkfKind, MoveMoveFormat, PlaceFormat,
 };
 
-use super::traverse::NodeAction;
+use super::{
+    rules::{check_move, IllegalMove},
+    traverse::NodeAction,
+};
 
 /// 「適用した結果、この先を辿ってよいか」を返すためのステータス
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,6 +37,9 @@ pub enum ApplyError {
 
     #[error("cannot apply move")]
     ApplyFailed,
+
+    #[error("invalid usi move: {0}")]
+    InvalidUsi(String),
 }
 
 pub fn apply_node_action(
@@ -61,6 +67,21 @@ pub fn apply_node_action(
     }
 }
 
+/// action を将棋のルールに照らし、指せない手ならその理由を返す。
+///
+/// 機械的に適用できない手 (手番違い・マス範囲外など) は `apply_node_action` が
+/// エラーにするので、ここでは None を返す。
+pub fn check_node_legality(pos: &PartialPosition, action: NodeAction) -> Option<IllegalMove> {
+    let NodeAction::Move(m) = action else {
+        return None;
+    };
+    if to_core_color(m.color) != pos.side_to_move() {
+        return None;
+    }
+    let mv = jkf_move_to_core_move(m).ok()?;
+    check_move(pos, mv).err()
+}
+
 fn jkf_move_to_core_move(m: MoveMoveFormat) -> Result<CoreMove, ApplyError> {
     let to = to_square(m.to)?;
 
@@ -82,6 +103,139 @@ fn jkf_move_to_core_move(m: MoveMoveFormat) -> Result<CoreMove, ApplyError> {
     }
 }
 
+/// JKF の指し手を USI 表記 ("7g7f", "P*5e", "8h2b+") にする
+pub fn jkf_move_to_usi(m: MoveMoveFormat) -> Result<String, ApplyError> {
+    Ok(core_move_to_usi(jkf_move_to_core_move(m)?))
+}
+
+pub fn core_move_to_usi(mv: CoreMove) -> String {
+    match mv {
+        CoreMove::Normal { from, to, promote } => format!(
+            "{}{}{}",
+            usi_square(from),
+            usi_square(to),
+            if promote { "+" } else { "" }
+        ),
+        CoreMove::Drop { piece, to } => {
+            let letter = match piece.piece_kind() {
+                PieceKind::Pawn => 'P',
+                PieceKind::Lance => 'L',
+                PieceKind::Knight => 'N',
+                PieceKind::Silver => 'S',
+                PieceKind::Gold => 'G',
+                PieceKind::Bishop => 'B',
+                PieceKind::Rook => 'R',
+                // 打てない駒は shogi_core 側で弾かれている
+                _ => '?',
+            };
+            format!("{letter}*{}", usi_square(to))
+        }
+    }
+}
+
+/// USI 表記の指し手を pos 上の指し手にする (合法かどうかは見ない)
+pub fn usi_to_core_move(pos: &PartialPosition, usi: &str) -> Result<CoreMove, ApplyError> {
+    let invalid = || ApplyError::InvalidUsi(usi.to_string());
+    let b = usi.as_bytes();
+
+    if b.len() == 4 && b[1] == b'*' {
+        let pk = match b[0] {
+            b'P' => PieceKind::Pawn,
+            b'L' => PieceKind::Lance,
+            b'N' => PieceKind::Knight,
+            b'S' => PieceKind::Silver,
+            b'G' => PieceKind::Gold,
+            b'B' => PieceKind::Bishop,
+            b'R' => PieceKind::Rook,
+            _ => return Err(invalid()),
+        };
+        let to = parse_usi_square(&b[2..4]).ok_or_else(invalid)?;
+        let piece = Piece::new(pk, pos.side_to_move());
+        return Ok(CoreMove::Drop { piece, to });
+    }
+
+    let promote = match b.len() {
+        4 => false,
+        5 if b[4] == b'+' => true,
+        _ => return Err(invalid()),
+    };
+    let from = parse_usi_square(&b[0..2]).ok_or_else(invalid)?;
+    let to = parse_usi_square(&b[2..4]).ok_or_else(invalid)?;
+    Ok(CoreMove::Normal { from, to, promote })
+}
+
+/// pos 上の指し手を JKF の指し手にする。same / relative は付けないので normalize で補う
+pub fn core_move_to_jkf(pos: &PartialPosition, mv: CoreMove) -> Result<MoveMoveFormat, ApplyError> {
+    match mv {
+        CoreMove::Normal { from, to, promote } => {
+            let piece = pos.piece_at(from).ok_or(ApplyError::ApplyFailed)?;
+            Ok(MoveMoveFormat {
+                color: to_jkf_color(piece.color()),
+                from: Some(to_place(from)),
+                to: to_place(to),
+                piece: to_jkf_kind(piece.piece_kind()),
+                same: None,
+                promote: promote.then_some(true),
+                capture: pos.piece_at(to).map(|p| to_jkf_kind(p.piece_kind())),
+                relative: None,
+            })
+        }
+        CoreMove::Drop { piece, to } => Ok(MoveMoveFormat {
+            color: to_jkf_color(piece.color()),
+            from: None,
+            to: to_place(to),
+            piece: to_jkf_kind(piece.piece_kind()),
+            same: None,
+            promote: None,
+            capture: None,
+            relative: None,
+        }),
+    }
+}
+
+fn parse_usi_square(b: &[u8]) -> Option<Square> {
+    let file = b[0].checked_sub(b'0')?;
+    let rank = b[1].checked_sub(b'a')? + 1;
+    Square::new(file, rank)
+}
+
+fn to_place(sq: Square) -> PlaceFormat {
+    PlaceFormat {
+        x: sq.file(),
+        y: sq.rank(),
+    }
+}
+
+fn to_jkf_kind(pk: PieceKind) -> JkfKind {
+    match pk {
+        PieceKind::Pawn => JkfKind::FU,
+        PieceKind::Lance => JkfKind::KY,
+        PieceKind::Knight => JkfKind::KE,
+        PieceKind::Silver => JkfKind::GI,
+        PieceKind::Gold => JkfKind::KI,
+        PieceKind::Bishop => JkfKind::KA,
+        PieceKind::Rook => JkfKind::HI,
+        PieceKind::King => JkfKind::OU,
+        PieceKind::ProPawn => JkfKind::TO,
+        PieceKind::ProLance => JkfKind::NY,
+        PieceKind::ProKnight => JkfKind::NK,
+        PieceKind::ProSilver => JkfKind::NG,
+        PieceKind::ProBishop => JkfKind::UM,
+        PieceKind::ProRook => JkfKind::RY,
+    }
+}
+
+fn to_jkf_color(c: CoreColor) -> JkfColor {
+    match c {
+        CoreColor::Black => JkfColor::Black,
+        CoreColor::White => JkfColor::White,
+    }
+}
+
+fn usi_square(sq: Square) -> String {
+    format!("{}{}", sq.file(), (b'a' + sq.rank() - 1) as char)
+}
+
 fn to_square(p: PlaceFormat) -> Result<Square, ApplyError> {
     Square::new(p.x, p.y).ok_or(ApplyError::InvalidSquare { x: p.x, y: p.y })
 }
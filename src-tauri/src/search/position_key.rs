@@ -128,6 +128,61 @@ pub fn key_from_partial_position(pos: &PartialPosition) -> PositionKey {
     key
 }
 
+/// 持ち駒を無視した (盤面 + 手番のみの) PositionKey を作る。
+///
+/// 途中局面の持ち駒が壊れた棋譜でも引けるようにするための補助キー。
+pub fn board_key_from_partial_position(pos: &PartialPosition) -> PositionKey {
+    let tbl = ZOBRIST.get_or_init(ZobristTable::new);
+
+    let mut key = PositionKey::ZERO;
+
+    key.xor_assign(tbl.side[cidx(pos.side_to_move())]);
+
+    for sq in Square::all() {
+        if let Some(piece) = pos.piece_at(sq) {
+            key.xor_assign(key_for_piece_on_square(tbl, piece, sq));
+        }
+    }
+
+    key
+}
+
+// 持ち駒の bit 幅 (HAND_KINDS 順)。歩 0..=18 は 5bit、香桂銀金 0..=4 は 3bit、
+// 角飛 0..=2 は 2bit。1 色 21bit × 2 色で u64 に収まる。
+const HAND_BITS: [u32; 7] = [5, 3, 3, 3, 3, 2, 2];
+const HAND_BITS_PER_COLOR: u32 = 21;
+
+/// 両者の持ち駒を 42bit に詰める (board index の hands 列用)
+pub fn pack_hands(pos: &PartialPosition) -> u64 {
+    let mut packed = 0u64;
+    for (ci, color) in [Color::Black, Color::White].into_iter().enumerate() {
+        let hand = pos.hand_of_a_player(color);
+        let mut shift = ci as u32 * HAND_BITS_PER_COLOR;
+        for (hk, pk) in HAND_KINDS.iter().enumerate() {
+            let bits = HAND_BITS[hk];
+            let max = (1u64 << bits) - 1;
+            let cnt = (hand.count(*pk).unwrap_or(0) as u64).min(max);
+            packed |= cnt << shift;
+            shift += bits;
+        }
+    }
+    packed
+}
+
+/// `pack_hands` の逆。`[color][hand_kind]` の枚数を返す (hand_kind は歩香桂銀金角飛)。
+pub fn unpack_hands(packed: u64) -> [[u8; 7]; 2] {
+    let mut out = [[0u8; 7]; 2];
+    for (ci, row) in out.iter_mut().enumerate() {
+        let mut shift = ci as u32 * HAND_BITS_PER_COLOR;
+        for (hk, cell) in row.iter_mut().enumerate() {
+            let bits = HAND_BITS[hk];
+            *cell = ((packed >> shift) & ((1u64 << bits) - 1)) as u8;
+            shift += bits;
+        }
+    }
+    out
+}
+
 #[inline]
 fn key_for_piece_on_square(tbl: &ZobristTable, piece: Piece, sq: Square) -> PositionKey {
     let (pk, c) = piece.to_parts();
@@ -0,0 +1,55 @@
+use shogi_core::{Color, PartialPosition, Piece, Square};
+
+/// 盤面を左右反転 (筋 f → 10-f) した局面を返す。
+///
+/// 手番・持ち駒・手数はそのまま。検索キーの計算用なので合法性は見ない。
+pub fn mirror_files(pos: &PartialPosition) -> PartialPosition {
+    let mut out = pos.clone();
+
+    for sq in Square::all() {
+        out.piece_set(sq, None);
+    }
+    for sq in Square::all() {
+        if let Some(piece) = pos.piece_at(sq) {
+            if let Some(to) = Square::new(10 - sq.file(), sq.rank()) {
+                out.piece_set(to, Some(piece));
+            }
+        }
+    }
+
+    out
+}
+
+/// 盤面を 180° 回転して先後を入れ替えた局面を返す。
+///
+/// 駒の色・持ち駒・手番をすべて入れ替えるので、「後手が同じ形を指した局面」に
+/// 一致するキーが得られる。
+pub fn flip_colors(pos: &PartialPosition) -> PartialPosition {
+    let mut out = pos.clone();
+
+    for sq in Square::all() {
+        out.piece_set(sq, None);
+    }
+    for sq in Square::all() {
+        if let Some(piece) = pos.piece_at(sq) {
+            let (pk, c) = piece.to_parts();
+            if let Some(to) = Square::new(10 - sq.file(), 10 - sq.rank()) {
+                out.piece_set(to, Some(Piece::new(pk, opposite(c))));
+            }
+        }
+    }
+
+    *out.hand_of_a_player_mut(Color::Black) = pos.hand_of_a_player(Color::White);
+    *out.hand_of_a_player_mut(Color::White) = pos.hand_of_a_player(Color::Black);
+    out.side_to_move_set(opposite(pos.side_to_move()));
+
+    out
+}
+
+#[inline]
+fn opposite(c: Color) -> Color {
+    match c {
+        Color::Black => Color::White,
+        Color::White => Color::Black,
+    }
+}
@@ -5,32 +5,61 @@ use std::{
     time::Duration,
 };
 
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{
+    event::{EventKind, ModifyKind},
+    RecommendedWatcher, RecursiveMode, Watcher,
+};
 use tauri::{AppHandle, Emitter};
 use tokio::{sync::Mutex, task, time};
 
 use crate::search::{
+    delta_log::{encode_delta, DeltaBatch},
     fs_scan::{
-        diff_snapshot, scan_kifu_files, snapshot_from_records, FileRecord, ScanOptions,
-        ScanSnapshot,
+        diff_snapshot, normalize_path, rescan_paths, scan_kifu_files, snapshot_from_records,
+        FileRecord, ScanOptions, ScanSnapshot,
+    },
+    index_builder::{
+        bucketize_board_entries, bucketize_entries, build_index_for_jkf, BuildPolicy, BuildWarn,
+    },
+    index_cache,
+    index_store::{
+        BoardBucketEntries, FileBucketEntries, IndexState as StoreIndexState, IndexStore,
     },
-    index_builder::{bucketize_entries, build_index_for_jkf, BuildPolicy},
-    index_store::{FileBucketEntries, IndexState as StoreIndexState, IndexStore},
     kifu_reader::read_to_jkf,
     node_table::NodeTable,
     position_key::PositionKey,
     types::{
-        FileEntry, FileId, IndexProgressPayload, IndexState, IndexStatePayload, IndexWarnPayload,
-        Occurrence, EVT_INDEX_PROGRESS, EVT_INDEX_STATE, EVT_INDEX_WARN,
+        FileEntry, FileId, GameMeta, IndexProgressPayload, IndexState, IndexStatePayload,
+        IndexWarnPayload, Occurrence, EVT_INDEX_PROGRESS, EVT_INDEX_STATE, EVT_INDEX_WARN,
     },
 };
 
 type BucketEntries = [Vec<(PositionKey, Occurrence)>; 256];
 
+/// 1 回の debounce でこれより多くのパスが変わったら、個別に見直さずフルスキャンする
+const MAX_TARGETED_PATHS: usize = 2048;
+
+/// watcher → debounce loop へ送るもの
+enum WatchMsg {
+    /// 変更のあったパス。`structural` は作成・削除・rename (親ディレクトリも見直す)
+    Path { path: PathBuf, structural: bool },
+    /// イベントの取りこぼし (overflow) や watcher のエラー。フルスキャンで補正する
+    Rescan,
+}
+
+/// debounce 中に溜めた変更
+#[derive(Debug, Default)]
+pub struct DirtyPaths {
+    pub paths: HashSet<PathBuf>,
+    pub dirs: HashSet<PathBuf>,
+}
+
 #[derive(Debug, Default)]
 struct Inner {
     root_dir: Option<PathBuf>,
     scan: ScanSnapshot,
+    /// 走査ルール。rescan と watcher のイベントの絞り込みに使う
+    scan_opts: Arc<ScanOptions>,
 
     path_to_id: HashMap<String, FileId>,
     next_file_id: FileId,
@@ -65,6 +94,30 @@ impl ProjectManager {
         g.next_file_id = next_file_id;
     }
 
+    /// 走査スナップショットにある各ファイルの (size, mtime_ms)。index に載せた時の stat
+    pub async fn indexed_stats(&self) -> HashMap<String, (u64, u128)> {
+        let g = self.inner.lock().await;
+        g.scan
+            .by_path
+            .iter()
+            .map(|(k, r)| (k.clone(), (r.size, r.mtime_ms)))
+            .collect()
+    }
+
+    /// open 時に AppConfig の走査ルールを渡す
+    pub async fn set_scan_options(&self, opts: Arc<ScanOptions>) {
+        self.inner.lock().await.scan_opts = opts;
+    }
+
+    /// root を閉じる時に watcher と debounce loop を止める
+    pub async fn stop(&self) {
+        let mut g = self.inner.lock().await;
+        if let Some(h) = g.debounce_task.take() {
+            h.abort();
+        }
+        g.watcher.take();
+    }
+
     pub async fn start_watcher_and_debounce(
         self: Arc<Self>,
         app: AppHandle,
@@ -72,24 +125,43 @@ impl ProjectManager {
         quiet: Duration,
     ) -> Result<(), String> {
         // 既存タスク停止＆watcher破棄
-        let root = {
+        let (root, opts) = {
             let mut g = self.inner.lock().await;
             if let Some(h) = g.debounce_task.take() {
                 h.abort();
             }
             g.watcher.take();
 
-            g.root_dir.clone().ok_or("project root_dir is not set")?
+            let root = g.root_dir.clone().ok_or("project root_dir is not set")?;
+            (root, g.scan_opts.clone())
         };
+        // イベントのパスは canonicalize 済みの root 配下で来るようにそろえる
+        let root = normalize_path(&root);
 
         // notify → tokio へ橋渡し
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WatchMsg>();
 
+        let watch_root = root.clone();
         let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
-            if let Ok(ev) = res {
-                for p in ev.paths {
-                    let _ = tx.send(p);
+            let ev = match res {
+                Ok(ev) if !ev.need_rescan() => ev,
+                _ => {
+                    let _ = tx.send(WatchMsg::Rescan);
+                    return;
+                }
+            };
+            let structural = matches!(
+                ev.kind,
+                EventKind::Create(_)
+                    | EventKind::Remove(_)
+                    | EventKind::Modify(ModifyKind::Name(_))
+            );
+            for path in ev.paths {
+                // 走査ルールで対象外のパス (.git 配下など) では起こさない
+                if opts.excludes(&watch_root, &path) {
+                    continue;
                 }
+                let _ = tx.send(WatchMsg::Path { path, structural });
             }
         })
         .map_err(|e| e.to_string())?;
@@ -107,7 +179,8 @@ impl ProjectManager {
         // Debounce loop
         let pm = self.clone();
         let handle = task::spawn(async move {
-            let mut dirty_paths: HashSet<PathBuf> = HashSet::new();
+            let mut dirty = DirtyPaths::default();
+            let mut full_rescan = false;
 
             // 適当な遠い sleep を置いて、イベント受信で reset する
             let sleep = time::sleep(Duration::from_secs(3600));
@@ -115,24 +188,41 @@ impl ProjectManager {
 
             loop {
                 tokio::select! {
-                    p = rx.recv() => {
-                        let Some(p) = p else { break; };
-                        dirty_paths.insert(p);
+                    msg = rx.recv() => {
+                        let Some(msg) = msg else { break; };
+                        match msg {
+                            WatchMsg::Path { path, structural } => {
+                                if structural {
+                                    if let Some(parent) = path.parent() {
+                                        dirty.dirs.insert(parent.to_path_buf());
+                                    }
+                                }
+                                dirty.paths.insert(path);
+                            }
+                            WatchMsg::Rescan => full_rescan = true,
+                        }
                         // 静穏時間を延長
                         sleep.as_mut().reset(time::Instant::now() + quiet);
                     }
                     _ = &mut sleep => {
-                        if dirty_paths.is_empty() {
+                        if dirty.paths.is_empty() && !full_rescan {
                             // 何もなければまた遠い sleep
                             sleep.as_mut().reset(time::Instant::now() + Duration::from_secs(3600));
                             continue;
                         }
 
                         // “dirty集合のスナップショットを切る”
-                        dirty_paths.clear();
-
-                        // Step2: 差分更新（フル再スキャン→diff→適用）
-                        pm.run_rescan_diff_apply(app.clone(), store.clone()).await;
+                        let batch = std::mem::take(&mut dirty);
+                        let full = std::mem::take(&mut full_rescan)
+                            || batch.paths.len() > MAX_TARGETED_PATHS;
+
+                        // Step2: 差分更新（変わったパスだけ見直す→diff→適用）
+                        if full {
+                            pm.run_rescan_diff_apply(app.clone(), store.clone()).await;
+                        } else {
+                            pm.run_targeted_rescan_apply(app.clone(), store.clone(), batch)
+                                .await;
+                        }
 
                         // 次のイベントを待つ
                         sleep.as_mut().reset(time::Instant::now() + Duration::from_secs(3600));
@@ -147,19 +237,49 @@ impl ProjectManager {
         Ok(())
     }
 
-    /// Step2本体：scan -> diff -> apply
+    /// Step2本体：scan -> diff -> apply (フルスキャン)
     pub async fn run_rescan_diff_apply(&self, app: AppHandle, store: Arc<IndexStore>) {
+        self.rescan_diff_apply(app, store, None).await
+    }
+
+    /// watcher が拾ったパスだけを見直して差分反映する
+    pub async fn run_targeted_rescan_apply(
+        &self,
+        app: AppHandle,
+        store: Arc<IndexStore>,
+        dirty: DirtyPaths,
+    ) {
+        self.rescan_diff_apply(app, store, Some(dirty)).await
+    }
+
+    async fn rescan_diff_apply(
+        &self,
+        app: AppHandle,
+        store: Arc<IndexStore>,
+        dirty: Option<DirtyPaths>,
+    ) {
         // プロジェクト情報を “cloneして” 取り出す（ロックを await に跨がない）
-        let (root, prev_scan, mut path_to_id, mut next_file_id) = {
+        let (root, opts, prev_scan, mut path_to_id, mut next_file_id) = {
             let g = self.inner.lock().await;
             let Some(root) = g.root_dir.clone() else {
                 return;
             };
-            (root, g.scan.clone(), g.path_to_id.clone(), g.next_file_id)
+            (
+                root,
+                g.scan_opts.clone(),
+                g.scan.clone(),
+                g.path_to_id.clone(),
+                g.next_file_id,
+            )
         };
 
-        // 再スキャン（雑にフルスキャンでOK：notify取りこぼしも補正できる）
-        let records = match scan_kifu_files(&root, &ScanOptions::default()) {
+        // 再スキャン：dirty が無ければフルスキャン (notify 取りこぼしも補正できる)
+        let scanned = if dirty.is_some() {
+            Ok(None)
+        } else {
+            scan_kifu_files(&root, &opts).map(Some)
+        };
+        let records = match scanned {
             Ok(v) => v,
             Err(e) => {
                 let _ = app.emit(
@@ -167,17 +287,40 @@ impl ProjectManager {
                     IndexWarnPayload {
                         path: root.to_string_lossy().to_string(),
                         message: format!("scan failed: {e}"),
+                        kind: None,
+                        cursor: None,
                     },
                 );
                 return;
             }
         };
 
-        let next_scan = snapshot_from_records(&root, records.clone());
-        let diff = diff_snapshot(&prev_scan, &next_scan);
+        // hash の計算でファイルを読むので blocking 側で diff を取る
+        let root2 = root.clone();
+        let diffed = task::spawn_blocking(move || {
+            let mut next_scan = match records {
+                Some(records) => snapshot_from_records(&root2, records),
+                None => {
+                    let dirty = dirty.unwrap_or_default();
+                    rescan_paths(&prev_scan, &dirty.paths, &dirty.dirs, &opts)
+                }
+            };
+            let diff = diff_snapshot(&prev_scan, &mut next_scan);
+            (diff, next_scan)
+        })
+        .await;
+        let (diff, next_scan) = match diffed {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("[project_manager] diff join error: {e}");
+                return;
+            }
+        };
         let snap = store.snapshot();
 
-        let dirty_count = (diff.added.len() + diff.modified.len() + diff.removed.len()) as u32;
+        let dirty_count =
+            (diff.added.len() + diff.modified.len() + diff.removed.len() + diff.renamed.len())
+                as u32;
         if dirty_count == 0 {
             // 変化なし：scanだけ更新して終了
             let mut g = self.inner.lock().await;
@@ -198,11 +341,16 @@ impl ProjectManager {
         );
 
         let mut done_dirty: u32 = 0;
+        let mut delta = DeltaBatch {
+            removed_paths: diff.removed.clone(),
+            ..Default::default()
+        };
 
         // removed → tombstone (cheap, fire immediately)
         for path_key in &diff.removed {
             if let Some(file_id) = path_to_id.remove(path_key) {
                 store.tombstone_file(file_id);
+                delta.tombstones.push(file_id);
             }
             done_dirty += 1;
             let _ = app.emit(
@@ -215,6 +363,34 @@ impl ProjectManager {
             );
         }
 
+        // renamed → 同じ file_id のまま path だけ付け替える (再 build しない)
+        let mut added: Vec<FileRecord> = diff.added.clone();
+        let mut renames: Vec<(FileId, String)> = Vec::with_capacity(diff.renamed.len());
+        for (old_key, rec) in &diff.renamed {
+            let path_str = rec.path.to_string_lossy().to_string();
+            match path_to_id.remove(old_key) {
+                Some(file_id) => {
+                    path_to_id.insert(crate::search::fs_scan::path_key(&rec.path), file_id);
+                    renames.push((file_id, path_str.clone()));
+                    delta.removed_paths.push(old_key.clone());
+                    delta.records.push((rec.clone(), file_id));
+                    delta.renames.push((file_id, path_str.clone()));
+                }
+                // 旧 path が未登録なら普通の追加として扱う
+                None => added.push(rec.clone()),
+            }
+            done_dirty += 1;
+            let _ = app.emit(
+                EVT_INDEX_PROGRESS,
+                IndexProgressPayload {
+                    current_path: path_str,
+                    done_files: done_dirty,
+                    total_files: dirty_count,
+                },
+            );
+        }
+        store.rename_files(&renames);
+
         // modified + added → 並列ビルドして 1 回の insert_many にまとめる (A-M1)
         struct PendingBuild {
             rec: FileRecord,
@@ -243,7 +419,7 @@ impl ProjectManager {
             });
         }
 
-        for rec in &diff.added {
+        for rec in &added {
             let path_key = crate::search::fs_scan::path_key(&rec.path);
             let file_id = next_file_id;
             next_file_id = next_file_id.wrapping_add(1);
@@ -255,6 +431,11 @@ impl ProjectManager {
             });
         }
 
+        delta
+            .records
+            .extend(pending.iter().map(|pb| (pb.rec.clone(), pb.file_id)));
+        delta.next_file_id = next_file_id;
+
         let mut batch: Vec<FileBucketEntries> = Vec::with_capacity(pending.len());
         for pb in pending {
             let path_str = pb.rec.path.to_string_lossy().to_string();
@@ -262,20 +443,25 @@ impl ProjectManager {
                 .build_one_file(&app, &pb.rec, pb.file_id, pb.new_gen)
                 .await
             {
-                Some(item) => batch.push(item),
-                None => {
+                Ok(item) => batch.push(item),
+                Err(reason) => {
                     // build error: still record a tombstone-ish entry so file_table
                     // gets updated and stale segments from the old gen are excluded.
+                    // 失敗の理由は統計 (get_index_stats) 用に残す
                     let empty: BucketEntries = std::array::from_fn(|_| Vec::new());
+                    let empty_board: BoardBucketEntries = std::array::from_fn(|_| Vec::new());
                     batch.push((
                         FileEntry {
                             file_id: pb.file_id,
                             path: path_str.clone(),
                             deleted: false,
                             r#gen: pb.new_gen,
+                            error: Some(reason),
                         },
                         Arc::new(NodeTable::empty()),
                         empty,
+                        empty_board,
+                        GameMeta::default(),
                     ));
                 }
             }
@@ -290,6 +476,7 @@ impl ProjectManager {
             );
         }
 
+        let payload = encode_delta(&delta, &batch);
         if !batch.is_empty() {
             store.insert_many_file_segments(batch);
         }
@@ -306,84 +493,147 @@ impl ProjectManager {
         );
 
         // プロジェクト状態をコミット
-        let mut g = self.inner.lock().await;
-        g.scan = next_scan;
-        g.path_to_id = path_to_id;
-        g.next_file_id = next_file_id;
+        {
+            let mut g = self.inner.lock().await;
+            g.scan = next_scan;
+            g.path_to_id = path_to_id;
+            g.next_file_id = next_file_id;
+        }
+
+        self.save_delta(&app, &root, &store, payload).await;
+    }
+
+    /// 差分を delta log に追記する。ログが大きくなっていたら本体ごと書き直して
+    /// (save_checkpoint) ログを空にする。
+    async fn save_delta(
+        &self,
+        app: &AppHandle,
+        root: &Path,
+        store: &Arc<IndexStore>,
+        payload: Vec<u8>,
+    ) {
+        let app2 = app.clone();
+        let root2 = root.to_path_buf();
+        let compact =
+            task::spawn_blocking(move || index_cache::save_delta(&app2, &root2, &payload)).await;
+        match compact {
+            Ok(Ok(false)) => return,
+            Ok(Ok(true)) => {}
+            Ok(Err(e)) => {
+                log::warn!("[project_manager] save_delta FAILED: {e}");
+                return;
+            }
+            Err(e) => {
+                log::warn!("[project_manager] save_delta join error: {e}");
+                return;
+            }
+        }
+
+        log::info!("[project_manager] delta log is large -> compact checkpoint");
+        let (scan, path_to_id, next_file_id, rules) = {
+            let g = self.inner.lock().await;
+            (
+                g.scan.clone(),
+                g.path_to_id.clone(),
+                g.next_file_id,
+                g.scan_opts.fingerprint(),
+            )
+        };
+        let snap = store.snapshot();
+        let app2 = app.clone();
+        let root2 = root.to_path_buf();
+        let saved = task::spawn_blocking(move || {
+            index_cache::save_checkpoint(
+                &app2,
+                &root2,
+                &rules,
+                &snap,
+                &scan,
+                &path_to_id,
+                next_file_id,
+            )
+        })
+        .await;
+        if let Ok(Err(e)) = saved {
+            log::warn!("[project_manager] compact checkpoint FAILED: {e}");
+        }
     }
 
     /// 1 ファイル分の build を spawn_blocking で行い、 store に直接書き込まずに
     /// FileBucketEntries を返す。 run_rescan_diff_apply 側で batch 化して
-    /// insert_many_file_segments を 1 回呼ぶ用 (A-M1)。
+    /// insert_many_file_segments を 1 回呼ぶ用 (A-M1)。失敗したら理由を返す
     async fn build_one_file(
         &self,
         app: &AppHandle,
         rec: &FileRecord,
         file_id: FileId,
         new_gen: u32,
-    ) -> Option<FileBucketEntries> {
+    ) -> Result<FileBucketEntries, String> {
         let path_str = rec.path.to_string_lossy().to_string();
         let rec_cloned = rec.clone();
 
-        let built = task::spawn_blocking(
-            move || -> Result<(BucketEntries, Arc<NodeTable>, Vec<String>), String> {
-                let jkf = read_to_jkf(&rec_cloned).map_err(|e| e.to_string())?;
-                let b = build_index_for_jkf(file_id, new_gen, &jkf, BuildPolicy::Loose)
-                    .map_err(|e| e.to_string())?;
-                let by_bucket: BucketEntries = bucketize_entries(b.entries);
-                let warns = b
-                    .warns
-                    .into_iter()
-                    .map(|w| format!("{:?}: {}", w.cursor, w.message))
-                    .collect::<Vec<_>>();
-                Ok((by_bucket, b.node_table, warns))
-            },
-        )
+        type BuildOk = (
+            BucketEntries,
+            BoardBucketEntries,
+            Arc<NodeTable>,
+            GameMeta,
+            Vec<BuildWarn>,
+        );
+        let built = task::spawn_blocking(move || -> Result<BuildOk, String> {
+            let jkf = read_to_jkf(&rec_cloned).map_err(|e| e.to_string())?;
+            let b = build_index_for_jkf(file_id, new_gen, &jkf, BuildPolicy::Loose)
+                .map_err(|e| e.to_string())?;
+            let by_bucket: BucketEntries = bucketize_entries(b.entries);
+            let board_by_bucket = bucketize_board_entries(b.board_entries);
+            Ok((by_bucket, board_by_bucket, b.node_table, b.meta, b.warns))
+        })
         .await;
 
-        let (by_bucket, node_table, warns) = match built {
+        let (by_bucket, board_by_bucket, node_table, meta, warns) = match built {
             Ok(Ok(v)) => v,
             Ok(Err(e)) => {
                 let _ = app.emit(
                     EVT_INDEX_WARN,
                     IndexWarnPayload {
                         path: path_str,
-                        message: e,
+                        message: e.clone(),
+                        kind: None,
+                        cursor: None,
                     },
                 );
-                return None;
+                return Err(e);
             }
             Err(e) => {
+                let message = format!("spawn_blocking join error: {e}");
                 let _ = app.emit(
                     EVT_INDEX_WARN,
                     IndexWarnPayload {
                         path: path_str,
-                        message: format!("spawn_blocking join error: {e}"),
+                        message: message.clone(),
+                        kind: None,
+                        cursor: None,
                     },
                 );
-                return None;
+                return Err(message);
             }
         };
 
         for w in warns {
-            let _ = app.emit(
-                EVT_INDEX_WARN,
-                IndexWarnPayload {
-                    path: path_str.clone(),
-                    message: w,
-                },
-            );
+            let _ = app.emit(EVT_INDEX_WARN, w.into_payload(path_str.clone()));
         }
 
-        Some((
+        Ok((
             FileEntry {
                 file_id,
                 path: path_str,
                 deleted: false,
                 r#gen: new_gen,
+                error: None,
             },
             node_table,
             by_bucket,
+            board_by_bucket,
+            meta,
         ))
     }
 }
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -11,34 +11,287 @@ use tauri::{AppHandle, Emitter};
 use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
 
-use crate::search::types::{CursorLite, FileId, PositionHit, RequestId};
+use crate::search::types::{CursorLite, FileId, Gen, Occurrence, PositionHit, RequestId, RootId};
 
 use super::{
-    index_store::{IndexState as StoreIndexState, IndexStore},
-    sfen_position::position_key_from_sfen,
+    game_meta::MetaFilter,
+    grouping::arrange_hits,
+    index_store::{IndexSnapshot, IndexState as StoreIndexState},
+    node_table::{NodeTableArc, NODE_FLAG_PERPETUAL_CHECK, NODE_FLAG_REPETITION},
+    pattern::{scan_file_for_pattern, Pattern},
+    position_key::{
+        board_key_from_partial_position, key_from_partial_position, unpack_hands, PositionKey,
+    },
+    position_transform::{flip_colors, mirror_files},
+    roots::{RootSet, RootSnapshots},
+    sequence::search_sequence,
+    sfen_position::{
+        hands_to_sfen, partial_position_from_sfen, position_key_from_sfen, SfenParseError,
+    },
     types::{
-        SearchBeginPayload, SearchChunkPayload, SearchEndPayload, SearchErrorPayload,
-        SearchPositionInput, SearchPositionOutput, EVT_SEARCH_BEGIN, EVT_SEARCH_CHUNK,
-        EVT_SEARCH_END, EVT_SEARCH_ERROR,
+        Consistency, SearchBeginPayload, SearchChunkPayload, SearchEndPayload, SearchErrorPayload,
+        SearchPatternInput, SearchPositionInput, SearchPositionOutput, SearchSequenceInput,
+        EVT_SEARCH_BEGIN, EVT_SEARCH_CHUNK, EVT_SEARCH_END, EVT_SEARCH_ERROR,
     },
 };
 
+/// 検索するキー 1 本分。どの変換で得たキーかを hit に載せるために持つ。
+#[derive(Debug, Clone, Copy)]
+struct QueryKey {
+    key: PositionKey,
+    mirrored: bool,
+    color_flipped: bool,
+}
+
+/// 入力 SFEN から検索キーを列挙する。先頭は常に無変換のキー。
+///
+/// mirror / color_flip の両方が指定されたら組み合わせ (反転 + 先後入替) も引く。
+/// ignore_hands なら全て持ち駒なしの盤面キーになる。
+fn query_keys(input: &SearchPositionInput) -> Result<Vec<QueryKey>, SfenParseError> {
+    let pos = partial_position_from_sfen(&input.sfen)?;
+
+    let mut variants = vec![(pos.clone(), false, false)];
+    if input.mirror {
+        variants.push((mirror_files(&pos), true, false));
+    }
+    if input.color_flip {
+        let flipped = flip_colors(&pos);
+        let mirrored_flipped = input.mirror.then(|| mirror_files(&flipped));
+        variants.push((flipped, false, true));
+        if let Some(p) = mirrored_flipped {
+            variants.push((p, true, true));
+        }
+    }
+
+    let mut keys: Vec<QueryKey> = Vec::with_capacity(variants.len());
+    for (p, mirrored, color_flipped) in variants {
+        let key = if input.ignore_hands {
+            board_key_from_partial_position(&p)
+        } else {
+            key_from_partial_position(&p)
+        };
+        // 対称な局面は同じキーになるので二重に出さない (先に積んだ変換を優先)
+        if keys.iter().any(|qk| qk.key == key) {
+            continue;
+        }
+        keys.push(QueryKey {
+            key,
+            mirrored,
+            color_flipped,
+        });
+    }
+
+    Ok(keys)
+}
+
+/// 1 件分の hit の素。node table を引いて `PositionHit` にするのは流す直前にする
+#[derive(Debug, Clone, Copy)]
+pub(super) struct RawHit {
+    root_id: RootId,
+    occ: Occurrence,
+    qk: QueryKey,
+    hands: Option<u64>,
+}
+
+/// 1 つの root の snapshot を引く。並べ替えは全 root の hit を集めてから行う
+fn search_root(
+    root_id: RootId,
+    snap: &IndexSnapshot,
+    keys: &[QueryKey],
+    by_board: bool,
+    filter: Option<&MetaFilter>,
+) -> Vec<RawHit> {
+    let mut out: Vec<RawHit> = Vec::new();
+    for qk in keys {
+        let raw = |occ, hands| RawHit {
+            root_id,
+            occ,
+            qk: *qk,
+            hands,
+        };
+        if by_board {
+            out.extend(
+                snap.search_board_occurrences(qk.key)
+                    .into_iter()
+                    .map(|(occ, hands)| raw(occ, Some(hands))),
+            );
+        } else {
+            out.extend(
+                snap.search_occurrences_by_key(qk.key)
+                    .into_iter()
+                    .map(|occ| raw(occ, None)),
+            );
+        }
+    }
+    // 変換キーの hit も `(file_id, node_id)` 順に混ぜて流す
+    if keys.len() > 1 {
+        out.sort_by_key(|h| (h.occ.file_id, h.occ.node_id));
+    }
+    if let Some(f) = filter {
+        let ft = &snap.file_table;
+        out.retain(|h| ft.meta_matches(h.occ.file_id, f));
+    }
+    out
+}
+
+/// hit の素を `PositionHit` にする。同じファイルが続く間は node table を引き直さない
+fn materialize_hits(raws: &[RawHit], snaps: &RootSnapshots) -> Vec<PositionHit> {
+    let mut last: Option<((RootId, FileId), Option<NodeTableArc>)> = None;
+    raws.iter()
+        .map(|h| {
+            let file = (h.root_id, h.occ.file_id);
+            let nt = match &last {
+                Some((f, nt)) if *f == file => nt.clone(),
+                _ => {
+                    let nt = snaps
+                        .iter()
+                        .find(|(id, _)| *id == h.root_id)
+                        .and_then(|(_, snap)| snap.node_tables.get(h.occ.file_id));
+                    last = Some((file, nt.clone()));
+                    nt
+                }
+            };
+            let cursor = nt
+                .as_ref()
+                .and_then(|nt| nt.cursor_lite(h.occ.node_id))
+                .unwrap_or_else(CursorLite::root);
+            let flags = nt.as_ref().map_or(0, |nt| nt.flags(h.occ.node_id));
+            PositionHit {
+                root_id: h.root_id,
+                occ: h.occ,
+                cursor,
+                mirrored: h.qk.mirrored,
+                color_flipped: h.qk.color_flipped,
+                hands: h.hands.map(|h| hands_to_sfen(&unpack_hands(h))),
+                hit_count: None,
+                repetition: flags & NODE_FLAG_REPETITION != 0,
+                perpetual_check: flags & NODE_FLAG_PERPETUAL_CHECK != 0,
+            }
+        })
+        .collect()
+}
+
+/// 検索の結果。並べ替え・まとめが要る時だけ全件を `PositionHit` にしておき、
+/// それ以外は hit の素のまま持って流す / 書き出す直前に組み立てる
+#[derive(Debug, Clone)]
+pub(super) enum SearchHits {
+    Hits(Arc<Vec<PositionHit>>),
+    Raw(Arc<Vec<RawHit>>),
+}
+
+impl SearchHits {
+    fn len(&self) -> usize {
+        match self {
+            Self::Hits(v) => v.len(),
+            Self::Raw(v) => v.len(),
+        }
+    }
+
+    fn chunk(&self, range: std::ops::Range<usize>, snaps: &RootSnapshots) -> Vec<PositionHit> {
+        match self {
+            Self::Hits(v) => v[range].to_vec(),
+            Self::Raw(v) => materialize_hits(&v[range], snaps),
+        }
+    }
+
+    /// 全件を `PositionHit` にする。hit の素はその時点の snapshot で組み立てる
+    pub(super) fn into_hits(self, snaps: &RootSnapshots) -> Arc<Vec<PositionHit>> {
+        match self {
+            Self::Hits(v) => v,
+            Self::Raw(v) => Arc::new(materialize_hits(&v, snaps)),
+        }
+    }
+}
+
+/// パターン検索で読むファイル: (root, file_id, gen, path, index 時の stat)
+type PatternFile = (RootId, FileId, Gen, String, Option<(u64, u128)>);
+
+/// パターン検索で 1 回の spawn_blocking に渡すファイル数。
+/// これごとに cancel を見て、溜まった hit を chunk として流す。
+const PATTERN_FILE_BATCH: usize = 64;
+
+/// hit 群に対応するファイルパスを添えて chunk を 1 つ emit する。
+fn emit_hits_chunk(
+    handle: &AppHandle,
+    request_id: RequestId,
+    hits: Vec<PositionHit>,
+    snaps: &RootSnapshots,
+) {
+    let mut file_paths: HashMap<(RootId, FileId), String> = HashMap::new();
+    for h in &hits {
+        let key = (h.root_id, h.occ.file_id);
+        if file_paths.contains_key(&key) {
+            continue;
+        }
+        let path = snaps
+            .iter()
+            .find(|(id, _)| *id == h.root_id)
+            .and_then(|(_, snap)| snap.file_table.get_path(h.occ.file_id))
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        file_paths.insert(key, path);
+    }
+
+    let files = file_paths
+        .into_iter()
+        .map(
+            |((root_id, file_id), abs_path)| super::types::FilePathEntry {
+                root_id,
+                file_id,
+                abs_path,
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let _ = handle.emit(
+        EVT_SEARCH_CHUNK,
+        SearchChunkPayload {
+            request_id,
+            chunk: hits,
+            files,
+        },
+    );
+}
+
+/// 書き出し (export) 用に結果を覚えておく直近の検索数
+const RECENT_RESULTS: usize = 8;
+
 #[derive(Debug)]
 pub struct QueryService {
-    store: Arc<IndexStore>,
+    roots: Arc<RootSet>,
     next_request_id: AtomicU64,
     app_handle: Arc<RwLock<Option<AppHandle>>>,
     cancellations: Arc<Mutex<HashMap<RequestId, CancellationToken>>>,
+    /// 最後まで流し終えた検索の hit (古いものから捨てる)
+    recent: Arc<Mutex<VecDeque<(RequestId, SearchHits)>>>,
 }
 
 impl QueryService {
-    pub fn new(store: Arc<IndexStore>) -> Self {
+    pub fn new(roots: Arc<RootSet>) -> Self {
         Self {
-            store,
+            roots,
             next_request_id: AtomicU64::new(1),
             app_handle: Arc::new(RwLock::new(None)),
             cancellations: Arc::new(Mutex::new(HashMap::new())),
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_RESULTS))),
+        }
+    }
+
+    /// 直近に完了した検索の hit を返す。古くて捨てた / キャンセルされたものは None
+    pub(super) fn recent_hits(&self, request_id: RequestId) -> Option<SearchHits> {
+        self.recent
+            .lock()
+            .iter()
+            .find(|(rid, _)| *rid == request_id)
+            .map(|(_, hits)| hits.clone())
+    }
+
+    fn remember_hits(&self, request_id: RequestId, hits: SearchHits) {
+        let mut recent = self.recent.lock();
+        if recent.len() >= RECENT_RESULTS {
+            recent.pop_front();
         }
+        recent.push_back((request_id, hits));
     }
 
     pub async fn set_app_handle(&self, handle: AppHandle) {
@@ -68,6 +321,73 @@ impl QueryService {
         Ok(SearchPositionOutput { request_id })
     }
 
+    /// パターン検索を spawn し、request_id を即座に return する。
+    /// 結果は局面検索と同じ `EVT_SEARCH_*` で流れ、`cancel` も共通。
+    pub async fn start_pattern_search(
+        self: Arc<Self>,
+        input: SearchPatternInput,
+    ) -> Result<SearchPositionOutput, String> {
+        let handle = self.app_handle.read().await.clone();
+        let Some(handle) = handle else {
+            return Err("search app handle not ready".to_string());
+        };
+
+        let pattern = Pattern::compile(&input).map_err(|e| e.to_string())?;
+
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancellationToken::new();
+        self.cancellations.lock().insert(request_id, cancel.clone());
+
+        let me = self.clone();
+        tauri::async_runtime::spawn(async move {
+            me.run_pattern_search(
+                request_id,
+                pattern,
+                input.consistency,
+                input.chunk_size,
+                handle,
+                cancel,
+            )
+            .await;
+        });
+
+        Ok(SearchPositionOutput { request_id })
+    }
+
+    /// 局面列検索を spawn し、request_id を即座に return する。
+    /// SFEN の誤りや局面数不足はここで Err にする。
+    pub async fn start_sequence_search(
+        self: Arc<Self>,
+        input: SearchSequenceInput,
+    ) -> Result<SearchPositionOutput, String> {
+        let handle = self.app_handle.read().await.clone();
+        let Some(handle) = handle else {
+            return Err("search app handle not ready".to_string());
+        };
+
+        if input.sfens.len() < 2 {
+            return Err("sequence search needs at least 2 positions".to_string());
+        }
+        let keys = input
+            .sfens
+            .iter()
+            .map(|s| position_key_from_sfen(s.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancellationToken::new();
+        self.cancellations.lock().insert(request_id, cancel.clone());
+
+        let me = self.clone();
+        tauri::async_runtime::spawn(async move {
+            me.run_sequence_search(request_id, keys, input.chunk_size, handle, cancel)
+                .await;
+        });
+
+        Ok(SearchPositionOutput { request_id })
+    }
+
     /// 進行中の検索をキャンセル (C-H2)。
     pub fn cancel(&self, request_id: RequestId) {
         if let Some(token) = self.cancellations.lock().remove(&request_id) {
@@ -82,20 +402,56 @@ impl QueryService {
         handle: AppHandle,
         cancel: CancellationToken,
     ) {
-        let snap = self.store.snapshot();
-        let stale = snap.state != StoreIndexState::Ready;
+        let snaps = self.roots.snapshots();
+        let stale = snaps
+            .iter()
+            .any(|(_, snap)| snap.state != StoreIndexState::Ready);
         let _ = handle.emit(EVT_SEARCH_BEGIN, SearchBeginPayload { request_id, stale });
 
         let chunk_size = (input.chunk_size.clamp(1, 10_000)) as usize;
 
-        match position_key_from_sfen(&input.sfen) {
-            Ok(key) => {
+        let prepared = query_keys(&input)
+            .map_err(|e| e.to_string())
+            .and_then(|keys| {
+                let filter = input
+                    .filter
+                    .as_ref()
+                    .map(MetaFilter::compile)
+                    .transpose()
+                    .map_err(|e| e.to_string())?;
+                Ok((keys, filter))
+            });
+
+        match prepared {
+            Ok((keys, filter)) => {
                 // 検索本体は CPU bound なので spawn_blocking に逃がす。
                 // ここを await ポイントにすることで、最初の chunk が出るまでの間も
                 // Tokio runtime が他タスク (cancel, watcher, 他検索) を進められる。
-                let snap_for_search = snap.clone();
-                let occs = match tokio::task::spawn_blocking(move || {
-                    snap_for_search.search_occurrences_by_key(key)
+                let snaps_for_search = snaps.clone();
+                let by_board = input.ignore_hands;
+                let (group_by_file, sort) = (input.group_by_file, input.sort);
+                let hits = match tokio::task::spawn_blocking(move || {
+                    // root の追加順に引いてつなげ、並べ替え・まとめは全 root を通して行う
+                    let mut raws = Vec::new();
+                    for (root_id, snap) in &snaps_for_search {
+                        raws.extend(search_root(
+                            *root_id,
+                            snap,
+                            &keys,
+                            by_board,
+                            filter.as_ref(),
+                        ));
+                    }
+                    if !group_by_file && sort.is_none() {
+                        return SearchHits::Raw(Arc::new(raws));
+                    }
+                    let hits = materialize_hits(&raws, &snaps_for_search);
+                    SearchHits::Hits(Arc::new(arrange_hits(
+                        hits,
+                        &snaps_for_search,
+                        group_by_file,
+                        sort,
+                    )))
                 })
                 .await
                 {
@@ -119,71 +475,228 @@ impl QueryService {
                     return;
                 }
 
-                let ft = snap.file_table.clone();
-                let nts = snap.node_tables.clone();
-
-                for chunk in occs.chunks(chunk_size) {
+                // 並べ替えが無ければ chunk ごとに hit を組み立てる (全件を先に作らない)
+                let total = hits.len();
+                for start in (0..total).step_by(chunk_size) {
                     if cancel.is_cancelled() {
                         log::debug!("[query] rid={request_id} cancelled mid-stream");
                         break;
                     }
 
-                    let mut hits: Vec<PositionHit> = Vec::with_capacity(chunk.len());
-                    for occ in chunk {
-                        let cursor = nts
-                            .get(occ.file_id)
-                            .and_then(|nt| nt.cursor_lite(occ.node_id))
-                            .unwrap_or_else(CursorLite::root);
-                        hits.push(PositionHit { occ: *occ, cursor });
-                    }
+                    let chunk = hits.chunk(start..(start + chunk_size).min(total), &snaps);
+                    emit_hits_chunk(&handle, request_id, chunk, &snaps);
+
+                    // chunk 間に await ポイントを入れる。
+                    // これが無いと連続 emit が同一 Tokio tick に閉じ、IPC / React batching
+                    // の都合でフロントには 1 フレームに合流して届く。yield_now で
+                    // runtime に制御を返し、実時間ストリーミングを成立させる。
+                    tokio::task::yield_now().await;
+                }
+
+                if !cancel.is_cancelled() {
+                    self.remember_hits(request_id, hits);
+                }
+                let _ = handle.emit(EVT_SEARCH_END, SearchEndPayload { request_id });
+            }
+            Err(message) => {
+                let _ = handle.emit(
+                    EVT_SEARCH_ERROR,
+                    SearchErrorPayload {
+                        request_id,
+                        message,
+                    },
+                );
+            }
+        }
+
+        self.cancellations.lock().remove(&request_id);
+    }
+
+    /// index 済みの生存ファイルを読み直し、パターンに合う局面を流す。
+    ///
+    /// index のキーはハッシュで盤面を復元できないため、パターンを当てるには
+    /// 全ファイルを読み直して parse するしかない (ファイル数 × 1 局分の parse・build)。
+    /// `PATTERN_FILE_BATCH` ごとに spawn_blocking して chunk を流し、cancel は
+    /// ファイル 1 つごとに見て、キャンセル後は次のファイルを読まない。hit はファイル順に出る。
+    ///
+    /// `WaitForClean` なら、index に載せた後に書き換わったファイル (node_id が index と
+    /// ずれている) と、まだ走査に載っていないファイルは読まない。
+    async fn run_pattern_search(
+        &self,
+        request_id: RequestId,
+        pattern: Pattern,
+        consistency: Consistency,
+        chunk_size: u32,
+        handle: AppHandle,
+        cancel: CancellationToken,
+    ) {
+        let snaps = self.roots.snapshots();
+        let stale = snaps
+            .iter()
+            .any(|(_, snap)| snap.state != StoreIndexState::Ready);
+        let _ = handle.emit(EVT_SEARCH_BEGIN, SearchBeginPayload { request_id, stale });
+
+        let chunk_size = (chunk_size.clamp(1, 10_000)) as usize;
+        let pattern = Arc::new(pattern);
 
-                    let mut file_paths: HashMap<FileId, String> = HashMap::new();
-                    for h in &hits {
-                        let fid = h.occ.file_id;
-                        if file_paths.contains_key(&fid) {
-                            continue;
+        let mut indexed: HashMap<RootId, HashMap<String, (u64, u128)>> = HashMap::new();
+        if matches!(consistency, Consistency::WaitForClean) {
+            for root in self.roots.list() {
+                indexed.insert(root.id, root.project.indexed_stats().await);
+            }
+        }
+
+        let files: Vec<PatternFile> = snaps
+            .iter()
+            .flat_map(|(root_id, snap)| {
+                let stats = indexed.get(root_id);
+                snap.file_table
+                    .iter_all()
+                    .filter(|(_, e)| !e.deleted)
+                    .filter_map(|(fid, e)| match stats {
+                        Some(stats) => {
+                            let stat = *stats.get(&e.path)?;
+                            Some((*root_id, fid, e.r#gen, e.path, Some(stat)))
                         }
-                        let path = ft.get_path(fid).map(|s| s.to_string()).unwrap_or_default();
-                        file_paths.insert(fid, path);
-                    }
+                        None => Some((*root_id, fid, e.r#gen, e.path, None)),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
 
-                    let files = file_paths
-                        .into_iter()
-                        .map(|(file_id, abs_path)| super::types::FilePathEntry {
-                            file_id,
-                            abs_path,
-                        })
-                        .collect::<Vec<_>>();
+        let mut pending: Vec<PositionHit> = Vec::new();
+        let mut all_hits: Vec<PositionHit> = Vec::new();
+        for batch in files.chunks(PATTERN_FILE_BATCH) {
+            if cancel.is_cancelled() {
+                log::debug!("[query] rid={request_id} pattern cancelled mid-scan");
+                break;
+            }
 
+            let batch = batch.to_vec();
+            let pattern = pattern.clone();
+            let cancel2 = cancel.clone();
+            let hits = match tokio::task::spawn_blocking(move || {
+                batch
+                    .iter()
+                    .take_while(|_| !cancel2.is_cancelled())
+                    .flat_map(|(root_id, fid, gen, path, stat)| {
+                        scan_file_for_pattern(*fid, *gen, path, *stat, &pattern)
+                            .into_iter()
+                            .map(move |h| PositionHit {
+                                root_id: *root_id,
+                                ..h
+                            })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            {
+                Ok(v) => v,
+                Err(e) => {
                     let _ = handle.emit(
-                        EVT_SEARCH_CHUNK,
-                        SearchChunkPayload {
+                        EVT_SEARCH_ERROR,
+                        SearchErrorPayload {
                             request_id,
-                            chunk: hits,
-                            files,
+                            message: format!("pattern task join error: {e}"),
                         },
                     );
-
-                    // chunk 間に await ポイントを入れる。
-                    // これが無いと連続 emit が同一 Tokio tick に閉じ、IPC / React batching
-                    // の都合でフロントには 1 フレームに合流して届く。yield_now で
-                    // runtime に制御を返し、実時間ストリーミングを成立させる。
-                    tokio::task::yield_now().await;
+                    self.cancellations.lock().remove(&request_id);
+                    return;
                 }
+            };
 
-                let _ = handle.emit(EVT_SEARCH_END, SearchEndPayload { request_id });
+            all_hits.extend(hits.iter().cloned());
+            pending.extend(hits);
+            while pending.len() >= chunk_size {
+                let rest = pending.split_off(chunk_size);
+                emit_hits_chunk(
+                    &handle,
+                    request_id,
+                    std::mem::replace(&mut pending, rest),
+                    &snaps,
+                );
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        if !cancel.is_cancelled() {
+            if !pending.is_empty() {
+                emit_hits_chunk(&handle, request_id, pending, &snaps);
+            }
+            self.remember_hits(request_id, SearchHits::Hits(Arc::new(all_hits)));
+        }
+
+        let _ = handle.emit(EVT_SEARCH_END, SearchEndPayload { request_id });
+        self.cancellations.lock().remove(&request_id);
+    }
+
+    async fn run_sequence_search(
+        &self,
+        request_id: RequestId,
+        keys: Vec<PositionKey>,
+        chunk_size: u32,
+        handle: AppHandle,
+        cancel: CancellationToken,
+    ) {
+        let snaps = self.roots.snapshots();
+        let stale = snaps
+            .iter()
+            .any(|(_, snap)| snap.state != StoreIndexState::Ready);
+        let _ = handle.emit(EVT_SEARCH_BEGIN, SearchBeginPayload { request_id, stale });
+
+        let chunk_size = (chunk_size.clamp(1, 10_000)) as usize;
+
+        let snaps_for_search = snaps.clone();
+        let hits = match tokio::task::spawn_blocking(move || {
+            let mut hits = Vec::new();
+            for (root_id, snap) in &snaps_for_search {
+                hits.extend(
+                    search_sequence(snap, &keys)
+                        .into_iter()
+                        .map(|h| PositionHit {
+                            root_id: *root_id,
+                            ..h
+                        }),
+                );
             }
+            hits
+        })
+        .await
+        {
+            Ok(v) => v,
             Err(e) => {
                 let _ = handle.emit(
                     EVT_SEARCH_ERROR,
                     SearchErrorPayload {
                         request_id,
-                        message: e.to_string(),
+                        message: format!("sequence task join error: {e}"),
                     },
                 );
+                self.cancellations.lock().remove(&request_id);
+                return;
+            }
+        };
+
+        if cancel.is_cancelled() {
+            log::debug!("[query] rid={request_id} sequence cancelled before stream");
+            self.cancellations.lock().remove(&request_id);
+            return;
+        }
+
+        for chunk in hits.chunks(chunk_size) {
+            if cancel.is_cancelled() {
+                log::debug!("[query] rid={request_id} sequence cancelled mid-stream");
+                break;
             }
+            emit_hits_chunk(&handle, request_id, chunk.to_vec(), &snaps);
+            tokio::task::yield_now().await;
         }
 
+        if !cancel.is_cancelled() {
+            self.remember_hits(request_id, SearchHits::Hits(Arc::new(hits)));
+        }
+        let _ = handle.emit(EVT_SEARCH_END, SearchEndPayload { request_id });
         self.cancellations.lock().remove(&request_id);
     }
 }
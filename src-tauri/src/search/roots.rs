@@ -0,0 +1,112 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::RwLock;
+
+use super::{
+    index_store::{IndexSnapshot, IndexStore},
+    project_manager::ProjectManager,
+    types::RootId,
+};
+
+/// 検索で束ねる root ごとの snapshot (root の追加順)
+pub type RootSnapshots = Vec<(RootId, Arc<IndexSnapshot>)>;
+
+/// 開いている棋譜フォルダ 1 つ分。index・cache・watcher は root ごとに独立している
+#[derive(Debug)]
+pub struct ProjectRoot {
+    pub id: RootId,
+    pub root_dir: PathBuf,
+    pub store: Arc<IndexStore>,
+    pub project: Arc<ProjectManager>,
+    /// 同じフォルダかどうかの判定に使う正規化したパス
+    canonical_dir: PathBuf,
+}
+
+impl ProjectRoot {
+    /// `dir` がこの root と同じフォルダを指しているか
+    pub fn is_dir(&self, dir: &Path) -> bool {
+        self.canonical_dir == canonical_root_dir(dir)
+    }
+}
+
+/// 同時に開いている root の集合。
+///
+/// root の追加・削除は他の root の index に触らない。FileId は root ごとの採番なので、
+/// hit やファイルは (RootId, FileId) で識別する。
+#[derive(Debug)]
+pub struct RootSet {
+    roots: RwLock<Vec<Arc<ProjectRoot>>>,
+    next_id: AtomicU32,
+}
+
+impl Default for RootSet {
+    fn default() -> Self {
+        Self {
+            roots: RwLock::new(Vec::new()),
+            // 0 は「root 不明」(古い hit の serde default) 用に空けておく
+            next_id: AtomicU32::new(1),
+        }
+    }
+}
+
+impl RootSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// root を追加する。同じフォルダが既に開いていればそれを返す (bool は新規かどうか)
+    ///
+    /// 正規化したパスで比べるので、`/a/b` と `/a/b/` は同じ root になる。
+    pub fn add(&self, root_dir: &Path) -> (Arc<ProjectRoot>, bool) {
+        let canonical_dir = canonical_root_dir(root_dir);
+        let mut roots = self.roots.write();
+        if let Some(r) = roots.iter().find(|r| r.canonical_dir == canonical_dir) {
+            return (r.clone(), false);
+        }
+        let root = Arc::new(ProjectRoot {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            root_dir: root_dir.components().collect(),
+            store: Arc::new(IndexStore::new()),
+            project: Arc::new(ProjectManager::new()),
+            canonical_dir,
+        });
+        roots.push(root.clone());
+        (root, true)
+    }
+
+    /// root を外す。watcher の停止は呼び出し側で行う
+    pub fn remove(&self, id: RootId) -> Option<Arc<ProjectRoot>> {
+        let mut roots = self.roots.write();
+        let i = roots.iter().position(|r| r.id == id)?;
+        Some(roots.remove(i))
+    }
+
+    pub fn get(&self, id: RootId) -> Option<Arc<ProjectRoot>> {
+        self.roots.read().iter().find(|r| r.id == id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Arc<ProjectRoot>> {
+        self.roots.read().clone()
+    }
+
+    pub fn snapshots(&self) -> RootSnapshots {
+        self.roots
+            .read()
+            .iter()
+            .map(|r| (r.id, r.store.snapshot()))
+            .collect()
+    }
+}
+
+/// root の同一判定に使うパス。canonicalize できなければ (まだ無いフォルダ等) 末尾の
+/// 区切りなどだけを落としたものにする
+fn canonical_root_dir(root_dir: &Path) -> PathBuf {
+    fs::canonicalize(root_dir).unwrap_or_else(|_| root_dir.components().collect())
+}
@@ -0,0 +1,389 @@
+use shogi_core::{Color, Move as CoreMove, PartialPosition, Piece, PieceKind, Square};
+use thiserror::Error;
+
+/// 将棋のルール上指せない手の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum IllegalMove {
+    #[error("no own piece on the source square")]
+    NoPiece,
+
+    #[error("the piece cannot move to the destination")]
+    Unreachable,
+
+    #[error("cannot capture own piece")]
+    CaptureOwnPiece,
+
+    #[error("drop on an occupied square")]
+    DropOnOccupied,
+
+    #[error("the piece is not in hand")]
+    NotInHand,
+
+    #[error("invalid promotion")]
+    InvalidPromotion,
+
+    #[error("piece with no legal moves (行き所のない駒)")]
+    DeadPiece,
+
+    #[error("two unpromoted pawns on the same file (二歩)")]
+    DoublePawn,
+
+    #[error("mate by pawn drop (打ち歩詰め)")]
+    PawnDropMate,
+
+    #[error("own king is left in check")]
+    SelfCheck,
+}
+
+const HAND_KINDS: [PieceKind; 7] = [
+    PieceKind::Pawn,
+    PieceKind::Lance,
+    PieceKind::Knight,
+    PieceKind::Silver,
+    PieceKind::Gold,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+];
+
+/// 先手から見た (筋の差, 段の差)。段は小さい方が先手の前
+type Delta = (i8, i8);
+
+const KNIGHT_STEPS: &[Delta] = &[(-1, -2), (1, -2)];
+const SILVER_STEPS: &[Delta] = &[(-1, -1), (0, -1), (1, -1), (-1, 1), (1, 1)];
+const GOLD_STEPS: &[Delta] = &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (0, 1)];
+const ORTHOGONAL: &[Delta] = &[(0, -1), (-1, 0), (1, 0), (0, 1)];
+const DIAGONAL: &[Delta] = &[(-1, -1), (1, -1), (-1, 1), (1, 1)];
+const ALL_DIRECTIONS: &[Delta] = &[
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// 1 マスだけ動ける方向 (先手視点)
+pub(crate) fn steps(kind: PieceKind) -> &'static [Delta] {
+    match kind {
+        PieceKind::Pawn => &[(0, -1)],
+        PieceKind::Knight => KNIGHT_STEPS,
+        PieceKind::Silver => SILVER_STEPS,
+        PieceKind::Gold
+        | PieceKind::ProPawn
+        | PieceKind::ProLance
+        | PieceKind::ProKnight
+        | PieceKind::ProSilver => GOLD_STEPS,
+        PieceKind::King => ALL_DIRECTIONS,
+        PieceKind::ProBishop => ORTHOGONAL,
+        PieceKind::ProRook => DIAGONAL,
+        PieceKind::Lance | PieceKind::Bishop | PieceKind::Rook => &[],
+    }
+}
+
+/// 駒に当たるまで走れる方向 (先手視点)
+pub(crate) fn slides(kind: PieceKind) -> &'static [Delta] {
+    match kind {
+        PieceKind::Lance => &[(0, -1)],
+        PieceKind::Bishop | PieceKind::ProBishop => DIAGONAL,
+        PieceKind::Rook | PieceKind::ProRook => ORTHOGONAL,
+        _ => &[],
+    }
+}
+
+/// 先手視点の方向を color の向きに直す
+#[inline]
+pub(crate) fn oriented((df, dr): Delta, color: Color) -> Delta {
+    match color {
+        Color::Black => (df, dr),
+        Color::White => (df, -dr),
+    }
+}
+
+#[inline]
+pub(crate) fn offset(sq: Square, (df, dr): Delta) -> Option<Square> {
+    let file = sq.file() as i8 + df;
+    let rank = sq.rank() as i8 + dr;
+    if !(1..=9).contains(&file) || !(1..=9).contains(&rank) {
+        return None;
+    }
+    Square::new(file as u8, rank as u8)
+}
+
+#[inline]
+fn opposite(c: Color) -> Color {
+    match c {
+        Color::Black => Color::White,
+        Color::White => Color::Black,
+    }
+}
+
+pub fn king_square(pos: &PartialPosition, color: Color) -> Option<Square> {
+    let king = Piece::new(PieceKind::King, color);
+    Square::all().find(|&sq| pos.piece_at(sq) == Some(king))
+}
+
+/// `sq` に `by` 側の駒が利いているか。
+///
+/// `sq` から 8 方向と桂の位置を逆にたどるので、盤全体は見ない。
+pub fn is_attacked(pos: &PartialPosition, sq: Square, by: Color) -> bool {
+    for &d in ALL_DIRECTIONS {
+        // 見つけた駒から `sq` へは盤上の向きで -d。駒の動き (oriented 済み) と比べる
+        let toward = (-d.0, -d.1);
+        let mut cur = sq;
+        let mut dist = 0;
+        while let Some(next) = offset(cur, d) {
+            dist += 1;
+            cur = next;
+            let Some(piece) = pos.piece_at(cur) else {
+                continue;
+            };
+            if piece.color() == by {
+                let kind = piece.piece_kind();
+                if slides(kind).iter().any(|&s| oriented(s, by) == toward) {
+                    return true;
+                }
+                if dist == 1 && steps(kind).iter().any(|&s| oriented(s, by) == toward) {
+                    return true;
+                }
+            }
+            break;
+        }
+    }
+
+    let knight = Piece::new(PieceKind::Knight, by);
+    KNIGHT_STEPS.iter().any(|&s| {
+        let (df, dr) = oriented(s, by);
+        offset(sq, (-df, -dr)).is_some_and(|from| pos.piece_at(from) == Some(knight))
+    })
+}
+
+/// color の玉に王手がかかっているか。玉がなければ false
+pub fn is_in_check(pos: &PartialPosition, color: Color) -> bool {
+    king_square(pos, color).is_some_and(|k| is_attacked(pos, k, opposite(color)))
+}
+
+/// 手番側が `mv` を指せるか、将棋のルールをすべて当てて確かめる。
+///
+/// 手番の不一致は `position_apply` 側で弾くので、ここでは手番側の手として見る。
+pub fn check_move(pos: &PartialPosition, mv: CoreMove) -> Result<(), IllegalMove> {
+    check_pseudo_legal(pos, mv)?;
+
+    let me = pos.side_to_move();
+    let mut next = pos.clone();
+    next.make_move(mv).ok_or(IllegalMove::Unreachable)?;
+    if is_in_check(&next, me) {
+        return Err(IllegalMove::SelfCheck);
+    }
+
+    // 打ち歩詰め: 歩を打って王手にし、相手に逃れる手がない
+    if let CoreMove::Drop { piece, .. } = mv {
+        if piece.piece_kind() == PieceKind::Pawn
+            && is_in_check(&next, opposite(me))
+            && !has_legal_move(&next)
+        {
+            return Err(IllegalMove::PawnDropMate);
+        }
+    }
+
+    Ok(())
+}
+
+/// 自玉の安全と打ち歩詰め以外の条件 (駒の動き・成り・二歩・行き所) を見る
+fn check_pseudo_legal(pos: &PartialPosition, mv: CoreMove) -> Result<(), IllegalMove> {
+    let me = pos.side_to_move();
+
+    match mv {
+        CoreMove::Normal { from, to, promote } => {
+            let piece = pos.piece_at(from).ok_or(IllegalMove::NoPiece)?;
+            if piece.color() != me {
+                return Err(IllegalMove::NoPiece);
+            }
+            if pos.piece_at(to).is_some_and(|p| p.color() == me) {
+                return Err(IllegalMove::CaptureOwnPiece);
+            }
+            if !reaches(pos, from, to, piece) {
+                return Err(IllegalMove::Unreachable);
+            }
+
+            let kind = piece.piece_kind();
+            if promote {
+                if !promotable(kind) || !(in_promotion_zone(from, me) || in_promotion_zone(to, me))
+                {
+                    return Err(IllegalMove::InvalidPromotion);
+                }
+            } else if is_dead_square(kind, to, me) {
+                return Err(IllegalMove::DeadPiece);
+            }
+        }
+        CoreMove::Drop { piece, to } => {
+            let kind = piece.piece_kind();
+            if piece.color() != me || pos.hand_of_a_player(me).count(kind).unwrap_or(0) == 0 {
+                return Err(IllegalMove::NotInHand);
+            }
+            if pos.piece_at(to).is_some() {
+                return Err(IllegalMove::DropOnOccupied);
+            }
+            if is_dead_square(kind, to, me) {
+                return Err(IllegalMove::DeadPiece);
+            }
+            if kind == PieceKind::Pawn && has_pawn_on_file(pos, to.file(), me) {
+                return Err(IllegalMove::DoublePawn);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 手番側に自玉を王手に晒さない手が 1 つでもあるか (打ち歩詰めの判定用)
+fn has_legal_move(pos: &PartialPosition) -> bool {
+    let me = pos.side_to_move();
+    let is_safe = |mv: CoreMove| {
+        if check_pseudo_legal(pos, mv).is_err() {
+            return false;
+        }
+        let mut next = pos.clone();
+        next.make_move(mv).is_some() && !is_in_check(&next, me)
+    };
+
+    for from in Square::all() {
+        let Some(piece) = pos.piece_at(from) else {
+            continue;
+        };
+        if piece.color() != me {
+            continue;
+        }
+        for to in destinations(pos, from, piece) {
+            let found = [false, true]
+                .into_iter()
+                .any(|promote| is_safe(CoreMove::Normal { from, to, promote }));
+            if found {
+                return true;
+            }
+        }
+    }
+
+    // 合駒。王手を防げるのは空きマスへの打ち込みだけ
+    let hand = pos.hand_of_a_player(me);
+    for kind in HAND_KINDS {
+        if hand.count(kind).unwrap_or(0) == 0 {
+            continue;
+        }
+        let piece = Piece::new(kind, me);
+        if Square::all()
+            .filter(|&to| pos.piece_at(to).is_none())
+            .any(|to| is_safe(CoreMove::Drop { piece, to }))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// 駒の動きで行けるマス (自駒のあるマスは除く)
+fn destinations(pos: &PartialPosition, from: Square, piece: Piece) -> Vec<Square> {
+    let color = piece.color();
+    let kind = piece.piece_kind();
+    let mut out = Vec::new();
+
+    for &s in steps(kind) {
+        if let Some(to) = offset(from, oriented(s, color)) {
+            if !matches!(pos.piece_at(to), Some(p) if p.color() == color) {
+                out.push(to);
+            }
+        }
+    }
+    for &s in slides(kind) {
+        let d = oriented(s, color);
+        let mut cur = from;
+        while let Some(to) = offset(cur, d) {
+            match pos.piece_at(to) {
+                None => out.push(to),
+                Some(p) => {
+                    if p.color() != color {
+                        out.push(to);
+                    }
+                    break;
+                }
+            }
+            cur = to;
+        }
+    }
+
+    out
+}
+
+/// from の駒が (間の駒に遮られずに) to へ動けるか
+fn reaches(pos: &PartialPosition, from: Square, to: Square, piece: Piece) -> bool {
+    let color = piece.color();
+    let kind = piece.piece_kind();
+    let delta = (
+        to.file() as i8 - from.file() as i8,
+        to.rank() as i8 - from.rank() as i8,
+    );
+
+    if steps(kind).iter().any(|&s| oriented(s, color) == delta) {
+        return true;
+    }
+
+    slides(kind).iter().any(|&s| {
+        let d = oriented(s, color);
+        let mut cur = from;
+        while let Some(next) = offset(cur, d) {
+            if next == to {
+                return true;
+            }
+            if pos.piece_at(next).is_some() {
+                return false;
+            }
+            cur = next;
+        }
+        false
+    })
+}
+
+fn promotable(kind: PieceKind) -> bool {
+    matches!(
+        kind,
+        PieceKind::Pawn
+            | PieceKind::Lance
+            | PieceKind::Knight
+            | PieceKind::Silver
+            | PieceKind::Bishop
+            | PieceKind::Rook
+    )
+}
+
+/// color から見た段 (1 = 敵陣の一番奥)
+#[inline]
+fn relative_rank(sq: Square, color: Color) -> u8 {
+    match color {
+        Color::Black => sq.rank(),
+        Color::White => 10 - sq.rank(),
+    }
+}
+
+#[inline]
+fn in_promotion_zone(sq: Square, color: Color) -> bool {
+    relative_rank(sq, color) <= 3
+}
+
+/// 成らずにそのマスへ置くと二度と動けない (行き所のない駒)
+fn is_dead_square(kind: PieceKind, sq: Square, color: Color) -> bool {
+    let r = relative_rank(sq, color);
+    match kind {
+        PieceKind::Pawn | PieceKind::Lance => r == 1,
+        PieceKind::Knight => r <= 2,
+        _ => false,
+    }
+}
+
+fn has_pawn_on_file(pos: &PartialPosition, file: u8, color: Color) -> bool {
+    let pawn = Piece::new(PieceKind::Pawn, color);
+    (1..=9)
+        .filter_map(|rank| Square::new(file, rank))
+        .any(|sq| pos.piece_at(sq) == Some(pawn))
+}
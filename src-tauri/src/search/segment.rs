@@ -1,20 +1,100 @@
 use std::sync::Arc;
 
-use super::{position_key::PositionKey, types::Occurrence};
+use super::{position_key::PositionKey, segment_file::SegmentFile, types::Occurrence};
 
 pub type SegmentArc = Arc<Segment>;
 
+/// board index 用のエントリ: (盤面キー, 出現箇所, pack_hands した持ち駒)
+pub type BoardEntry = (PositionKey, Occurrence, u64);
+
 /// bucket 内の不変セグメント (SoA レイアウト)。
 ///
 /// (z0, z1) が binary search のホット列なので並列 Vec にする。Occurrence 列は
 /// hit 後にしか参照しないため、binary search 中の L1 を z0/z1 が占有できる。
+///
+/// `hands` は持ち駒を無視した board index のセグメントだけが持つ列で、
+/// 通常の (完全一致) セグメントでは空。
+///
+/// 列は RAM 上 (build 直後) か、セグメントファイル上 (cache から復元) のどちらか。
+/// 後者は mmap したファイルの上で直接 binary search し、hit もそこから読む。
 #[derive(Debug, Default)]
 pub struct Segment {
+    cols: Columns,
+}
+
+#[derive(Debug)]
+enum Columns {
+    Mem(MemColumns),
+    Disk(DiskColumns),
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self::Mem(MemColumns::default())
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemColumns {
     z0: Vec<u64>,
     z1: Vec<u64>,
     file_ids: Vec<u32>,
     gens: Vec<u32>,
     node_ids: Vec<u32>,
+    hands: Vec<u64>,
+}
+
+/// セグメントファイル内の 1 bucket。列の並びは `segment_file` を参照
+#[derive(Debug)]
+struct DiskColumns {
+    file: Arc<SegmentFile>,
+    base: u64,
+    len: usize,
+    has_hands: bool,
+}
+
+impl DiskColumns {
+    /// 列 `col` (0=z0, 1=z1, 2=file_id, 3=gen, 4=node_id, 5=hands) の先頭 offset
+    #[inline]
+    fn col_offset(&self, col: u8) -> u64 {
+        let n = self.len as u64;
+        self.base
+            + match col {
+                0 => 0,
+                1 => 8 * n,
+                2 => 16 * n,
+                3 => 20 * n,
+                4 => 24 * n,
+                _ => 28 * n,
+            }
+    }
+
+    #[inline]
+    fn u64_at(&self, col: u8, idx: usize) -> u64 {
+        self.file.read_u64(self.col_offset(col) + 8 * idx as u64)
+    }
+
+    #[inline]
+    fn u32_at(&self, col: u8, idx: usize) -> u32 {
+        self.file.read_u32(self.col_offset(col) + 4 * idx as u64)
+    }
+
+    /// 全列を読んで RAM 上の列にする
+    fn load(&self) -> MemColumns {
+        let n = self.len;
+        MemColumns {
+            z0: self.file.read_u64s(self.col_offset(0), n),
+            z1: self.file.read_u64s(self.col_offset(1), n),
+            file_ids: self.file.read_u32s(self.col_offset(2), n),
+            gens: self.file.read_u32s(self.col_offset(3), n),
+            node_ids: self.file.read_u32s(self.col_offset(4), n),
+            hands: if self.has_hands {
+                self.file.read_u64s(self.col_offset(5), n)
+            } else {
+                Vec::new()
+            },
+        }
+    }
 }
 
 impl Segment {
@@ -35,53 +115,138 @@ impl Segment {
             node_ids.push(occ.node_id);
         }
 
-        Self {
+        Self::from_mem(MemColumns {
             z0,
             z1,
             file_ids,
             gens,
             node_ids,
+            hands: Vec::new(),
+        })
+    }
+
+    /// board index 用。`entries` は (z0,z1) 昇順ソート済みであること。
+    pub fn new_sorted_with_hands(entries: Vec<BoardEntry>) -> Self {
+        let n = entries.len();
+        let mut z0 = Vec::with_capacity(n);
+        let mut z1 = Vec::with_capacity(n);
+        let mut file_ids = Vec::with_capacity(n);
+        let mut gens = Vec::with_capacity(n);
+        let mut node_ids = Vec::with_capacity(n);
+        let mut hands = Vec::with_capacity(n);
+
+        for (k, occ, h) in entries {
+            z0.push(k.z0);
+            z1.push(k.z1);
+            file_ids.push(occ.file_id);
+            gens.push(occ.gen);
+            node_ids.push(occ.node_id);
+            hands.push(h);
         }
+
+        Self::from_mem(MemColumns {
+            z0,
+            z1,
+            file_ids,
+            gens,
+            node_ids,
+            hands,
+        })
     }
 
+    /// `hands` は board index なら他の列と同じ長さ、通常セグメントなら空。
     pub fn from_soa(
         z0: Vec<u64>,
         z1: Vec<u64>,
         file_ids: Vec<u32>,
         gens: Vec<u32>,
         node_ids: Vec<u32>,
+        hands: Vec<u64>,
     ) -> Self {
         debug_assert_eq!(z0.len(), z1.len());
         debug_assert_eq!(z0.len(), file_ids.len());
         debug_assert_eq!(z0.len(), gens.len());
         debug_assert_eq!(z0.len(), node_ids.len());
-        Self {
+        debug_assert!(hands.is_empty() || hands.len() == z0.len());
+        Self::from_mem(MemColumns {
             z0,
             z1,
             file_ids,
             gens,
             node_ids,
+            hands,
+        })
+    }
+
+    fn from_mem(cols: MemColumns) -> Self {
+        Self {
+            cols: Columns::Mem(cols),
+        }
+    }
+
+    /// セグメントファイルの `base` から始まる `len` 件の bucket を参照する。
+    pub fn on_disk(file: Arc<SegmentFile>, base: u64, len: usize, has_hands: bool) -> Self {
+        Self {
+            cols: Columns::Disk(DiskColumns {
+                file,
+                base,
+                len,
+                has_hands,
+            }),
+        }
+    }
+
+    /// 全エントリを舐める処理 (compaction・書き出し) の前に、on-disk なら列を
+    /// まとめて読んで RAM 上のセグメントにする。RAM 上ならそのまま返す。
+    pub fn in_memory(self: &Arc<Self>) -> SegmentArc {
+        match &self.cols {
+            Columns::Mem(_) => self.clone(),
+            Columns::Disk(d) => Arc::new(Self::from_mem(d.load())),
+        }
+    }
+
+    #[inline]
+    pub fn has_hands(&self) -> bool {
+        match &self.cols {
+            Columns::Mem(m) => !m.hands.is_empty(),
+            Columns::Disk(d) => d.has_hands,
         }
     }
 
+    /// 列がセグメントファイル上にあるか
+    #[inline]
+    pub fn is_on_disk(&self) -> bool {
+        matches!(self.cols, Columns::Disk(_))
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.z0.is_empty()
+        self.len() == 0
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.z0.len()
+        match &self.cols {
+            Columns::Mem(m) => m.z0.len(),
+            Columns::Disk(d) => d.len,
+        }
     }
 
     #[inline]
     fn cmp_at(&self, idx: usize, key: PositionKey) -> std::cmp::Ordering {
-        (self.z0[idx], self.z1[idx]).cmp(&(key.z0, key.z1))
+        match &self.cols {
+            Columns::Mem(m) => (m.z0[idx], m.z1[idx]).cmp(&(key.z0, key.z1)),
+            // z0 が違えば z1 は読まない
+            Columns::Disk(d) => d
+                .u64_at(0, idx)
+                .cmp(&key.z0)
+                .then_with(|| d.u64_at(1, idx).cmp(&key.z1)),
+        }
     }
 
     fn lower_bound(&self, key: PositionKey) -> usize {
         let mut lo = 0usize;
-        let mut hi = self.z0.len();
+        let mut hi = self.len();
         while lo < hi {
             let mid = lo + (hi - lo) / 2;
             if self.cmp_at(mid, key).is_lt() {
@@ -95,7 +260,7 @@ impl Segment {
 
     fn upper_bound(&self, key: PositionKey) -> usize {
         let mut lo = 0usize;
-        let mut hi = self.z0.len();
+        let mut hi = self.len();
         while lo < hi {
             let mid = lo + (hi - lo) / 2;
             if self.cmp_at(mid, key).is_gt() {
@@ -110,7 +275,7 @@ impl Segment {
     /// key に完全一致する `[lo, hi)` 半開区間。
     pub fn range_by_key(&self, key: PositionKey) -> (usize, usize) {
         let lo = self.lower_bound(key);
-        if lo >= self.z0.len() || self.cmp_at(lo, key).is_ne() {
+        if lo >= self.len() || self.cmp_at(lo, key).is_ne() {
             return (lo, lo);
         }
         let hi = self.upper_bound(key);
@@ -119,22 +284,44 @@ impl Segment {
 
     #[inline]
     pub fn occ_at(&self, idx: usize) -> Occurrence {
-        Occurrence {
-            file_id: self.file_ids[idx],
-            gen: self.gens[idx],
-            node_id: self.node_ids[idx],
+        match &self.cols {
+            Columns::Mem(m) => Occurrence {
+                file_id: m.file_ids[idx],
+                gen: m.gens[idx],
+                node_id: m.node_ids[idx],
+            },
+            Columns::Disk(d) => Occurrence {
+                file_id: d.u32_at(2, idx),
+                gen: d.u32_at(3, idx),
+                node_id: d.u32_at(4, idx),
+            },
         }
     }
 
     #[inline]
     pub fn key_at(&self, idx: usize) -> PositionKey {
-        PositionKey {
-            z0: self.z0[idx],
-            z1: self.z1[idx],
+        match &self.cols {
+            Columns::Mem(m) => PositionKey {
+                z0: m.z0[idx],
+                z1: m.z1[idx],
+            },
+            Columns::Disk(d) => PositionKey {
+                z0: d.u64_at(0, idx),
+                z1: d.u64_at(1, idx),
+            },
+        }
+    }
+
+    /// board index のセグメントなら pack_hands した持ち駒を返す。
+    #[inline]
+    pub fn hands_at(&self, idx: usize) -> Option<u64> {
+        match &self.cols {
+            Columns::Mem(m) => m.hands.get(idx).copied(),
+            Columns::Disk(d) => (d.has_hands && idx < d.len).then(|| d.u64_at(5, idx)),
         }
     }
 
     pub fn iter_entries(&self) -> impl Iterator<Item = (PositionKey, Occurrence)> + '_ {
-        (0..self.z0.len()).map(|i| (self.key_at(i), self.occ_at(i)))
+        (0..self.len()).map(|i| (self.key_at(i), self.occ_at(i)))
     }
 }
@@ -0,0 +1,373 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+
+use super::{
+    index_store::NodeTables,
+    node_table::{ForkPtr, NodeCursor, NodeTable},
+    position_key::PositionKey,
+    segment::{BoardEntry, Segment, SegmentArc},
+    types::{FileId, Occurrence},
+};
+
+const MAGIC: [u8; 8] = *b"OBSSGv01";
+const VERSION: u32 = 2;
+
+/// MAGIC + VERSION + root_hash + token + node table 部の offset
+const HEADER_LEN: u64 = 8 + 4 + 32 + 8 + 8;
+/// (offset u64, len u32) × 256 bucket × 2 (完全一致 / board)
+const DIR_ENTRY_LEN: u64 = 12;
+const DIR_LEN: u64 = DIR_ENTRY_LEN * 256 * 2;
+/// node table の directory 1 件: (offset u64, nodes_len u32, forks_len u32)。offset=0 は無し
+const NT_DIR_ENTRY_LEN: u64 = 16;
+/// NodeCursor 1 件 (tesuu u32, fork_off u32, fork_len u16, flags u16)
+const NT_NODE_LEN: u64 = 12;
+/// ForkPtr 1 件 (te u32, fork_index u32)
+const NT_FORK_LEN: u64 = 8;
+
+/// bucket ごとのセグメントを列で並べた非圧縮ファイル。
+///
+/// ```text
+/// header     : MAGIC, VERSION, root_hash[32], token, nt_offset
+/// directory  : [(offset, len); 256] 完全一致 → [(offset, len); 256] board
+/// bucket     : z0[n] z1[n] file_id[n] gen[n] node_id[n] (board なら hands[n])
+/// node table : count, [(offset, nodes_len, forks_len); count], nodes/forks ...
+/// ```
+///
+/// 起動時はファイルを mmap して header と directory だけを検める。各 bucket は
+/// `Segment::on_disk` として mmap 上で直接 binary search し、node table も
+/// file_id ごとに必要になった分だけ読む (触った所だけがページキャッシュに載る)。
+#[derive(Debug)]
+pub struct SegmentFile {
+    map: Mmap,
+}
+
+impl SegmentFile {
+    fn open(file: &File) -> io::Result<Self> {
+        // SAFETY: セグメントファイルは tmp に書いてから rename で置くだけで、
+        // 置いた後に書き換えることはない
+        let map = unsafe { Mmap::map(file)? };
+        Ok(Self { map })
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    /// 範囲は open 時 (bucket) / 読む前 (node table) に file 長と照合済みであること
+    #[inline]
+    fn bytes(&self, offset: u64, len: usize) -> &[u8] {
+        let start = offset as usize;
+        &self.map[start..start + len]
+    }
+
+    #[inline]
+    fn read_at<const N: usize>(&self, offset: u64) -> [u8; N] {
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(self.bytes(offset, N));
+        buf
+    }
+
+    #[inline]
+    pub fn read_u64(&self, offset: u64) -> u64 {
+        u64::from_le_bytes(self.read_at::<8>(offset))
+    }
+
+    #[inline]
+    pub fn read_u32(&self, offset: u64) -> u32 {
+        u32::from_le_bytes(self.read_at::<4>(offset))
+    }
+
+    #[inline]
+    fn read_u16(&self, offset: u64) -> u16 {
+        u16::from_le_bytes(self.read_at::<2>(offset))
+    }
+
+    /// offset から `n` 個の u64 をまとめて読む
+    pub fn read_u64s(&self, offset: u64, n: usize) -> Vec<u64> {
+        self.bytes(offset, n * 8)
+            .chunks_exact(8)
+            .map(|c| u64::from_le_bytes(c.try_into().unwrap_or([0; 8])))
+            .collect()
+    }
+
+    /// offset から `n` 個の u32 をまとめて読む
+    pub fn read_u32s(&self, offset: u64, n: usize) -> Vec<u32> {
+        self.bytes(offset, n * 4)
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap_or([0; 4])))
+            .collect()
+    }
+}
+
+/// セグメントファイル内の node table 部。file_id ごとに読み出す
+#[derive(Debug)]
+pub struct DiskNodeTables {
+    file: Arc<SegmentFile>,
+    dir: u64,
+    count: u32,
+}
+
+impl DiskNodeTables {
+    /// 載っている file_id の上限 (これ未満の file_id だけを持ちうる)
+    pub fn id_bound(&self) -> usize {
+        self.count as usize
+    }
+
+    /// (データの offset, nodes_len, forks_len)。範囲外・壊れた entry は None
+    fn entry(&self, file_id: FileId) -> Option<(u64, u32, u32)> {
+        if file_id >= self.count {
+            return None;
+        }
+        let at = self.dir + NT_DIR_ENTRY_LEN * file_id as u64;
+        let offset = self.file.read_u64(at);
+        if offset == 0 {
+            return None;
+        }
+        let nodes_len = self.file.read_u32(at + 8);
+        let forks_len = self.file.read_u32(at + 12);
+        let end = offset
+            .checked_add(NT_NODE_LEN * nodes_len as u64)?
+            .checked_add(NT_FORK_LEN * forks_len as u64)?;
+        (end <= self.file.len()).then_some((offset, nodes_len, forks_len))
+    }
+
+    pub fn nodes_len(&self, file_id: FileId) -> usize {
+        self.entry(file_id).map_or(0, |(_, n, _)| n as usize)
+    }
+
+    pub fn get(&self, file_id: FileId) -> Option<NodeTable> {
+        let (offset, nodes_len, forks_len) = self.entry(file_id)?;
+        let f = &self.file;
+
+        let mut nt = NodeTable::empty();
+        nt.nodes.reserve(nodes_len as usize);
+        nt.forks.reserve(forks_len as usize);
+        for i in 0..nodes_len as u64 {
+            let at = offset + NT_NODE_LEN * i;
+            nt.nodes.push(NodeCursor {
+                tesuu: f.read_u32(at),
+                fork_off: f.read_u32(at + 4),
+                fork_len: f.read_u16(at + 8),
+                flags: f.read_u16(at + 10),
+            });
+        }
+        let forks = offset + NT_NODE_LEN * nodes_len as u64;
+        for i in 0..forks_len as u64 {
+            let at = forks + NT_FORK_LEN * i;
+            nt.forks.push(ForkPtr {
+                te: f.read_u32(at),
+                fork_index: f.read_u32(at + 4),
+            });
+        }
+        Some(nt)
+    }
+}
+
+pub struct RestoredSegments {
+    pub buckets: [Vec<SegmentArc>; 256],
+    pub board_buckets: [Vec<SegmentArc>; 256],
+    pub node_tables: DiskNodeTables,
+}
+
+/// セグメントファイルを書く。
+///
+/// `exact(b)` / `board(b)` は bucket b の (z0,z1) 昇順のエントリを返す。bucket ごとに
+/// 呼んで書き出すので、全 bucket を同時にメモリに載せない。最後に `node_tables` を書く。
+pub fn write_segment_file(
+    path: &Path,
+    root_hash: &[u8; 32],
+    token: u64,
+    mut exact: impl FnMut(usize) -> Vec<(PositionKey, Occurrence)>,
+    mut board: impl FnMut(usize) -> Vec<BoardEntry>,
+    node_tables: &NodeTables,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+
+    w.write_all(&MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(root_hash)?;
+    w.write_all(&token.to_le_bytes())?;
+    // nt_offset と directory は後で埋める
+    w.write_all(&0u64.to_le_bytes())?;
+    w.write_all(&vec![0u8; DIR_LEN as usize])?;
+
+    let mut dir: Vec<(u64, u32)> = Vec::with_capacity(512);
+    let mut offset = HEADER_LEN + DIR_LEN;
+
+    for b in 0..256 {
+        let v = exact(b);
+        dir.push((offset, v.len() as u32));
+        for (k, _) in &v {
+            w.write_all(&k.z0.to_le_bytes())?;
+        }
+        for (k, _) in &v {
+            w.write_all(&k.z1.to_le_bytes())?;
+        }
+        for (_, occ) in &v {
+            w.write_all(&occ.file_id.to_le_bytes())?;
+        }
+        for (_, occ) in &v {
+            w.write_all(&occ.r#gen.to_le_bytes())?;
+        }
+        for (_, occ) in &v {
+            w.write_all(&occ.node_id.to_le_bytes())?;
+        }
+        offset += column_bytes(v.len(), false);
+    }
+
+    for b in 0..256 {
+        let v = board(b);
+        dir.push((offset, v.len() as u32));
+        for (k, _, _) in &v {
+            w.write_all(&k.z0.to_le_bytes())?;
+        }
+        for (k, _, _) in &v {
+            w.write_all(&k.z1.to_le_bytes())?;
+        }
+        for (_, occ, _) in &v {
+            w.write_all(&occ.file_id.to_le_bytes())?;
+        }
+        for (_, occ, _) in &v {
+            w.write_all(&occ.r#gen.to_le_bytes())?;
+        }
+        for (_, occ, _) in &v {
+            w.write_all(&occ.node_id.to_le_bytes())?;
+        }
+        for (_, _, hands) in &v {
+            w.write_all(&hands.to_le_bytes())?;
+        }
+        offset += column_bytes(v.len(), true);
+    }
+
+    // node table の directory も後で埋める。表は 1 つずつ引いて書き、同時に 1 つしか持たない
+    let nt_offset = offset;
+    let count = node_tables.id_bound();
+    w.write_all(&(count as u32).to_le_bytes())?;
+    w.write_all(&vec![0u8; (NT_DIR_ENTRY_LEN * count as u64) as usize])?;
+    let mut nt_dir: Vec<(u64, u32, u32)> = Vec::with_capacity(count);
+    let mut data = nt_offset + 4 + NT_DIR_ENTRY_LEN * count as u64;
+    for id in 0..count as FileId {
+        let Some(nt) = node_tables.get(id) else {
+            nt_dir.push((0, 0, 0));
+            continue;
+        };
+        nt_dir.push((data, nt.nodes.len() as u32, nt.forks.len() as u32));
+        for n in &nt.nodes {
+            w.write_all(&n.tesuu.to_le_bytes())?;
+            w.write_all(&n.fork_off.to_le_bytes())?;
+            w.write_all(&n.fork_len.to_le_bytes())?;
+            w.write_all(&n.flags.to_le_bytes())?;
+        }
+        for f in &nt.forks {
+            w.write_all(&f.te.to_le_bytes())?;
+            w.write_all(&f.fork_index.to_le_bytes())?;
+        }
+        data += NT_NODE_LEN * nt.nodes.len() as u64 + NT_FORK_LEN * nt.forks.len() as u64;
+    }
+
+    w.seek(SeekFrom::Start(nt_offset + 4))?;
+    for (off, nodes_len, forks_len) in &nt_dir {
+        w.write_all(&off.to_le_bytes())?;
+        w.write_all(&nodes_len.to_le_bytes())?;
+        w.write_all(&forks_len.to_le_bytes())?;
+    }
+
+    w.seek(SeekFrom::Start(HEADER_LEN - 8))?;
+    w.write_all(&nt_offset.to_le_bytes())?;
+    for (off, len) in &dir {
+        w.write_all(&off.to_le_bytes())?;
+        w.write_all(&len.to_le_bytes())?;
+    }
+
+    let file = w.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()
+}
+
+/// mmap して header と directory だけを検め、各 bucket を on-disk セグメントとして開く。
+pub fn open_segment_file(
+    path: &Path,
+    root_hash: &[u8; 32],
+    token: u64,
+) -> Result<RestoredSegments, String> {
+    let file = File::open(path).map_err(|e| format!("open {}: {e}", path.display()))?;
+    let file =
+        Arc::new(SegmentFile::open(&file).map_err(|e| format!("mmap {}: {e}", path.display()))?);
+    let file_len = file.len();
+    if file_len < HEADER_LEN + DIR_LEN {
+        return Err("segment file too short".to_string());
+    }
+
+    let head = file.bytes(0, (HEADER_LEN + DIR_LEN) as usize);
+    if head[0..8] != MAGIC {
+        return Err("segment file: bad magic".to_string());
+    }
+    let ver = u32::from_le_bytes(head[8..12].try_into().map_err(|_| "bad header")?);
+    if ver != VERSION {
+        return Err(format!("segment file: bad version: {ver}"));
+    }
+    if head[12..44] != root_hash[..] {
+        return Err("segment file: root hash mismatch".to_string());
+    }
+    let saved_token = u64::from_le_bytes(head[44..52].try_into().map_err(|_| "bad header")?);
+    if saved_token != token {
+        return Err("segment file: token mismatch (stale segments)".to_string());
+    }
+    let nt_offset = u64::from_le_bytes(head[52..60].try_into().map_err(|_| "bad header")?);
+    if nt_offset.checked_add(4).map_or(true, |end| end > file_len) {
+        return Err("segment file: node tables out of range".to_string());
+    }
+    let count = file.read_u32(nt_offset);
+    if nt_offset + 4 + NT_DIR_ENTRY_LEN * count as u64 > file_len {
+        return Err("segment file: node table directory out of range".to_string());
+    }
+
+    let dir = &head[HEADER_LEN as usize..];
+    let open = |i: usize, with_hands: bool| -> Result<Vec<SegmentArc>, String> {
+        let e = &dir[i * DIR_ENTRY_LEN as usize..(i + 1) * DIR_ENTRY_LEN as usize];
+        let offset = u64::from_le_bytes(e[0..8].try_into().map_err(|_| "bad directory")?);
+        let len = u32::from_le_bytes(e[8..12].try_into().map_err(|_| "bad directory")?) as usize;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if offset + column_bytes(len, with_hands) > file_len {
+            return Err(format!("segment file: bucket {i} out of range"));
+        }
+        Ok(vec![Arc::new(Segment::on_disk(
+            file.clone(),
+            offset,
+            len,
+            with_hands,
+        ))])
+    };
+
+    let mut buckets: [Vec<SegmentArc>; 256] = std::array::from_fn(|_| Vec::new());
+    let mut board_buckets: [Vec<SegmentArc>; 256] = std::array::from_fn(|_| Vec::new());
+    for (b, (exact, board)) in buckets.iter_mut().zip(board_buckets.iter_mut()).enumerate() {
+        *exact = open(b, false)?;
+        *board = open(256 + b, true)?;
+    }
+
+    Ok(RestoredSegments {
+        buckets,
+        board_buckets,
+        node_tables: DiskNodeTables {
+            file,
+            dir: nt_offset + 4,
+            count,
+        },
+    })
+}
+
+/// n エントリ分の列の合計バイト数
+#[inline]
+pub fn column_bytes(n: usize, with_hands: bool) -> u64 {
+    let per = if with_hands { 36 } else { 28 };
+    (n as u64) * per
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use super::{
+    index_store::IndexSnapshot,
+    node_table::{NODE_FLAG_PERPETUAL_CHECK, NODE_FLAG_REPETITION},
+    position_key::PositionKey,
+    types::{CursorLite, FileId, Occurrence, PositionHit},
+};
+
+/// `keys` の局面がこの順に同じ系列上に現れるノードを探し、最後の局面の hit を返す。
+///
+/// 各キーの出現をファイルごとに集め、i 番目の局面のうち「i-1 番目の有効な
+/// ノードを祖先に持つもの」だけを残していく。祖先判定は NodeTable の
+/// cursor (`tesuu` + `fork_pointers`) で行うので棋譜は読み直さない。
+pub fn search_sequence(snap: &IndexSnapshot, keys: &[PositionKey]) -> Vec<PositionHit> {
+    let Some((first, rest)) = keys.split_first() else {
+        return Vec::new();
+    };
+
+    let mut alive: BTreeMap<FileId, Vec<(Occurrence, CursorLite)>> = BTreeMap::new();
+    for occ in snap.search_occurrences_by_key(*first) {
+        if let Some(cursor) = cursor_of(snap, occ) {
+            alive.entry(occ.file_id).or_default().push((occ, cursor));
+        }
+    }
+
+    for key in rest {
+        if alive.is_empty() {
+            break;
+        }
+
+        let mut next: BTreeMap<FileId, Vec<(Occurrence, CursorLite)>> = BTreeMap::new();
+        for occ in snap.search_occurrences_by_key(*key) {
+            let Some(prev) = alive.get(&occ.file_id) else {
+                continue;
+            };
+            let Some(cursor) = cursor_of(snap, occ) else {
+                continue;
+            };
+            if prev.iter().any(|(_, c)| c.is_ancestor_of(&cursor)) {
+                next.entry(occ.file_id).or_default().push((occ, cursor));
+            }
+        }
+        alive = next;
+    }
+
+    alive
+        .into_values()
+        .flatten()
+        .map(|(occ, cursor)| {
+            let flags = snap
+                .node_tables
+                .get(occ.file_id)
+                .map_or(0, |nt| nt.flags(occ.node_id));
+            PositionHit {
+                root_id: 0,
+                occ,
+                cursor,
+                mirrored: false,
+                color_flipped: false,
+                hands: None,
+                hit_count: None,
+                repetition: flags & NODE_FLAG_REPETITION != 0,
+                perpetual_check: flags & NODE_FLAG_PERPETUAL_CHECK != 0,
+            }
+        })
+        .collect()
+}
+
+fn cursor_of(snap: &IndexSnapshot, occ: Occurrence) -> Option<CursorLite> {
+    snap.node_tables.get(occ.file_id)?.cursor_lite(occ.node_id)
+}
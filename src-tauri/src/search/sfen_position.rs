@@ -90,10 +90,114 @@ pub fn position_key_from_sfen(input: &str) -> Result<PositionKey, SfenParseError
     Ok(key_from_partial_position(&pos))
 }
 
+/// `[color][hand_kind]` (歩香桂銀金角飛) の枚数から SFEN の持駒表記を作る。
+///
+/// SFEN の慣例どおり飛角金銀桂香歩の順、先手 (大文字) → 後手 (小文字)。
+pub fn hands_to_sfen(counts: &[[u8; 7]; 2]) -> String {
+    const LETTERS: [char; 7] = ['P', 'L', 'N', 'S', 'G', 'B', 'R'];
+
+    let mut out = String::new();
+    for (ci, row) in counts.iter().enumerate() {
+        for hk in (0..7).rev() {
+            let n = row[hk];
+            if n == 0 {
+                continue;
+            }
+            if n > 1 {
+                out.push_str(&n.to_string());
+            }
+            let ch = LETTERS[hk];
+            out.push(if ci == 0 { ch } else { ch.to_ascii_lowercase() });
+        }
+    }
+
+    if out.is_empty() {
+        out.push('-');
+    }
+    out
+}
+
+/// PartialPosition を SFEN (`<盤面> <手番> <持駒> <手数>`) にする。USI の `position sfen` 用
+pub fn partial_position_to_sfen(pos: &PartialPosition) -> String {
+    let mut board = String::new();
+    for y in 1..=9u8 {
+        if y > 1 {
+            board.push('/');
+        }
+        let mut empty = 0u8;
+        // SFEN は各段 9 筋 → 1 筋の順
+        for x in (1..=9u8).rev() {
+            let Some(piece) = Square::new(x, y).and_then(|sq| pos.piece_at(sq)) else {
+                empty += 1;
+                continue;
+            };
+            if empty > 0 {
+                board.push_str(&empty.to_string());
+                empty = 0;
+            }
+            let (pk, color) = piece.to_parts();
+            let (promoted, letter) = sfen_letter(pk);
+            if promoted {
+                board.push('+');
+            }
+            board.push(match color {
+                Color::Black => letter,
+                Color::White => letter.to_ascii_lowercase(),
+            });
+        }
+        if empty > 0 {
+            board.push_str(&empty.to_string());
+        }
+    }
+
+    const HAND_ORDER: [PieceKind; 7] = [
+        PieceKind::Pawn,
+        PieceKind::Lance,
+        PieceKind::Knight,
+        PieceKind::Silver,
+        PieceKind::Gold,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+    ];
+    let mut counts = [[0u8; 7]; 2];
+    for (ci, color) in [Color::Black, Color::White].into_iter().enumerate() {
+        let hand = pos.hand_of_a_player(color);
+        for (hk, pk) in HAND_ORDER.iter().enumerate() {
+            counts[ci][hk] = hand.count(*pk).unwrap_or(0);
+        }
+    }
+
+    let side = match pos.side_to_move() {
+        Color::Black => 'b',
+        Color::White => 'w',
+    };
+    format!("{board} {side} {} {}", hands_to_sfen(&counts), pos.ply())
+}
+
 // ---------------------------
 // internal helpers
 // ---------------------------
 
+/// 駒種の SFEN 表記 (成駒なら true と元の駒の大文字)
+fn sfen_letter(pk: PieceKind) -> (bool, char) {
+    match pk {
+        PieceKind::Pawn => (false, 'P'),
+        PieceKind::Lance => (false, 'L'),
+        PieceKind::Knight => (false, 'N'),
+        PieceKind::Silver => (false, 'S'),
+        PieceKind::Gold => (false, 'G'),
+        PieceKind::Bishop => (false, 'B'),
+        PieceKind::Rook => (false, 'R'),
+        PieceKind::King => (false, 'K'),
+        PieceKind::ProPawn => (true, 'P'),
+        PieceKind::ProLance => (true, 'L'),
+        PieceKind::ProKnight => (true, 'N'),
+        PieceKind::ProSilver => (true, 'S'),
+        PieceKind::ProBishop => (true, 'B'),
+        PieceKind::ProRook => (true, 'R'),
+    }
+}
+
 fn parse_board_into(pos: &mut PartialPosition, board: &str) -> Result<(), SfenParseError> {
     let ranks: Vec<&str> = board.split('/').collect();
     if ranks.len() != 9 {
@@ -195,7 +299,7 @@ fn add_n(mut h: Hand, pk: PieceKind, n: usize) -> Result<Hand, SfenParseError> {
     Ok(h)
 }
 
-fn piecekind_from_sfen_letter(
+pub(crate) fn piecekind_from_sfen_letter(
     ch: char,
     promoted: bool,
 ) -> Result<(Color, PieceKind), SfenParseError> {
@@ -234,7 +338,7 @@ fn piecekind_from_sfen_letter(
     Ok((color, pk))
 }
 
-fn hand_piecekind_from_letter(ch: char) -> Result<(Color, PieceKind), SfenParseError> {
+pub(crate) fn hand_piecekind_from_letter(ch: char) -> Result<(Color, PieceKind), SfenParseError> {
     let color = if ch.is_ascii_uppercase() {
         Color::Black
     } else if ch.is_ascii_lowercase() {
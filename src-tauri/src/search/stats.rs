@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use super::{
+    index_store::IndexSnapshot,
+    segment::SegmentArc,
+    segment_file::column_bytes,
+    types::{BucketStats, CacheStats, FailedFile, IndexStats, RootId},
+};
+
+/// snapshot から index の統計を作る。
+///
+/// セグメントの件数・バイト数は列の長さから出すので、on-disk セグメントでも列は読まない。
+/// 件数には tombstone 済みファイル (古い gen) の行も含む (compaction で消える)。
+pub fn collect_index_stats(
+    root_id: RootId,
+    root_dir: &Path,
+    snap: &IndexSnapshot,
+    cache: Option<CacheStats>,
+) -> IndexStats {
+    let mut buckets = Vec::with_capacity(256);
+    for b in 0..256 {
+        let (segments, on_disk, entries, bytes) = sum_segments(&snap.buckets[b]);
+        let (board_segments, board_on_disk, board_entries, board_bytes) =
+            sum_segments(&snap.board_buckets[b]);
+        buckets.push(BucketStats {
+            bucket: b as u32,
+            segments,
+            on_disk_segments: on_disk + board_on_disk,
+            entries,
+            bytes,
+            board_segments,
+            board_entries,
+            board_bytes,
+        });
+    }
+
+    let mut total_files = 0u32;
+    let mut tombstoned_files = 0u32;
+    let mut failed_files = Vec::new();
+    for (file_id, e) in snap.file_table.iter_all() {
+        if e.deleted {
+            tombstoned_files += 1;
+            continue;
+        }
+        total_files += 1;
+        if let Some(reason) = e.error {
+            failed_files.push(FailedFile {
+                file_id,
+                path: e.path,
+                reason,
+            });
+        }
+    }
+    failed_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let known = total_files + tombstoned_files;
+    let total_nodes = snap.node_tables.total_nodes();
+
+    IndexStats {
+        root_id,
+        root_dir: root_dir.to_string_lossy().to_string(),
+        state: snap.state.into(),
+        total_files,
+        tombstoned_files,
+        tombstoned_ratio: if known == 0 {
+            0.0
+        } else {
+            tombstoned_files as f64 / known as f64
+        },
+        total_positions: buckets.iter().map(|b| b.entries).sum(),
+        total_board_positions: buckets.iter().map(|b| b.board_entries).sum(),
+        total_nodes,
+        total_segments: buckets.iter().map(|b| b.segments + b.board_segments).sum(),
+        total_bytes: buckets.iter().map(|b| b.bytes + b.board_bytes).sum(),
+        buckets,
+        failed_files,
+        cache,
+    }
+}
+
+/// (セグメント数, on-disk の数, 件数, バイト数)
+fn sum_segments(segs: &[SegmentArc]) -> (u32, u32, u64, u64) {
+    let mut on_disk = 0;
+    let mut entries = 0;
+    let mut bytes = 0;
+    for seg in segs {
+        if seg.is_on_disk() {
+            on_disk += 1;
+        }
+        entries += seg.len() as u64;
+        bytes += column_bytes(seg.len(), seg.has_hands());
+    }
+    (segs.len() as u32, on_disk, entries, bytes)
+}
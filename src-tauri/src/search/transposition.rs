@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use shogi_core::PartialPosition;
+use shogi_kifu_converter_obsshogi::jkf::JsonKifuFormat;
+
+use super::{
+    index_builder::{build_index_for_jkf_with_visitor, BuildError, BuildPolicy},
+    position_key::PositionKey,
+    sfen_position::partial_position_to_sfen,
+    types::{CursorLite, NodeId, TranspositionGroup},
+};
+
+/// 1 つの棋譜の中で、別々の分岐から同じ局面に到達しているノードを列挙する。
+///
+/// 同じ系列上で局面が繰り返すだけ (千日手など) の組は手順違いではないので除く。
+/// 局面の出現順 (最初のノードの node_id 順) に返す。
+pub fn find_transpositions(jkf: &JsonKifuFormat) -> Result<Vec<TranspositionGroup>, BuildError> {
+    // 組の局面を SFEN で返すために各ノードの局面を控えておく
+    let mut positions: HashMap<NodeId, PartialPosition> = HashMap::new();
+    let built =
+        build_index_for_jkf_with_visitor(0, 0, jkf, BuildPolicy::Loose, &mut |node_id, pos| {
+            positions.insert(node_id, pos.clone());
+        })?;
+
+    let mut by_key: HashMap<PositionKey, Vec<NodeId>> = HashMap::new();
+    for (key, occ) in &built.entries {
+        by_key.entry(*key).or_default().push(occ.node_id);
+    }
+
+    let mut groups: Vec<(NodeId, TranspositionGroup)> = Vec::new();
+    for node_ids in by_key.into_values() {
+        if node_ids.len() < 2 {
+            continue;
+        }
+
+        let mut cursors: Vec<CursorLite> = node_ids
+            .iter()
+            .filter_map(|&id| built.node_table.cursor_lite(id))
+            .collect();
+        let on_other_branch = cursors.iter().enumerate().any(|(i, a)| {
+            cursors[i + 1..]
+                .iter()
+                .any(|b| !a.is_ancestor_of(b) && !b.is_ancestor_of(a))
+        });
+        if !on_other_branch {
+            continue;
+        }
+
+        cursors.sort_by_key(|c| (c.tesuu, c.fork_pointers.len()));
+        let first_id = node_ids.iter().copied().min().unwrap_or(0);
+        let Some(pos) = positions.get(&first_id) else {
+            continue;
+        };
+        let sfen = partial_position_to_sfen(pos);
+        groups.push((first_id, TranspositionGroup { sfen, cursors }));
+    }
+
+    groups.sort_by_key(|(first_id, _)| *first_id);
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
@@ -1,4 +1,4 @@
-use shogi_kifu_converter_obsshogi::jkf::{MoveMoveFormat, MoveSpecial};
+use shogi_kifu_converter_obsshogi::jkf::{MoveFormat, MoveMoveFormat, MoveSpecial};
 
 use super::types::{CursorLite, ForkPointer};
 
@@ -24,6 +24,116 @@ impl CursorLite {
             fork_pointers: fps,
         }
     }
+
+    /// self が other と同じ系列上で、other より前の手数にあるか。
+    ///
+    /// other の fork_pointers を self の手数で切ったものが self と一致すれば、
+    /// other へ至る道筋は self を通る。
+    pub fn is_ancestor_of(&self, other: &CursorLite) -> bool {
+        if self.tesuu >= other.tesuu {
+            return false;
+        }
+        let theirs: Vec<&ForkPointer> = other
+            .fork_pointers
+            .iter()
+            .filter(|p| p.te <= self.tesuu)
+            .collect();
+        theirs.len() == self.fork_pointers.len()
+            && theirs
+                .iter()
+                .zip(&self.fork_pointers)
+                .all(|(q, p)| q.te == p.te && q.fork_index == p.fork_index)
+    }
+}
+
+/// cursor が指すノードを JKF 上で探し、そのノードを含む系列と系列内の位置を返す。
+///
+/// `moves` は `jkf.moves` (先頭が開始局面)。本譜は index = 手数、分岐 `te` の系列は
+/// 先頭が手数 `te` になる (index_builder の採番と同じ)。
+pub fn locate_in_jkf<'a>(
+    moves: &'a [MoveFormat],
+    cursor: &CursorLite,
+) -> Option<(&'a [MoveFormat], usize)> {
+    let mut seq = moves;
+    let mut base = 0u32;
+
+    for fp in &cursor.fork_pointers {
+        let node = seq.get(fp.te.checked_sub(base)? as usize)?;
+        seq = node.forks.as_ref()?.get(fp.fork_index as usize)?;
+        base = fp.te;
+    }
+
+    let idx = cursor.tesuu.checked_sub(base)? as usize;
+    (idx < seq.len()).then_some((seq, idx))
+}
+
+/// `locate_in_jkf` の可変版。cursor が指すノードそのものを返す
+pub fn locate_in_jkf_mut<'a>(
+    moves: &'a mut [MoveFormat],
+    cursor: &CursorLite,
+) -> Option<&'a mut MoveFormat> {
+    let mut seq = moves;
+    let mut base = 0u32;
+
+    for fp in &cursor.fork_pointers {
+        let cur = seq;
+        let node = cur.get_mut(fp.te.checked_sub(base)? as usize)?;
+        seq = node.forks.as_mut()?.get_mut(fp.fork_index as usize)?;
+        base = fp.te;
+    }
+
+    seq.get_mut(cursor.tesuu.checked_sub(base)? as usize)
+}
+
+/// cursor の局面から指された次の一手 (本譜 + その手の分岐) を列挙する。
+///
+/// 次のノードが special (投了など) のものは含めない。
+pub fn next_moves_in_jkf(moves: &[MoveFormat], cursor: &CursorLite) -> Vec<MoveMoveFormat> {
+    let Some((seq, idx)) = locate_in_jkf(moves, cursor) else {
+        return Vec::new();
+    };
+    let Some(next) = seq.get(idx + 1) else {
+        return Vec::new();
+    };
+
+    let mut out: Vec<MoveMoveFormat> = next.move_.into_iter().collect();
+    if let Some(forks) = &next.forks {
+        out.extend(forks.iter().filter_map(|line| line.first()?.move_));
+    }
+    out
+}
+
+/// 本譜から `fork_pointers` の分岐に入りながら辿った系列の指し手を、初手から順に返す。
+///
+/// `fork_pointers` は `CursorLite` と同じ (手数 `te` でその手の分岐 `fork_index` に入る)。
+/// special (投了など) か系列の終わりで止まる。
+pub fn line_moves_in_jkf(
+    moves: &[MoveFormat],
+    fork_pointers: &[ForkPointer],
+) -> Vec<MoveMoveFormat> {
+    let mut out = Vec::new();
+    let mut seq = moves;
+    let mut base = 0u32;
+    let mut fps = fork_pointers.iter().peekable();
+
+    for te in 1u32.. {
+        if let Some(fp) = fps.next_if(|fp| fp.te == te) {
+            let line = seq
+                .get((te - base) as usize)
+                .and_then(|node| node.forks.as_ref())
+                .and_then(|forks| forks.get(fp.fork_index as usize));
+            let Some(line) = line else {
+                break;
+            };
+            seq = line;
+            base = te;
+        }
+        let Some(mv) = seq.get((te - base) as usize).and_then(|node| node.move_) else {
+            break;
+        };
+        out.push(mv);
+    }
+    out
 }
 
 /// 走査で得られる「ノードの意味」
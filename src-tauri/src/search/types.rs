@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use shogi_kifu_converter_obsshogi::jkf::JsonKifuFormat;
 
 pub const EVT_INDEX_STATE: &str = "position-index-state";
 pub const EVT_INDEX_PROGRESS: &str = "position-index-progress";
@@ -12,6 +13,8 @@ pub type RequestId = u64;
 pub type FileId = u32;
 pub type Gen = u32;
 pub type NodeId = u32;
+/// 開いている棋譜フォルダ (root) の識別子。FileId は root ごとに振られる
+pub type RootId = u32;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -21,15 +24,61 @@ pub struct FileEntry {
     pub deleted: bool,
     #[serde(rename = "gen")]
     pub r#gen: Gen,
+    /// 読み込み・index 作成に失敗した理由 (KifuReadError / BuildError)。成功なら None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FilePathEntry {
+    #[serde(default)]
+    pub root_id: RootId,
     pub file_id: FileId,
     pub abs_path: String,
 }
 
+/// 棋譜 1 局の結果 (本譜の終局 special から判定)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameResult {
+    BlackWin,
+    WhiteWin,
+    Draw,
+    #[default]
+    Unknown,
+}
+
+/// 棋譜ヘッダから拾った対局情報
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameMeta {
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub event: Option<String>,
+    /// 開始日時 (ヘッダの表記のまま)
+    pub start_date: Option<String>,
+    pub result: GameResult,
+}
+
+/// 検索結果を対局情報で絞り込む条件。指定した項目はすべて満たす必要がある
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameFilter {
+    /// 先手・後手どちらかの名前に含まれる文字列
+    #[serde(default)]
+    pub player: Option<String>,
+    /// 棋戦名に含まれる文字列
+    #[serde(default)]
+    pub event: Option<String>,
+    /// 開始日の下限 (両端含む)。"2024-01-01" / "2024/1/1" など年月日が読めればよい
+    #[serde(default)]
+    pub date_from: Option<String>,
+    #[serde(default)]
+    pub date_to: Option<String>,
+    #[serde(default)]
+    pub result: Option<GameResult>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IndexState {
     Empty,
@@ -60,6 +109,82 @@ pub struct SearchPositionInput {
     pub sfen: String,
     pub consistency: Consistency,
     pub chunk_size: u32,
+    /// 左右反転した局面も同時に検索する
+    #[serde(default)]
+    pub mirror: bool,
+    /// 先後を入れ替えた (180° 回転 + 駒・持ち駒・手番の反転) 局面も同時に検索する
+    #[serde(default)]
+    pub color_flip: bool,
+    /// 持ち駒を無視し、盤面 + 手番だけで一致を取る
+    #[serde(default)]
+    pub ignore_hands: bool,
+    /// 対局情報での絞り込み。hit を流す前に適用する
+    #[serde(default)]
+    pub filter: Option<GameFilter>,
+    /// ファイルごとに 1 件 (最も浅い手数の hit) にまとめ、hit_count を付ける
+    #[serde(default)]
+    pub group_by_file: bool,
+    /// ファイル単位の並び順。省略時は `(file_id, node_id)` 順
+    #[serde(default)]
+    pub sort: Option<HitSort>,
+}
+
+/// 検索結果のファイル単位の並び順。同順位は file_id 順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HitSort {
+    /// パスの昇順
+    Path,
+    /// 更新日時の新しい順
+    Mtime,
+    /// 最も浅い hit の手数の昇順
+    EarliestPly,
+    /// ファイル内の hit 数の多い順
+    HitCount,
+}
+
+/// 複数局面を順に通る棋譜 (同じ系列上で sfens[0] → sfens[1] → ...) の検索
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSequenceInput {
+    /// 2 つ以上。hit は最後の局面の出現箇所
+    pub sfens: Vec<String>,
+    pub consistency: Consistency,
+    pub chunk_size: u32,
+}
+
+/// パターン検索のマス目条件。`piece` は SFEN の駒表記 ("S", "+r" など)、
+/// 省略時は空きマスを要求する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatternSquare {
+    pub file: u8,
+    pub rank: u8,
+    #[serde(default)]
+    pub piece: Option<String>,
+}
+
+/// パターン検索の持ち駒条件。`piece` は SFEN の持ち駒表記 (大文字=先手, 小文字=後手)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatternHand {
+    pub piece: String,
+    #[serde(default)]
+    pub min: Option<u8>,
+    #[serde(default)]
+    pub max: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchPatternInput {
+    pub squares: Vec<PatternSquare>,
+    #[serde(default)]
+    pub hands: Vec<PatternHand>,
+    /// "b" / "w"。省略時は手番を問わない
+    #[serde(default)]
+    pub side_to_move: Option<String>,
+    pub consistency: Consistency,
+    pub chunk_size: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +193,59 @@ pub struct SearchPositionOutput {
     pub request_id: RequestId,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveStatsInput {
+    pub sfen: String,
+    /// 持ち駒を無視し、盤面 + 手番だけで一致を取る
+    #[serde(default)]
+    pub ignore_hands: bool,
+}
+
+/// 対局結果の集計 (先手から見た勝敗ではなく、先手勝ち/後手勝ちで数える)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultCounts {
+    pub black_wins: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    /// 中断・結果なしなど判定できなかったもの
+    pub unknown: u32,
+}
+
+impl ResultCounts {
+    pub fn add(&mut self, r: GameResult) {
+        match r {
+            GameResult::BlackWin => self.black_wins += 1,
+            GameResult::WhiteWin => self.white_wins += 1,
+            GameResult::Draw => self.draws += 1,
+            GameResult::Unknown => self.unknown += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveStat {
+    /// USI 表記の指し手
+    pub usi: String,
+    pub count: u32,
+    pub results: ResultCounts,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveStatsOutput {
+    /// 局面の出現数 (分岐を含む)
+    pub total_hits: u32,
+    /// 局面が出現した棋譜数
+    pub total_games: u32,
+    /// 棋譜単位の結果
+    pub results: ResultCounts,
+    /// 出現数の多い順
+    pub moves: Vec<MoveStat>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CancelSearchInput {
@@ -100,8 +278,149 @@ pub struct CursorLite {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PositionHit {
+    /// hit した棋譜の root。`occ.file_id` はこの root の中での番号
+    #[serde(default)]
+    pub root_id: RootId,
     pub occ: Occurrence,
     pub cursor: CursorLite,
+    /// 左右反転した局面として一致した (UI は盤面を反転して表示する)
+    #[serde(default)]
+    pub mirrored: bool,
+    /// 先後を入れ替えた局面として一致した
+    #[serde(default)]
+    pub color_flipped: bool,
+    /// ignore_hands 検索時、hit した局面の実際の持ち駒 (SFEN 表記)
+    #[serde(default)]
+    pub hands: Option<String>,
+    /// group_by_file 検索時、このファイル内の hit 数 (この hit は最も浅いもの)
+    #[serde(default)]
+    pub hit_count: Option<u32>,
+    /// この局面で千日手が成立している (同一局面の 4 回目以降)
+    #[serde(default)]
+    pub repetition: bool,
+    /// 千日手のうち連続王手によるもの
+    #[serde(default)]
+    pub perpetual_check: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpeningTreeInput {
+    /// 棋譜を集めるフォルダ (配下を再帰的に読む)
+    pub dir: String,
+    /// 各棋譜の本譜を何手目までまとめるか
+    pub max_plies: u32,
+    /// これ未満の出現数の手は木に残さない (0 / 省略時は 1)
+    #[serde(default)]
+    pub min_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpeningTreeOutput {
+    /// 正規化済み。`write_kifu_to_file` でそのまま保存できる
+    pub jkf: JsonKifuFormat,
+    /// 木に入れた棋譜数
+    pub games: u32,
+    /// 読めなかった / 開始局面が違うので入れなかった棋譜数
+    pub skipped: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranspositionsInput {
+    pub jkf: JsonKifuFormat,
+}
+
+/// 同じ局面に別々の手順で到達しているノードの組
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranspositionGroup {
+    /// 合流した局面の SFEN (手数は最初に現れたノードのもの)
+    pub sfen: String,
+    /// 手数の浅い順 (同手数なら分岐の少ない順)。先頭が合流先の代表
+    pub cursors: Vec<CursorLite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranspositionsOutput {
+    pub groups: Vec<TranspositionGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateKifuInput {
+    /// 棋譜ファイル、またはフォルダ (配下を再帰的に見る)
+    pub path: String,
+}
+
+/// 棋譜の問題 1 件。読めないファイルは cursor / kind なし
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KifuIssue {
+    pub path: String,
+    pub cursor: Option<CursorLite>,
+    pub kind: Option<BuildWarnKind>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateKifuOutput {
+    /// 調べたファイル数
+    pub checked: u32,
+    pub issues: Vec<KifuIssue>,
+}
+
+/// 検索結果の書き出し方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportMode {
+    /// 棋譜ファイルをフォルダへコピーする
+    Copy,
+    /// 棋譜ファイルへのシンボリックリンクをフォルダに作る
+    Symlink,
+    /// 対象ファイルと局面の一覧を 1 つの JSON に書く
+    Manifest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResultsInput {
+    /// 直近に完了した検索の結果を書き出す
+    #[serde(default)]
+    pub request_id: Option<RequestId>,
+    /// 書き出す hit を直接渡す。request_id より優先
+    #[serde(default)]
+    pub hits: Option<Vec<PositionHit>>,
+    /// Copy / Symlink は書き出し先フォルダ、Manifest は書き出すファイル
+    pub dest: String,
+    pub mode: ExportMode,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResultsOutput {
+    /// 作成したファイル (Manifest なら 1 件)
+    pub written: Vec<String>,
+    /// 削除済み・更新済みなどで書き出さなかった元ファイル
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub version: u32,
+    pub created_ms: u64,
+    pub entries: Vec<ExportManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifestEntry {
+    pub abs_path: String,
+    /// このファイル内で hit した局面
+    pub cursors: Vec<CursorLite>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -141,9 +460,96 @@ pub struct OpenProjectInput {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenProjectOutput {
+    pub root_id: RootId,
     pub total_files: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveProjectRootInput {
+    pub root_id: RootId,
+}
+
+/// 開いている root の一覧用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectRootInfo {
+    pub root_id: RootId,
+    pub root_dir: String,
+    pub state: IndexState,
+    /// index 済みファイル数 (削除済みを除く)
+    pub total_files: u32,
+}
+
+/// bucket 1 つ分のセグメントの統計。件数は tombstone 済みファイルの行も含む
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketStats {
+    pub bucket: u32,
+    pub segments: u32,
+    /// セグメントファイル上 (cache から復元) のセグメント数
+    pub on_disk_segments: u32,
+    pub entries: u64,
+    /// 列の合計バイト数
+    pub bytes: u64,
+    /// 持ち駒を無視した盤面 index の分
+    pub board_segments: u32,
+    pub board_entries: u64,
+    pub board_bytes: u64,
+}
+
+/// 読み込み・index 作成に失敗したファイル
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedFile {
+    pub file_id: FileId,
+    pub path: String,
+    pub reason: String,
+}
+
+/// index cache のディスク上のサイズ
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub total_bytes: u64,
+    pub index_bytes: u64,
+    pub segment_bytes: u64,
+    pub delta_bytes: u64,
+    /// 最後に本体を書き直した (checkpoint) 時刻 (unix ms)
+    pub last_checkpoint_ms: Option<u64>,
+}
+
+/// root 1 つ分の index の統計
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub root_id: RootId,
+    pub root_dir: String,
+    pub state: IndexState,
+    /// 削除済みを除くファイル数
+    pub total_files: u32,
+    pub tombstoned_files: u32,
+    /// tombstoned_files / (total_files + tombstoned_files)
+    pub tombstoned_ratio: f64,
+    pub total_positions: u64,
+    pub total_board_positions: u64,
+    pub total_nodes: u64,
+    pub total_segments: u32,
+    pub total_bytes: u64,
+    /// bucket 番号順 (256 個)
+    pub buckets: Vec<BucketStats>,
+    pub failed_files: Vec<FailedFile>,
+    /// cache が未保存なら None
+    pub cache: Option<CacheStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatsOutput {
+    /// 開いている root ごと (追加順)
+    pub roots: Vec<IndexStats>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IndexProgressPayload {
@@ -157,4 +563,22 @@ pub struct IndexProgressPayload {
 pub struct IndexWarnPayload {
     pub path: String,
     pub message: String,
+    /// 棋譜の中の特定の手についての警告なら、その種類と位置
+    #[serde(default)]
+    pub kind: Option<BuildWarnKind>,
+    #[serde(default)]
+    pub cursor: Option<CursorLite>,
+}
+
+/// index 作成中に棋譜の中で見つかった事柄の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildWarnKind {
+    /// 指し手を適用できず、その系列の残りを捨てた
+    ApplyFailed,
+    /// 同一局面が同じ系列上で 4 回現れた (千日手)
+    Repetition,
+    /// 連続王手の千日手
+    PerpetualCheck,
+    /// ルール上指せない手 (BuildPolicy::Legal のときだけ)
+    IllegalMove,
 }
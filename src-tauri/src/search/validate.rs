@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use super::{
+    fs_scan::{scan_kifu_files, FileRecord, KifuKind, ScanOptions},
+    index_builder::{build_index_for_jkf, BuildPolicy},
+    kifu_reader::read_to_jkf,
+    types::{BuildWarnKind, KifuIssue, ValidateKifuInput, ValidateKifuOutput},
+};
+
+/// 棋譜 (またはフォルダ配下の棋譜) を全分岐込みでルールに照らして調べる。
+///
+/// 指せない手があればその系列の残りは見ない。千日手は反則ではないので含めない。
+pub fn validate_kifu(
+    input: &ValidateKifuInput,
+    opts: &ScanOptions,
+) -> Result<ValidateKifuOutput, String> {
+    let path = Path::new(&input.path);
+
+    let records = if path.is_dir() {
+        scan_kifu_files(path, opts).map_err(|e| e.to_string())?
+    } else {
+        let kind = KifuKind::from_path(path)
+            .ok_or_else(|| format!("not a kifu file: {}", path.display()))?;
+        let meta = std::fs::metadata(path).map_err(|e| e.to_string())?;
+        vec![FileRecord {
+            path: path.to_path_buf(),
+            kind,
+            size: meta.len(),
+            mtime_ms: 0,
+            hash: None,
+            hashed_at_ms: 0,
+        }]
+    };
+
+    let mut out = ValidateKifuOutput::default();
+    for rec in &records {
+        out.checked += 1;
+        out.issues.extend(validate_file(rec));
+    }
+
+    Ok(out)
+}
+
+fn validate_file(rec: &FileRecord) -> Vec<KifuIssue> {
+    let path = rec.path.to_string_lossy().to_string();
+    let plain = |message: String| KifuIssue {
+        path: path.clone(),
+        cursor: None,
+        kind: None,
+        message,
+    };
+
+    let jkf = match read_to_jkf(rec) {
+        Ok(jkf) => jkf,
+        Err(e) => return vec![plain(e.to_string())],
+    };
+    let built = match build_index_for_jkf(0, 0, &jkf, BuildPolicy::Legal) {
+        Ok(b) => b,
+        Err(e) => return vec![plain(e.to_string())],
+    };
+
+    built
+        .warns
+        .into_iter()
+        .filter(|w| {
+            matches!(
+                w.kind,
+                BuildWarnKind::ApplyFailed | BuildWarnKind::IllegalMove
+            )
+        })
+        .map(|w| KifuIssue {
+            path: path.clone(),
+            cursor: Some(w.cursor),
+            kind: Some(w.kind),
+            message: w.message,
+        })
+        .collect()
+}